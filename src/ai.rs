@@ -0,0 +1,689 @@
+use crate::boid::Boid;
+use crate::combat::{Health, RangedAttack};
+use crate::faction::Faction;
+use crate::formations::{
+    Formation, FormationOf, FormationOrder, Formations, MemberOf, Members, Pace,
+};
+use crate::horse::Horse;
+use crate::kinematics::{NNTree, TrackedByTree};
+use crate::morale::{Morale, MoraleState, Routers};
+use crate::vision::FogOfWar;
+use bevy::prelude::*;
+use bevy_spatial::SpatialAccess;
+
+/// Computer opponents: every [`AiCommander`] leads the top-level formations
+/// of its faction (see [`ai_commander_system`]).
+pub struct AiPlugin;
+
+impl Plugin for AiPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(FixedUpdate, ai_commander_system);
+    }
+}
+
+/// How well an [`AiCommander`] plays.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Difficulty {
+    /// Seconds between decisions.
+    pub think_interval: f32,
+    /// Own strength (boids) over the enemy's at which it attacks rather
+    /// than stands on the defensive.
+    pub attack_ratio: f32,
+    /// Share of its formations held back to reinforce the line.
+    pub reserve_fraction: f32,
+    /// Turns to meet enemies overlapping or getting round its line.
+    pub reacts_to_flanks: bool,
+}
+
+impl Difficulty {
+    pub const EASY: Self = Self {
+        think_interval: 4.0,
+        attack_ratio: 1.5,
+        reserve_fraction: 0.0,
+        reacts_to_flanks: false,
+    };
+    pub const NORMAL: Self = Self {
+        think_interval: 2.0,
+        attack_ratio: 1.2,
+        reserve_fraction: 0.2,
+        reacts_to_flanks: true,
+    };
+    pub const HARD: Self = Self {
+        think_interval: 1.0,
+        attack_ratio: 1.0,
+        reserve_fraction: 0.3,
+        reacts_to_flanks: true,
+    };
+}
+
+impl Default for Difficulty {
+    fn default() -> Self {
+        Self::NORMAL
+    }
+}
+
+/// Whether a commander is going for the enemy or holding its ground.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Stance {
+    #[default]
+    Defend,
+    Attack,
+}
+
+/// A computer player commanding `faction`. Like the human player it acts
+/// only by queueing orders on its formations ([`Formation::tasks`]), and
+/// only on top-level ones: sub-formations follow their parents.
+#[derive(Component, Debug)]
+pub struct AiCommander {
+    pub faction: Faction,
+    pub difficulty: Difficulty,
+    pub stance: Stance,
+    /// Where the line is headed: the enemy's main body when attacking, the
+    /// ground taken up when defending.
+    pub objective: Option<Vec3>,
+    /// Seconds until the next decision.
+    think_in: f32,
+}
+
+impl AiCommander {
+    pub fn new(faction: Faction, difficulty: Difficulty) -> Self {
+        Self {
+            faction,
+            difficulty,
+            stance: Stance::default(),
+            objective: None,
+            think_in: 0.0,
+        }
+    }
+}
+
+/// Gap between neighbouring formations in the battle line.
+pub const LINE_GAP: f32 = 4.0;
+
+/// An attacking line halts this short of the enemy's centre, leaving the
+/// last steps to the fighting.
+pub const ENGAGE_DISTANCE: f32 = 5.0;
+
+/// Reserves stand this far behind the line.
+pub const RESERVE_DEPTH: f32 = 20.0;
+
+/// A formation already ordered to (or standing at) within this of its post
+/// is not ordered again.
+pub const REORDER_TOLERANCE: f32 = 3.0;
+
+/// Enemies within this of a formation threaten it ...
+pub const THREAT_RADIUS: f32 = 25.0;
+
+/// ... when they outnumber it by this ratio (or it is shaken).
+pub const THREAT_RATIO: f32 = 1.0;
+
+/// Enemies this far beyond either end of the line, or behind it, are
+/// getting round it ...
+pub const FLANK_MARGIN: f32 = 5.0;
+
+/// ... counted within this distance of the line ...
+pub const FLANK_RADIUS: f32 = 60.0;
+
+/// ... once there are at least this many of them on one side.
+pub const FLANK_MIN_BOIDS: usize = 10;
+
+/// Facings closer than this (dot product of the directions) are the same.
+const SAME_FACING: f32 = 0.99;
+
+/// What a commander knows about one of its top-level formations.
+struct Unit {
+    entity: Entity,
+    pos: Vec3,
+    /// Boids at any depth.
+    strength: usize,
+    width: f32,
+    depth: f32,
+    /// Range of its shooters, if it has any.
+    range: Option<f32>,
+    mounted: bool,
+    shaken: bool,
+}
+
+/// Boids below `formation` at any depth, and the first of them.
+fn muster(
+    q_hierarchy: &Query<(Option<&Members>, Option<&Formations>), With<Formation>>,
+    formation: Entity,
+) -> (usize, Option<Entity>) {
+    let Ok((members, subs)) = q_hierarchy.get(formation) else {
+        return (0, None);
+    };
+    let mut count = members.map_or(0, |m| m.len());
+    let mut first = members.and_then(|m| m.iter().next());
+    for sub in subs.into_iter().flat_map(|s| s.iter()) {
+        let (n, sub_first) = muster(q_hierarchy, sub);
+        count += n;
+        first = first.or(sub_first);
+    }
+    (count, first)
+}
+
+/// Order `formation` to march to `pos` and face `facing` at `pace`, unless
+/// it is already going there (or standing there so faced), or busy with a
+/// maneuver of its own that an order would cut short.
+fn order_move(formation: &mut Formation, at: Vec3, pos: Vec3, facing: Vec3, pace: Pace) {
+    let on_post = |p: Vec3, f: Vec3| {
+        p.distance(pos) < REORDER_TOLERANCE && f.normalize_or_zero().dot(facing) > SAME_FACING
+    };
+    match formation.tasks.front() {
+        Some(FormationOrder::Move {
+            pos: p,
+            facing_dir: f,
+            ..
+        }) if on_post(*p, *f) => return,
+        None | Some(FormationOrder::Hold { .. }) if on_post(at, formation.dir) => return,
+        None
+        | Some(
+            FormationOrder::Hold { .. }
+            | FormationOrder::Move { .. }
+            | FormationOrder::FireAt { .. }
+            | FormationOrder::Charge { .. },
+        ) => {}
+        Some(_) => return,
+    }
+    formation.tasks.clear();
+    formation.tasks.push_back(FormationOrder::Move {
+        pos,
+        facing_dir: facing,
+        pace,
+    });
+}
+
+/// Every [`AiCommander`], once per [`Difficulty::think_interval`]:
+/// - sizes up both sides (boids of its faction in its top-level
+///   formations against every enemy boid) and picks its [`Stance`]:
+///   attack the enemy's main body when strong enough
+///   ([`Difficulty::attack_ratio`]), otherwise hold the ground it stands
+///   on;
+/// - draws up a battle line across the direction of the enemy, the
+///   formations side by side in their present order from left to right,
+///   with [`FormationOrder::Move`]s at the double when attacking, at the
+///   quick march when defending; shooters with an enemy in range fire at
+///   it instead;
+/// - keeps a reserve ([`Difficulty::reserve_fraction`], cavalry first)
+///   behind the line, and sends it to back up formations that are
+///   outnumbered nearby or shaken, or to meet enemies getting round an end
+///   of the line; without a reserve the end formation turns to face them
+///   ([`Difficulty::reacts_to_flanks`]);
+/// - rallies its routed formations.
+///
+/// It knows only of the enemies its faction sees ([`FogOfWar`]); with none
+/// in sight it keeps to its last orders. Boids the LOD manager unloaded
+/// stand where they were left, not where their formation is, so they are
+/// not counted (the manager reloads any formation in sight anyway).
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+pub fn ai_commander_system(
+    time: Res<Time>,
+    tree: Res<NNTree>,
+    fog: Res<FogOfWar>,
+    mut q_commanders: Query<&mut AiCommander>,
+    mut q_formations: Query<
+        (
+            Entity,
+            &mut Formation,
+            &Transform,
+            &Faction,
+            &Morale,
+            Option<&Routers>,
+        ),
+        Without<FormationOf>,
+    >,
+    q_hierarchy: Query<(Option<&Members>, Option<&Formations>), With<Formation>>,
+    q_boids: Query<(&Transform, &Faction), (With<Boid>, With<Health>, With<TrackedByTree>)>,
+    q_arms: Query<(Option<&RangedAttack>, Option<&Horse>)>,
+    q_member_of: Query<&MemberOf>,
+) {
+    let dt = time.delta_secs();
+    for mut commander in &mut q_commanders {
+        commander.think_in -= dt;
+        if commander.think_in > 0.0 {
+            continue;
+        }
+        commander.think_in = commander.difficulty.think_interval;
+        let faction = commander.faction;
+
+        let mut units: Vec<Unit> = Vec::new();
+        for (entity, mut formation, transform, side, morale, routers) in &mut q_formations {
+            if *side != faction {
+                continue;
+            }
+            let (strength, first) = muster(&q_hierarchy, entity);
+            if strength == 0 {
+                if routers.is_some()
+                    && !matches!(formation.tasks.front(), Some(FormationOrder::Rally))
+                {
+                    formation.tasks.clear();
+                    formation.tasks.push_back(FormationOrder::Rally);
+                    info!("[ai] {faction:?} rallies {entity:?}");
+                }
+                continue;
+            }
+            let (ranged, horse) = first
+                .and_then(|boid| q_arms.get(boid).ok())
+                .unwrap_or_default();
+            units.push(Unit {
+                entity,
+                pos: transform.translation.with_y(0.0),
+                strength,
+                width: formation.footprint.width,
+                depth: formation.footprint.depth,
+                range: ranged.map(|r| r.range),
+                mounted: horse.is_some_and(|h| h.mounted),
+                shaken: morale.state() != MoraleState::Steady,
+            });
+        }
+        let seen_enemy = |entity: Entity| {
+            q_boids.get(entity).is_ok_and(|(transform, side)| {
+                *side != faction && fog.sees(faction, transform.translation)
+            })
+        };
+        let enemies: Vec<Vec3> = q_boids
+            .iter()
+            .filter(|(transform, side)| {
+                **side != faction && fog.sees(faction, transform.translation)
+            })
+            .map(|(transform, _)| transform.translation.with_y(0.0))
+            .collect();
+        if units.is_empty() || enemies.is_empty() {
+            continue;
+        }
+
+        let own_strength: usize = units.iter().map(|u| u.strength).sum();
+        let own_center = units
+            .iter()
+            .map(|u| u.pos * u.strength as f32)
+            .sum::<Vec3>()
+            / own_strength as f32;
+        let enemy_center = enemies.iter().sum::<Vec3>() / enemies.len() as f32;
+        let forward = (enemy_center - own_center).normalize_or(Vec3::Z);
+        let right = Vec3::new(forward.z, 0.0, -forward.x);
+
+        let stance =
+            if own_strength as f32 >= commander.difficulty.attack_ratio * enemies.len() as f32 {
+                Stance::Attack
+            } else {
+                Stance::Defend
+            };
+        if stance != commander.stance || commander.objective.is_none() {
+            info!(
+                "[ai] {faction:?} takes the {stance:?} ({own_strength} against {})",
+                enemies.len()
+            );
+        }
+        let objective = match (stance, commander.objective) {
+            (Stance::Attack, _) => enemy_center - forward * ENGAGE_DISTANCE,
+            (Stance::Defend, Some(ground)) if commander.stance == Stance::Defend => ground,
+            (Stance::Defend, _) => own_center,
+        };
+        commander.stance = stance;
+        commander.objective = Some(objective);
+
+        // Reserves: cavalry first, then whoever stands furthest back.
+        let reserves = ((units.len() as f32 * commander.difficulty.reserve_fraction) as usize)
+            .min(units.len() - 1);
+        units.sort_by(|a, b| {
+            b.mounted
+                .cmp(&a.mounted)
+                .then(a.pos.dot(forward).total_cmp(&b.pos.dot(forward)))
+        });
+        let mut reserve: Vec<Unit> = units.drain(..reserves).collect();
+        let mut line = units;
+        line.sort_by(|a, b| a.pos.dot(right).total_cmp(&b.pos.dot(right)));
+
+        let frontage =
+            line.iter().map(|u| u.width).sum::<f32>() + LINE_GAP * (line.len() - 1) as f32;
+        let mut posts: Vec<(Vec3, Vec3)> = Vec::with_capacity(line.len());
+        let mut offset = -frontage / 2.0;
+        for unit in &line {
+            posts.push((objective + right * (offset + unit.width / 2.0), forward));
+            offset += unit.width + LINE_GAP;
+        }
+
+        let mut reinforce: Vec<(Vec3, Vec3)> = Vec::new();
+        if commander.difficulty.reacts_to_flanks {
+            // Enemies beyond either end of the line, or behind it.
+            let mut flanks = [(0usize, Vec3::ZERO); 2];
+            for &enemy in &enemies {
+                let relative = enemy - objective;
+                let (across, ahead) = (relative.dot(right), relative.dot(forward));
+                if ahead.abs() > FLANK_RADIUS || across.abs() > frontage / 2.0 + FLANK_RADIUS {
+                    continue;
+                }
+                if across.abs() > frontage / 2.0 + FLANK_MARGIN || ahead < -FLANK_MARGIN {
+                    let flank = &mut flanks[usize::from(across > 0.0)];
+                    flank.0 += 1;
+                    flank.1 += enemy;
+                }
+            }
+            for (side, (count, sum)) in flanks.into_iter().enumerate() {
+                if count < FLANK_MIN_BOIDS {
+                    continue;
+                }
+                let threat = sum / count as f32;
+                let sign = if side == 0 { -1.0 } else { 1.0 };
+                let end = objective + right * sign * (frontage / 2.0 + LINE_GAP);
+                let facing = (threat - end).normalize_or(forward);
+                if reserve.is_empty() {
+                    let index = if side == 0 { 0 } else { posts.len() - 1 };
+                    posts[index].1 = facing;
+                } else {
+                    reinforce.push((end, facing));
+                }
+                info!("[ai] {faction:?} meets {count} boids on its flank");
+            }
+        }
+
+        // Threatened sectors: outnumbered close by, or shaken.
+        let mut threatened: Vec<(f32, Vec3)> = line
+            .iter()
+            .zip(&posts)
+            .filter_map(|(unit, &(post, _))| {
+                let near = tree
+                    .within_distance(unit.pos, THREAT_RADIUS)
+                    .into_iter()
+                    .filter_map(|(_, entity)| entity)
+                    .filter(|&entity| seen_enemy(entity))
+                    .count();
+                let threat = near as f32 / unit.strength as f32;
+                (threat > THREAT_RATIO || unit.shaken)
+                    .then(|| (threat, post - forward * (unit.depth + LINE_GAP)))
+            })
+            .collect();
+        threatened.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+        reinforce.extend(threatened.into_iter().map(|(_, pos)| (pos, forward)));
+
+        let mut orders: Vec<(Entity, Vec3, Vec3)> = Vec::new();
+        for (pos, facing) in reinforce {
+            let nearest = reserve
+                .iter()
+                .enumerate()
+                .min_by(|(_, a), (_, b)| a.pos.distance(pos).total_cmp(&b.pos.distance(pos)))
+                .map(|(i, _)| i);
+            let Some(nearest) = nearest else {
+                break;
+            };
+            let unit = reserve.swap_remove(nearest);
+            info!("[ai] {faction:?} sends {:?} to {pos}", unit.entity);
+            orders.push((unit.entity, pos, facing));
+        }
+        let mut offset = -(reserve.len() as f32 - 1.0) / 2.0;
+        for unit in &reserve {
+            let pos =
+                objective - forward * RESERVE_DEPTH + right * offset * (unit.width + LINE_GAP);
+            orders.push((unit.entity, pos, forward));
+            offset += 1.0;
+        }
+
+        // The line: shooters with an enemy in range fire at will.
+        for (unit, &(post, facing)) in line.iter().zip(&posts) {
+            let in_range = unit.range.and_then(|range| {
+                tree.within_distance(unit.pos, range)
+                    .into_iter()
+                    .filter_map(|(at, entity)| Some((entity?, at.distance(unit.pos))))
+                    .filter(|&(entity, _)| seen_enemy(entity))
+                    .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            });
+            let Some((enemy, _)) = in_range else {
+                orders.push((unit.entity, post, facing));
+                continue;
+            };
+            let target = q_member_of.get(enemy).map_or(enemy, |m| m.0);
+            let Ok((_, mut formation, ..)) = q_formations.get_mut(unit.entity) else {
+                continue;
+            };
+            let firing = matches!(
+                formation.tasks.front(),
+                Some(FormationOrder::FireAt { target: t }) if *t == target
+            );
+            if !firing {
+                formation.tasks.clear();
+                formation.tasks.push_back(FormationOrder::FireAt { target });
+            }
+        }
+
+        // An attack presses on at the double; a defence takes up its ground
+        // at the quick march, in order and with breath left to hold it.
+        let pace = match stance {
+            Stance::Attack => Pace::DoubleQuick,
+            Stance::Defend => Pace::QuickMarch,
+        };
+        for (entity, pos, facing) in orders {
+            if let Ok((_, mut formation, transform, ..)) = q_formations.get_mut(entity) {
+                let at = transform.translation.with_y(0.0);
+                order_move(&mut formation, at, pos, facing, pace);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formations::Footprint;
+    use crate::morale::RoutedFrom;
+    use bevy_spatial::{AutomaticUpdate, TransformMode};
+    use std::time::Duration;
+
+    fn moving_to(formation: &Formation) -> Option<(Vec3, Vec3, Pace)> {
+        match formation.tasks.front() {
+            Some(&FormationOrder::Move {
+                pos,
+                facing_dir,
+                pace,
+            }) => Some((pos, facing_dir, pace)),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn order_move_marches_a_formation_to_a_new_post() {
+        let mut formation = Formation::default();
+        formation.tasks.push_back(FormationOrder::Hold {
+            pos: Vec3::ZERO,
+            facing_dir: Vec3::Z,
+        });
+        let post = Vec3::new(20.0, 0.0, 0.0);
+        order_move(&mut formation, Vec3::ZERO, post, Vec3::X, Pace::Walk);
+        assert_eq!(formation.tasks.len(), 1, "replaces the hold");
+        assert_eq!(moving_to(&formation), Some((post, Vec3::X, Pace::Walk)));
+    }
+
+    #[test]
+    fn order_move_leaves_a_formation_going_to_or_standing_on_its_post() {
+        let post = Vec3::new(20.0, 0.0, 0.0);
+        let mut going = Formation::default();
+        let near = post + Vec3::Z * (REORDER_TOLERANCE / 2.0);
+        going.tasks.push_back(FormationOrder::Move {
+            pos: near,
+            facing_dir: Vec3::X,
+            pace: Pace::Walk,
+        });
+        order_move(&mut going, Vec3::ZERO, post, Vec3::X, Pace::Charge);
+        assert_eq!(moving_to(&going), Some((near, Vec3::X, Pace::Walk)));
+
+        let mut standing = Formation {
+            dir: Vec3::X,
+            ..default()
+        };
+        order_move(&mut standing, near, post, Vec3::X, Pace::Walk);
+        assert!(standing.tasks.is_empty());
+
+        // On the spot but faced the wrong way: turns.
+        order_move(&mut standing, near, post, Vec3::NEG_X, Pace::Walk);
+        assert_eq!(moving_to(&standing), Some((post, Vec3::NEG_X, Pace::Walk)));
+    }
+
+    #[test]
+    fn order_move_does_not_cut_a_maneuver_short() {
+        for maneuver in [
+            FormationOrder::AboutFace,
+            FormationOrder::Countermarch,
+            FormationOrder::Rally,
+        ] {
+            let mut formation = Formation::default();
+            formation.tasks.push_back(maneuver);
+            order_move(
+                &mut formation,
+                Vec3::ZERO,
+                Vec3::X * 20.0,
+                Vec3::Z,
+                Pace::Walk,
+            );
+            assert_eq!(formation.tasks.len(), 1);
+            assert!(moving_to(&formation).is_none(), "{maneuver:?} kept");
+        }
+    }
+
+    fn spawn_formation(app: &mut App, pos: Vec3, faction: Faction, members: &[Vec3]) -> Entity {
+        let formation = app
+            .world_mut()
+            .spawn((
+                Formation {
+                    footprint: Footprint {
+                        width: 8.0,
+                        depth: 4.0,
+                        center: Vec3::ZERO,
+                    },
+                    ..default()
+                },
+                Transform::from_translation(pos),
+                faction,
+            ))
+            .id();
+        for &at in members {
+            app.world_mut().spawn((
+                Boid::default(),
+                Transform::from_translation(at),
+                TrackedByTree,
+                Health::default(),
+                faction,
+                MemberOf(formation),
+            ));
+        }
+        formation
+    }
+
+    /// An app running a hard commander of the first faction, who sees
+    /// everything, and the commander.
+    fn commander_app() -> (App, Entity) {
+        let mut app = App::new();
+        let mut fog = FogOfWar::default();
+        fog.enabled = false;
+        app.init_resource::<Time>()
+            .insert_resource(fog)
+            .add_plugins(
+                AutomaticUpdate::<TrackedByTree>::new()
+                    .with_frequency(Duration::from_secs_f32(1.0 / 20.0))
+                    .with_transform(TransformMode::Transform),
+            )
+            .add_systems(Update, ai_commander_system);
+        let mut commander = AiCommander::new(Faction(0), Difficulty::HARD);
+        // Not before the spatial index is up.
+        commander.think_in = 0.5;
+        let commander = app.world_mut().spawn(commander).id();
+        (app, commander)
+    }
+
+    /// Let the spatial index come up, then the commander think once.
+    fn think(app: &mut App) {
+        for dt in [0.1, 1.0] {
+            app.world_mut()
+                .resource_mut::<Time>()
+                .advance_by(Duration::from_secs_f32(dt));
+            app.update();
+        }
+    }
+
+    #[test]
+    fn commander_draws_up_a_line_with_a_reserve_fires_and_rallies() {
+        let (mut app, commander) = commander_app();
+
+        // Ten enemies a hundred ahead, centred on the Z axis.
+        let enemies: Vec<Vec3> = (0..10)
+            .map(|i| Vec3::new(i as f32 - 4.5, 0.0, 100.0))
+            .collect();
+        let enemy = spawn_formation(&mut app, Vec3::new(0.0, 0.0, 100.0), Faction(1), &enemies);
+        // Four of five boids each, twice the enemy: one stands back, the
+        // middle of the front three shoots.
+        let five = |at: Vec3| [at; 5];
+        let ours = [-12.0, 0.0, 12.0].map(|x| {
+            let pos = Vec3::new(x, 0.0, 0.0);
+            spawn_formation(&mut app, pos, Faction(0), &five(pos))
+        });
+        let back = Vec3::new(0.0, 0.0, -10.0);
+        let reserve = spawn_formation(&mut app, back, Faction(0), &five(back));
+        let members = app.world().get::<Members>(ours[1]).unwrap();
+        let shooter = members.iter().next().unwrap();
+        app.world_mut().entity_mut(shooter).insert(RangedAttack {
+            range: 200.0,
+            ..default()
+        });
+        let routed = app
+            .world_mut()
+            .spawn((Formation::default(), Transform::default(), Faction(0)))
+            .id();
+        app.world_mut()
+            .spawn((Boid::default(), Faction(0), RoutedFrom(routed)));
+
+        think(&mut app);
+
+        let world = app.world();
+        let commander = world.get::<AiCommander>(commander).unwrap();
+        assert_eq!(commander.stance, Stance::Attack);
+        let objective = Vec3::new(0.0, 0.0, 100.0 - ENGAGE_DISTANCE);
+        assert!(commander.objective.unwrap().distance(objective) < 1e-3);
+        let formation = |entity| world.get::<Formation>(entity).unwrap();
+        let post = |entity| {
+            let (pos, facing, pace) = moving_to(formation(entity)).expect("ordered to move");
+            assert!(facing.distance(Vec3::Z) < 1e-3, "facing the enemy");
+            assert_eq!(pace, Pace::DoubleQuick, "attacking");
+            pos
+        };
+        // Side by side across the objective, a gap apart.
+        for (entity, x) in [(ours[0], -12.0), (ours[2], 12.0)] {
+            assert!(post(entity).distance(objective + Vec3::X * x) < 1e-3);
+        }
+        assert!(post(reserve).distance(objective - Vec3::Z * RESERVE_DEPTH) < 1e-3);
+        assert!(matches!(
+            formation(ours[1]).tasks.front(),
+            Some(&FormationOrder::FireAt { target }) if target == enemy
+        ));
+        assert!(matches!(
+            formation(routed).tasks.front(),
+            Some(FormationOrder::Rally)
+        ));
+    }
+
+    #[test]
+    fn attack_goes_at_the_double_and_defence_at_the_quick_march() {
+        for (enemies, stance, pace) in [
+            (5, Stance::Attack, Pace::DoubleQuick),
+            (40, Stance::Defend, Pace::QuickMarch),
+        ] {
+            let (mut app, commander) = commander_app();
+            let at: Vec<Vec3> = (0..enemies)
+                .map(|i| Vec3::new(i as f32 / 4.0, 0.0, 100.0))
+                .collect();
+            spawn_formation(&mut app, Vec3::new(0.0, 0.0, 100.0), Faction(1), &at);
+            // Ten boids in two formations standing wide apart, so each has
+            // a post to march to whatever the stance.
+            let ours = [-20.0, 20.0].map(|x| {
+                let pos = Vec3::new(x, 0.0, 0.0);
+                spawn_formation(&mut app, pos, Faction(0), &[pos; 5])
+            });
+
+            think(&mut app);
+            let world = app.world();
+            assert_eq!(world.get::<AiCommander>(commander).unwrap().stance, stance);
+            for entity in ours {
+                let formation = world.get::<Formation>(entity).unwrap();
+                let (_, _, ordered) = moving_to(formation).expect("ordered to move");
+                assert_eq!(ordered, pace, "{stance:?}");
+            }
+        }
+    }
+}
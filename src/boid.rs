@@ -1,18 +1,56 @@
+use crate::combat::{Health, MeleeAttack};
+use crate::faction::Faction;
 use crate::kinematics::*;
+use crate::morale::Morale;
 use crate::resources::Materials;
 use crate::target::Target;
 use crate::terrain::Obstacle;
+use crate::vision::VIEW_RANGE;
 use bevy::prelude::Bundle;
 use bevy::prelude::*;
 use bevy_spatial::SpatialAccess;
 use rand::Rng;
 
 #[derive(Component, Default)]
+#[require(Faction, Morale)]
 pub struct Boid {}
 
+/// Kind of unit a boid is. Units of one type are interchangeable: selecting
+/// "all of this type" goes by it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum UnitType {
+    #[default]
+    Infantry,
+    Cavalry,
+}
+
+/// Per-boid statistics.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct UnitStats {
+    pub unit_type: UnitType,
+    /// Top speed; a formation marches no faster than its slowest member
+    /// (see [`init_formation_speed`](crate::formations::init_formation_speed)).
+    pub max_speed: f32,
+    /// How far it sees (see [`FogOfWar`](crate::vision::FogOfWar)).
+    pub view_range: f32,
+}
+
+impl Default for UnitStats {
+    fn default() -> Self {
+        Self {
+            unit_type: UnitType::default(),
+            max_speed: MAX_VELOCITY,
+            view_range: VIEW_RANGE,
+        }
+    }
+}
+
 #[derive(Bundle, Default)]
 pub struct BoidBundle {
     boid: Boid,
+    stats: UnitStats,
+    health: Health,
+    melee: MeleeAttack,
     transform: Transform,
     target: Target,
     vel: Velocity,
@@ -54,6 +92,7 @@ impl BoidBundle {
             target: Target {
                 pos: Vec3::from_array([-x, 1.0, -z]),
                 dir: Default::default(),
+                speed: None,
             },
             mesh: Mesh3d(mesh),
             material: MeshMaterial3d(material),
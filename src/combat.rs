@@ -0,0 +1,976 @@
+use crate::boid::{Bob, Boid};
+use crate::faction::Faction;
+use crate::formations::{
+    Formation, FormationOf, FormationOrder, FormationSlot, Formations, MemberOf, Members,
+    NeedsSpeedInit,
+};
+use crate::kinematics::{NNTree, SoftCollision, TrackedByTree, Velocity};
+use crate::morale::{Morale, RoutedFrom, Routers};
+use crate::player::Selected;
+use crate::resources::{Materials, Meshes};
+use crate::target::Target;
+use bevy::prelude::*;
+use bevy_spatial::SpatialAccess;
+use rand::Rng;
+use std::f32::consts::FRAC_PI_2;
+
+/// Hit points. At zero the unit dies: [`death_system`] lays it down as a
+/// [`Corpse`].
+#[derive(Component, Clone, Copy, Debug)]
+pub struct Health {
+    pub current: f32,
+    pub max: f32,
+}
+
+impl Default for Health {
+    fn default() -> Self {
+        Self {
+            current: 100.0,
+            max: 100.0,
+        }
+    }
+}
+
+impl Health {
+    pub fn is_dead(&self) -> bool {
+        self.current <= 0.0
+    }
+}
+
+/// Hand-to-hand attack: strikes the nearest enemy within `reach` for
+/// `damage`, then waits `cooldown` seconds (see [`melee_system`]).
+#[derive(Component, Clone, Copy, Debug)]
+pub struct MeleeAttack {
+    pub damage: f32,
+    pub reach: f32,
+    pub cooldown: f32,
+    /// Seconds until the next strike.
+    pub ready_in: f32,
+}
+
+impl Default for MeleeAttack {
+    fn default() -> Self {
+        Self {
+            damage: 10.0,
+            reach: 1.2,
+            cooldown: 1.0,
+            ready_in: 0.0,
+        }
+    }
+}
+
+/// Shooting with bow or musket: projectiles launched at `speed` on a
+/// ballistic arc toward a target within `range`, one every `reload`
+/// seconds. `accuracy` (0..=1) narrows the scatter about the aim point,
+/// which grows with distance (see [`SCATTER_PER_DISTANCE`]). Fired by
+/// formations under [`FormationOrder::FireAt`].
+#[derive(Component, Clone, Copy, Debug)]
+pub struct RangedAttack {
+    pub damage: f32,
+    pub range: f32,
+    pub reload: f32,
+    pub accuracy: f32,
+    /// Launch speed of the projectile.
+    pub speed: f32,
+    /// Seconds until loaded again.
+    pub ready_in: f32,
+}
+
+impl Default for RangedAttack {
+    fn default() -> Self {
+        Self {
+            damage: 35.0,
+            range: 60.0,
+            reload: 4.0,
+            accuracy: 0.8,
+            speed: 40.0,
+            ready_in: 0.0,
+        }
+    }
+}
+
+/// Scatter radius about the aim point per unit of distance, for an
+/// accuracy of zero.
+pub const SCATTER_PER_DISTANCE: f32 = 0.1;
+
+/// Ranks (from the front) that shoot under [`FormationOrder::FireAt`]; the
+/// rest stand ready behind them.
+pub const FIRING_RANKS: usize = 2;
+
+/// Delay (seconds) before a rank that had nobody loaded and in range is
+/// tried again.
+const VOLLEY_RETRY_SECS: f32 = 0.5;
+
+/// Volley fire state of a formation: the firing ranks fire one after the
+/// other, spread over the reload time, so one rank is always loaded.
+/// Inserted with every `Formation` (`require`).
+#[derive(Component, Default, Clone, Copy, Debug)]
+pub struct Volley {
+    /// Rank (from the front) to fire next.
+    pub next_rank: usize,
+    /// Seconds until it does.
+    pub ready_in: f32,
+}
+
+/// A projectile in ballistic flight. Hits the first enemy it comes within
+/// [`PROJECTILE_HIT_RADIUS`] of, or buries itself in the ground.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct Projectile {
+    pub velocity: Vec3,
+    pub damage: f32,
+    /// The shooter's side; projectiles do not hit their own.
+    pub faction: Faction,
+    pub shooter: Entity,
+}
+
+pub const GRAVITY: f32 = 9.81;
+
+/// A projectile this close to a unit hits it.
+pub const PROJECTILE_HIT_RADIUS: f32 = 0.6;
+
+/// Below this height a projectile is low enough to hit anyone.
+const PROJECTILE_HIT_HEIGHT: f32 = 2.5;
+
+/// Extra radius for searches of the spatial index, which only catches up
+/// with where units stand once a second: a unit that has stepped into
+/// reach since is still found, and then measured where it really is.
+const TREE_SLACK: f32 = 2.0;
+
+/// Launch velocity of speed `speed` from `from` that lands on `to` by the
+/// low (flat) arc. `None` when `to` is out of reach at that speed.
+pub fn launch_velocity(from: Vec3, to: Vec3, speed: f32) -> Option<Vec3> {
+    let offset = to - from;
+    let horizontal = Vec3::new(offset.x, 0.0, offset.z);
+    let x = horizontal.length();
+    if x < 1e-3 {
+        return None;
+    }
+    let (v2, y) = (speed * speed, offset.y);
+    let root = v2 * v2 - GRAVITY * (GRAVITY * x * x + 2.0 * y * v2);
+    if root < 0.0 {
+        return None;
+    }
+    let angle = ((v2 - root.sqrt()) / (GRAVITY * x)).atan();
+    Some(speed * (angle.cos() * horizontal / x + angle.sin() * Vec3::Y))
+}
+
+/// A dead boid left lying on the field, stripped of everything that made it
+/// move, collide or fight. Despawned once `decay` (seconds) runs out.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct Corpse {
+    pub decay: f32,
+}
+
+/// Seconds a corpse stays on the field.
+pub const CORPSE_DECAY_SECS: f32 = 30.0;
+
+/// What happened in a fight, for systems that react to it (morale, AI,
+/// objectives). The victim's formation is the one it belonged to when hit.
+#[derive(Message, Clone, Copy, Debug)]
+pub enum CombatEvent {
+    Hit {
+        attacker: Entity,
+        target: Entity,
+        damage: f32,
+    },
+    Killed {
+        attacker: Entity,
+        target: Entity,
+        faction: Faction,
+        formation: Option<Entity>,
+        pos: Vec3,
+    },
+}
+
+/// Every unit with a [`MeleeAttack`] whose cooldown has run out strikes the
+/// nearest living enemy (another [`Faction`]) within reach. Candidates come
+/// from the spatial index, searched [`TREE_SLACK`] wider as it lags
+/// behind; reach is measured where they stand now. Strikes apply at once,
+/// so a unit killed earlier in the tick is not struck again and does not
+/// strike back.
+pub fn melee_system(
+    time: Res<Time>,
+    tree: Res<NNTree>,
+    mut q_attackers: Query<(Entity, &Transform, &Faction, &mut MeleeAttack)>,
+    mut q_targets: Query<(&Transform, &Faction, &mut Health)>,
+    q_member_of: Query<&MemberOf>,
+    mut events: MessageWriter<CombatEvent>,
+) {
+    let dt = time.delta_secs();
+    for (attacker, transform, faction, mut attack) in &mut q_attackers {
+        attack.ready_in = (attack.ready_in - dt).max(0.0);
+        if attack.ready_in > 0.0 {
+            continue;
+        }
+        if q_targets.get(attacker).is_ok_and(|(_, _, h)| h.is_dead()) {
+            continue;
+        }
+        let pos = transform.translation;
+        let nearest = tree
+            .within_distance(pos, attack.reach + TREE_SLACK)
+            .into_iter()
+            .filter_map(|(_, entity)| entity)
+            .filter(|&entity| entity != attacker)
+            .filter_map(|entity| {
+                let (target_transform, target_faction, health) = q_targets.get(entity).ok()?;
+                let distance = target_transform.translation.distance(pos);
+                (target_faction != faction && !health.is_dead() && distance <= attack.reach)
+                    .then_some((entity, distance))
+            })
+            .min_by(|(_, a), (_, b)| a.total_cmp(b));
+        let Some((target, _)) = nearest else {
+            continue;
+        };
+
+        let (target_transform, target_faction, mut health) =
+            q_targets.get_mut(target).expect("target was just found");
+        attack.ready_in = attack.cooldown;
+        strike(
+            &mut events,
+            attacker,
+            target,
+            attack.damage,
+            &mut health,
+            *target_faction,
+            q_member_of.get(target).ok().map(|m| m.0),
+            target_transform.translation,
+        );
+    }
+}
+
+/// Deal `damage` to `target` and report it, with a kill if it dies of it.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn strike(
+    events: &mut MessageWriter<CombatEvent>,
+    attacker: Entity,
+    target: Entity,
+    damage: f32,
+    health: &mut Health,
+    faction: Faction,
+    formation: Option<Entity>,
+    pos: Vec3,
+) {
+    health.current -= damage;
+    events.write(CombatEvent::Hit {
+        attacker,
+        target,
+        damage,
+    });
+    if health.is_dead() {
+        events.write(CombatEvent::Killed {
+            attacker,
+            target,
+            faction,
+            formation,
+            pos,
+        });
+    }
+}
+
+/// Lay the dead down as [`Corpse`]s, fallen over. A dead member leaves its
+/// formation (`MemberOf` together with `FormationSlot`, see
+/// [`FormationSlot`]), so `assign_slots` backfills the gap and the ranks
+/// close up; the formation re-derives its speed without it.
+///
+/// A formation whose last boid falls, member or fleeing from it, is wiped
+/// out and despawned (see [`despawn_if_wiped_out`]), which ends every
+/// [`FormationOrder::FireAt`] and `Charge` aimed at it.
+pub fn death_system(
+    mut q_dead: Query<
+        (
+            Entity,
+            &Health,
+            &mut Transform,
+            Option<&MemberOf>,
+            Option<&RoutedFrom>,
+        ),
+        (Changed<Health>, With<Boid>),
+    >,
+    mut commands: Commands,
+) {
+    for (entity, health, mut transform, member_of, routed_from) in &mut q_dead {
+        if !health.is_dead() {
+            continue;
+        }
+        transform.rotate_local_x(FRAC_PI_2);
+        transform.translation.y = 0.1;
+        if let Some(member_of) = member_of {
+            commands.entity(member_of.0).insert(NeedsSpeedInit);
+        }
+        commands
+            .entity(entity)
+            .remove::<(
+                Boid,
+                Velocity,
+                Target,
+                Bob,
+                MemberOf,
+                FormationSlot,
+                TrackedByTree,
+                SoftCollision,
+                MeleeAttack,
+                Health,
+                Morale,
+                RoutedFrom,
+                Selected,
+            )>()
+            .insert(Corpse {
+                decay: CORPSE_DECAY_SECS,
+            });
+        // Queued after the boid has left, so the last to fall finds the
+        // formation empty.
+        if let Some(formation) = member_of.map(|m| m.0).or(routed_from.map(|r| r.0)) {
+            commands.queue(move |world: &mut World| despawn_if_wiped_out(world, formation));
+        }
+    }
+}
+
+/// Despawn `formation` if no one is left below it: no members, no
+/// sub-formations and no boids fleeing from it. Its parent, left without
+/// it, is checked in turn.
+fn despawn_if_wiped_out(world: &mut World, formation: Entity) {
+    let Ok(entity) = world.get_entity(formation) else {
+        return;
+    };
+    let wiped_out = entity.contains::<Formation>()
+        && entity.get::<Members>().is_none_or(|m| m.is_empty())
+        && entity.get::<Formations>().is_none_or(|s| s.is_empty())
+        && entity.get::<Routers>().is_none_or(|r| r.is_empty());
+    if !wiped_out {
+        return;
+    }
+    let parent = entity.get::<FormationOf>().map(|p| p.0);
+    world.despawn(formation);
+    info!("[death] formation {formation:?} wiped out");
+    if let Some(parent) = parent {
+        despawn_if_wiped_out(world, parent);
+    }
+}
+
+/// Corpses rot away after [`CORPSE_DECAY_SECS`].
+pub fn corpse_system(
+    time: Res<Time>,
+    mut q_corpses: Query<(Entity, &mut Corpse)>,
+    mut commands: Commands,
+) {
+    for (entity, mut corpse) in &mut q_corpses {
+        corpse.decay -= time.delta_secs();
+        if corpse.decay <= 0.0 {
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+/// Volley fire for formations under [`FormationOrder::FireAt`]. Each
+/// formation's firing ranks ([`FIRING_RANKS`] from the front, see
+/// [`Formation::rank_from_front`]) fire in turn: everyone of the rank who
+/// is loaded and within range of the target looses a [`Projectile`] at it
+/// together, then the next rank follows after the reload time shared out
+/// among the ranks. The order is dropped once the target is gone (or, for a
+/// boid, dead).
+#[allow(clippy::too_many_arguments)]
+pub fn ranged_fire_system(
+    time: Res<Time>,
+    mut q_formations: Query<(
+        &mut Formation,
+        &mut Volley,
+        &Faction,
+        Option<&Members>,
+        Option<&Formations>,
+    )>,
+    q_positions: Query<&GlobalTransform, Or<(With<Formation>, With<Health>)>>,
+    mut q_shooters: Query<(Entity, &Transform, &FormationSlot, &mut RangedAttack)>,
+    meshes: Res<Meshes>,
+    materials: Res<Materials>,
+    mut commands: Commands,
+) {
+    let dt = time.delta_secs();
+    for (_, _, _, mut attack) in &mut q_shooters {
+        attack.ready_in = (attack.ready_in - dt).max(0.0);
+    }
+    let mut rng = rand::rng();
+    for (mut formation, mut volley, faction, members, subs) in &mut q_formations {
+        let Some(FormationOrder::FireAt { target }) = formation.tasks.front().copied() else {
+            continue;
+        };
+        let Ok(target_pos) = q_positions.get(target).map(|t| t.translation()) else {
+            formation.tasks.pop_front();
+            continue;
+        };
+        let Some(members) = members else {
+            continue;
+        };
+        volley.ready_in -= dt;
+        if volley.ready_in > 0.0 {
+            continue;
+        }
+
+        let total = members.len() + subs.map_or(0, |s| s.len());
+        let ranks = formation.rank_from_front(0, total).1.clamp(1, FIRING_RANKS);
+        if volley.next_rank >= ranks {
+            volley.next_rank = 0;
+        }
+        let mut fired = 0;
+        let mut reload: f32 = 0.0;
+        for member in members.iter() {
+            let Ok((shooter, transform, slot, mut attack)) = q_shooters.get_mut(member) else {
+                continue;
+            };
+            let (rank, _) = formation.rank_from_front(slot.0, total);
+            if rank != volley.next_rank || attack.ready_in > 0.0 {
+                continue;
+            }
+            let from = transform.translation;
+            let distance = from.distance(target_pos);
+            if distance > attack.range {
+                continue;
+            }
+            let scatter = (1.0 - attack.accuracy.clamp(0.0, 1.0)) * SCATTER_PER_DISTANCE * distance;
+            let aim = target_pos
+                + Vec3::new(
+                    rng.random_range(-1.0..=1.0),
+                    0.0,
+                    rng.random_range(-1.0..=1.0),
+                ) * scatter;
+            let Some(velocity) = launch_velocity(from, aim, attack.speed) else {
+                continue;
+            };
+            commands.spawn((
+                Projectile {
+                    velocity,
+                    damage: attack.damage,
+                    faction: *faction,
+                    shooter,
+                },
+                Mesh3d(meshes.cube.clone()),
+                MeshMaterial3d(materials.black.clone()),
+                Transform::from_translation(from).with_scale(Vec3::splat(0.1)),
+            ));
+            attack.ready_in = attack.reload;
+            reload = reload.max(attack.reload);
+            fired += 1;
+        }
+        volley.next_rank = (volley.next_rank + 1) % ranks;
+        volley.ready_in = if fired > 0 {
+            reload / ranks as f32
+        } else {
+            VOLLEY_RETRY_SECS
+        };
+        if fired > 0 {
+            info!("[volley] {fired} shots at {target:?}");
+        }
+    }
+}
+
+/// Fly projectiles under gravity and resolve their hits. Each frame's
+/// flight is swept as a segment, so a fast projectile cannot pass through
+/// a unit between two frames: the first living unit of another faction it
+/// passes within [`PROJECTILE_HIT_RADIUS`] of takes the damage. Candidates
+/// come from the spatial index, searched [`TREE_SLACK`] wider as it lags
+/// behind, and are measured where they stand now. A projectile that
+/// reaches the ground without hitting anyone is spent.
+pub fn projectile_system(
+    time: Res<Time>,
+    tree: Res<NNTree>,
+    mut q_projectiles: Query<(Entity, &mut Projectile, &mut Transform)>,
+    mut q_targets: Query<
+        (&Transform, &Faction, &mut Health, Option<&MemberOf>),
+        Without<Projectile>,
+    >,
+    mut events: MessageWriter<CombatEvent>,
+    mut commands: Commands,
+) {
+    let dt = time.delta_secs();
+    for (entity, mut projectile, mut transform) in &mut q_projectiles {
+        let from = transform.translation;
+        projectile.velocity.y -= GRAVITY * dt;
+        let mut to = from + projectile.velocity * dt;
+        transform.translation = to;
+        if let Ok(direction) = Dir3::new(projectile.velocity) {
+            transform.look_to(direction, Vec3::Y);
+        }
+        let grounded = to.y <= 0.0;
+        if grounded && from.y > to.y {
+            // Sweep no further than where it meets the ground.
+            to = from.lerp(to, from.y / (from.y - to.y));
+        }
+        if from.y.min(to.y) <= PROJECTILE_HIT_HEIGHT {
+            let search = from.distance(to) / 2.0 + PROJECTILE_HIT_RADIUS + TREE_SLACK;
+            let hit = tree
+                .within_distance(from.lerp(to, 0.5), search)
+                .into_iter()
+                .filter_map(|(_, target)| target)
+                .filter_map(|target| {
+                    let (target_transform, faction, health, _) = q_targets.get(target).ok()?;
+                    if *faction == projectile.faction || health.is_dead() {
+                        return None;
+                    }
+                    Some((target, sweep_hit(from, to, target_transform.translation)?))
+                })
+                .min_by(|(_, a), (_, b)| a.total_cmp(b));
+            if let Some((target, along)) = hit {
+                let (_, faction, mut health, member_of) =
+                    q_targets.get_mut(target).expect("just found");
+                strike(
+                    &mut events,
+                    projectile.shooter,
+                    target,
+                    projectile.damage,
+                    &mut health,
+                    *faction,
+                    member_of.map(|m| m.0),
+                    from.lerp(to, along),
+                );
+                commands.entity(entity).despawn();
+                continue;
+            }
+        }
+        if grounded {
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+/// Fraction (0..=1) along the flight `from`-`to` where it passes closest
+/// to `point`, if it passes within [`PROJECTILE_HIT_RADIUS`] of it.
+fn sweep_hit(from: Vec3, to: Vec3, point: Vec3) -> Option<f32> {
+    let flight = to - from;
+    let along = if flight.length_squared() > 0.0 {
+        ((point - from).dot(flight) / flight.length_squared()).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    (from.lerp(to, along).distance(point) <= PROJECTILE_HIT_RADIUS).then_some(along)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formations::{FormationKind, FormationParams, assign_slots};
+    use bevy_spatial::{AutomaticUpdate, TransformMode};
+    use std::time::Duration;
+
+    /// Every combat event written, in order.
+    #[derive(Resource, Default)]
+    struct Log(Vec<CombatEvent>);
+
+    fn record(mut reader: MessageReader<CombatEvent>, mut log: ResMut<Log>) {
+        log.0.extend(reader.read().copied());
+    }
+
+    /// Headless app: manual time, the kd tree refreshed once a second as in
+    /// the game, and combat with its aftermath in execution order.
+    fn test_app() -> App {
+        let mut app = App::new();
+        app.init_resource::<Time>()
+            .init_resource::<Log>()
+            .init_resource::<Meshes>()
+            .init_resource::<Materials>()
+            .add_message::<CombatEvent>()
+            .add_plugins(
+                AutomaticUpdate::<TrackedByTree>::new()
+                    .with_frequency(Duration::from_secs_f32(1.0))
+                    .with_transform(TransformMode::Transform),
+            )
+            .add_systems(
+                Update,
+                (
+                    melee_system,
+                    ranged_fire_system,
+                    projectile_system,
+                    death_system,
+                    assign_slots,
+                    record,
+                )
+                    .chain(),
+            );
+        app
+    }
+
+    fn tick(app: &mut App, dt: f32) {
+        app.world_mut()
+            .resource_mut::<Time>()
+            .advance_by(Duration::from_secs_f32(dt));
+        app.update();
+    }
+
+    fn spawn_boid(app: &mut App, pos: Vec3, faction: Faction) -> Entity {
+        app.world_mut()
+            .spawn((
+                Boid::default(),
+                Transform::from_translation(pos),
+                // Read by `ranged_fire_system`; nothing propagates it here.
+                GlobalTransform::from_translation(pos),
+                TrackedByTree,
+                Health::default(),
+                faction,
+            ))
+            .id()
+    }
+
+    fn health(app: &App, entity: Entity) -> f32 {
+        app.world().get::<Health>(entity).unwrap().current
+    }
+
+    fn kill(app: &mut App, entity: Entity) {
+        app.world_mut().get_mut::<Health>(entity).unwrap().current = 0.0;
+    }
+
+    fn spawn_projectile(app: &mut App, pos: Vec3, velocity: Vec3) -> Entity {
+        let shooter = app.world_mut().spawn_empty().id();
+        app.world_mut()
+            .spawn((
+                Projectile {
+                    velocity,
+                    damage: 50.0,
+                    faction: Faction(0),
+                    shooter,
+                },
+                Transform::from_translation(pos),
+            ))
+            .id()
+    }
+
+    #[test]
+    fn launch_velocity_lands_on_targets_in_reach() {
+        let (from, to) = (Vec3::new(0.0, 1.0, 0.0), Vec3::new(30.0, 0.0, 10.0));
+        let velocity = launch_velocity(from, to, 40.0).expect("in reach");
+        assert!((velocity.length() - 40.0).abs() < 1e-3);
+        let flat = Vec3::new(velocity.x, 0.0, velocity.z);
+        assert!(velocity.y < flat.length(), "the low arc, under 45 degrees");
+        // Fly it out to the target's distance: it comes down on it.
+        let t = (to - from).with_y(0.0).length() / flat.length();
+        let landing = from + velocity * t - 0.5 * GRAVITY * t * t * Vec3::Y;
+        assert!(landing.distance(to) < 1e-2, "landed at {landing}");
+    }
+
+    #[test]
+    fn launch_velocity_gives_up_out_of_reach() {
+        // Farthest throw at 10 is 10^2 / g, about 10.2 on the level.
+        assert!(launch_velocity(Vec3::ZERO, Vec3::new(12.0, 0.0, 0.0), 10.0).is_none());
+        assert!(launch_velocity(Vec3::ZERO, Vec3::new(9.0, 0.0, 0.0), 10.0).is_some());
+        assert!(
+            launch_velocity(Vec3::ZERO, Vec3::new(0.0, 5.0, 0.0), 40.0).is_none(),
+            "no arc lands straight overhead"
+        );
+    }
+
+    #[test]
+    fn volley_fires_rank_after_rank() {
+        let mut app = test_app();
+        let target = spawn_boid(&mut app, Vec3::new(0.0, 0.0, 40.0), Faction(1));
+        // Stands up to every volley, so the order holds.
+        app.world_mut().entity_mut(target).insert(Health {
+            current: 1e6,
+            max: 1e6,
+        });
+        let formation = app
+            .world_mut()
+            .spawn((
+                Formation {
+                    kind: FormationKind::Grid,
+                    params: FormationParams {
+                        files: Some(3),
+                        ..default()
+                    },
+                    ..default()
+                },
+                Transform::default(),
+            ))
+            .id();
+        let shooters: Vec<Entity> = (0..9)
+            .map(|i| {
+                let pos = Vec3::new((i % 3) as f32, 0.0, -((i / 3) as f32));
+                let shooter = spawn_boid(&mut app, pos, Faction(0));
+                app.world_mut().entity_mut(shooter).insert((
+                    MemberOf(formation),
+                    FormationSlot(i),
+                    RangedAttack::default(),
+                ));
+                shooter
+            })
+            .collect();
+        app.world_mut()
+            .get_mut::<Formation>(formation)
+            .unwrap()
+            .tasks
+            .push_back(FormationOrder::FireAt { target });
+
+        let fired = |app: &App| -> Vec<usize> {
+            let formation = app.world().get::<Formation>(formation).unwrap();
+            let mut ranks: Vec<usize> = shooters
+                .iter()
+                .enumerate()
+                .filter(|&(_, &s)| app.world().get::<RangedAttack>(s).unwrap().ready_in > 0.0)
+                .map(|(i, _)| formation.rank_from_front(i, 9).0)
+                .collect();
+            ranks.sort();
+            ranks
+        };
+        tick(&mut app, 0.1);
+        assert_eq!(fired(&app), [0, 0, 0], "the front rank first");
+        let volley = *app.world().get::<Volley>(formation).unwrap();
+        assert_eq!(volley.next_rank, 1);
+        let reload = RangedAttack::default().reload;
+        assert_eq!(volley.ready_in, reload / FIRING_RANKS as f32);
+
+        tick(&mut app, reload / 2.0);
+        assert_eq!(fired(&app), [0, 0, 0, 1, 1, 1], "then the second");
+        // The third rank stands ready; the front rank, reloaded, goes again.
+        tick(&mut app, reload / 2.0);
+        assert_eq!(fired(&app), [0, 0, 0, 1, 1, 1]);
+        let volley = app.world().get::<Volley>(formation).unwrap();
+        assert_eq!(volley.next_rank, 1);
+    }
+
+    #[test]
+    fn fast_projectile_hits_what_it_passes_between_frames() {
+        let mut app = test_app();
+        let target = spawn_boid(&mut app, Vec3::new(0.0, 0.5, 0.0), Faction(1));
+        tick(&mut app, 1.0);
+        // Ten units a frame: starts five short, ends five past.
+        spawn_projectile(
+            &mut app,
+            Vec3::new(0.0, 0.5, -5.0),
+            Vec3::new(0.0, 0.0, 100.0),
+        );
+
+        tick(&mut app, 0.1);
+        assert_eq!(health(&app, target), 50.0);
+        assert!(
+            app.world_mut()
+                .query::<&Projectile>()
+                .iter(app.world())
+                .next()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn projectiles_hit_units_where_they_stand_now() {
+        let mut app = test_app();
+        let stepped_off = spawn_boid(&mut app, Vec3::new(0.0, 0.5, 0.0), Faction(1));
+        let stepped_on = spawn_boid(&mut app, Vec3::new(0.0, 0.5, 2.5), Faction(1));
+        tick(&mut app, 1.0);
+        let world = app.world_mut();
+        world
+            .get_mut::<Transform>(stepped_off)
+            .unwrap()
+            .translation
+            .z = 2.0;
+        world.get_mut::<Transform>(stepped_on).unwrap().translation = Vec3::new(0.5, 0.5, 0.0);
+        spawn_projectile(
+            &mut app,
+            Vec3::new(-1.0, 0.5, 0.0),
+            Vec3::new(20.0, 0.0, 0.0),
+        );
+
+        tick(&mut app, 0.1);
+        assert_eq!(health(&app, stepped_on), 50.0);
+        assert_eq!(health(&app, stepped_off), 100.0);
+    }
+
+    #[test]
+    fn projectiles_that_miss_are_spent_on_the_ground() {
+        let mut app = test_app();
+        let bystander = spawn_boid(&mut app, Vec3::new(0.0, 0.5, 3.0), Faction(1));
+        tick(&mut app, 1.0);
+        let projectile = spawn_projectile(&mut app, Vec3::new(0.0, 0.2, 0.0), -5.0 * Vec3::Y);
+
+        tick(&mut app, 0.1);
+        assert!(app.world().get_entity(projectile).is_err());
+        assert_eq!(health(&app, bystander), 100.0);
+    }
+
+    #[test]
+    fn melee_strikes_within_reach_then_waits_for_the_cooldown() {
+        let mut app = test_app();
+        let attacker = spawn_boid(&mut app, Vec3::ZERO, Faction(0));
+        let stepped_back = spawn_boid(&mut app, Vec3::new(1.0, 0.0, 0.0), Faction(1));
+        let stepped_in = spawn_boid(&mut app, Vec3::new(3.0, 0.0, 0.0), Faction(1));
+        // Index the field, then let both step: the index still has them
+        // where they were.
+        tick(&mut app, 1.0);
+        let world = app.world_mut();
+        world
+            .get_mut::<Transform>(stepped_back)
+            .unwrap()
+            .translation
+            .x = 3.0;
+        world
+            .get_mut::<Transform>(stepped_in)
+            .unwrap()
+            .translation
+            .x = 1.1;
+        world.entity_mut(attacker).insert(MeleeAttack {
+            damage: 10.0,
+            reach: 1.2,
+            cooldown: 1.0,
+            ready_in: 0.0,
+        });
+
+        tick(&mut app, 0.1);
+        assert_eq!(health(&app, stepped_in), 90.0);
+        assert_eq!(health(&app, stepped_back), 100.0, "out of reach now");
+        tick(&mut app, 0.5);
+        assert_eq!(health(&app, stepped_in), 90.0, "cooling down");
+        tick(&mut app, 0.6);
+        assert_eq!(health(&app, stepped_in), 80.0);
+    }
+
+    #[test]
+    fn melee_spares_its_own_side() {
+        let mut app = test_app();
+        let attacker = spawn_boid(&mut app, Vec3::ZERO, Faction(0));
+        let friend = spawn_boid(&mut app, Vec3::new(0.5, 0.0, 0.0), Faction(0));
+        tick(&mut app, 1.0);
+        app.world_mut()
+            .entity_mut(attacker)
+            .insert(MeleeAttack::default());
+
+        tick(&mut app, 0.1);
+        assert_eq!(health(&app, friend), 100.0);
+        let attack = app.world().get::<MeleeAttack>(attacker).unwrap();
+        assert_eq!(attack.ready_in, 0.0, "nothing struck, still ready");
+        assert!(app.world().resource::<Log>().0.is_empty());
+    }
+
+    #[test]
+    fn killing_blow_reports_the_victim_and_its_formation() {
+        let mut app = test_app();
+        let attacker = spawn_boid(&mut app, Vec3::ZERO, Faction(0));
+        let formation = app
+            .world_mut()
+            .spawn((Formation::default(), Transform::default(), Faction(1)))
+            .id();
+        let victim = spawn_boid(&mut app, Vec3::new(1.0, 0.0, 0.0), Faction(1));
+        app.world_mut()
+            .entity_mut(victim)
+            .insert(MemberOf(formation));
+        tick(&mut app, 1.0);
+        app.world_mut().entity_mut(attacker).insert(MeleeAttack {
+            damage: 100.0,
+            ..default()
+        });
+
+        tick(&mut app, 0.1);
+        let log = &app.world().resource::<Log>().0;
+        assert!(matches!(
+            log[..],
+            [
+                CombatEvent::Hit {
+                    attacker: a,
+                    target: t,
+                    damage,
+                },
+                CombatEvent::Killed {
+                    attacker: ka,
+                    target: kt,
+                    faction: Faction(1),
+                    formation: Some(f),
+                    pos,
+                },
+            ] if a == attacker && t == victim && damage == 100.0 && ka == attacker && kt == victim
+                && f == formation && pos == Vec3::new(1.0, 0.0, 0.0)
+        ));
+    }
+
+    #[test]
+    fn the_dead_lie_down_and_the_ranks_close_up() {
+        let mut app = test_app();
+        let formation = app
+            .world_mut()
+            .spawn((Formation::default(), Transform::default()))
+            .id();
+        let boids: Vec<Entity> = (0..3)
+            .map(|i| {
+                let boid = spawn_boid(&mut app, Vec3::new(i as f32, 0.0, 0.0), Faction(0));
+                app.world_mut()
+                    .entity_mut(boid)
+                    .insert((MemberOf(formation), FormationSlot(i)));
+                boid
+            })
+            .collect();
+        tick(&mut app, 1.0);
+        app.world_mut()
+            .entity_mut(formation)
+            .remove::<NeedsSpeedInit>();
+
+        kill(&mut app, boids[0]);
+        tick(&mut app, 0.1);
+        let world = app.world();
+        let corpse = world.entity(boids[0]);
+        assert!(corpse.contains::<Corpse>());
+        for gone in [
+            corpse.contains::<Boid>(),
+            corpse.contains::<Health>(),
+            corpse.contains::<MemberOf>(),
+            corpse.contains::<FormationSlot>(),
+            corpse.contains::<TrackedByTree>(),
+        ] {
+            assert!(!gone, "a corpse neither fights nor stands in the ranks");
+        }
+        assert!(world.get::<NeedsSpeedInit>(formation).is_some());
+        // The gap at slot 0 is filled: the survivors hold slots 0 and 1.
+        let mut slots: Vec<usize> = boids[1..]
+            .iter()
+            .map(|&b| world.get::<FormationSlot>(b).unwrap().0)
+            .collect();
+        slots.sort();
+        assert_eq!(slots, [0, 1]);
+    }
+
+    #[test]
+    fn wiped_out_formations_are_despawned_and_no_longer_fired_at() {
+        let mut app = test_app();
+        let world = app.world_mut();
+        let parent = world
+            .spawn((Formation::default(), Transform::default()))
+            .id();
+        let company = world
+            .spawn((
+                Formation::default(),
+                Transform::default(),
+                FormationOf(parent),
+            ))
+            .id();
+        let routed = world
+            .spawn((Formation::default(), Transform::default()))
+            .id();
+        let shooters = world
+            .spawn((Formation::default(), Transform::default()))
+            .id();
+        world
+            .get_mut::<Formation>(shooters)
+            .unwrap()
+            .tasks
+            .push_back(FormationOrder::FireAt { target: company });
+        let last = spawn_boid(&mut app, Vec3::ZERO, Faction(1));
+        let member = spawn_boid(&mut app, Vec3::X, Faction(1));
+        let fleeing = spawn_boid(&mut app, Vec3::Z, Faction(1));
+        let world = app.world_mut();
+        world.entity_mut(last).insert(MemberOf(company));
+        world.entity_mut(member).insert(MemberOf(routed));
+        world.entity_mut(fleeing).insert(RoutedFrom(routed));
+        tick(&mut app, 1.0);
+
+        kill(&mut app, last);
+        kill(&mut app, member);
+        tick(&mut app, 0.1);
+        let world = app.world();
+        assert!(world.get_entity(company).is_err(), "last member fell");
+        assert!(world.get_entity(parent).is_err(), "nothing left below it");
+        assert!(
+            world.get_entity(routed).is_ok(),
+            "a fleeing boid may still rally"
+        );
+
+        tick(&mut app, 0.1);
+        let world = app.world();
+        assert!(world.get::<Formation>(shooters).unwrap().tasks.is_empty());
+
+        kill(&mut app, fleeing);
+        tick(&mut app, 0.1);
+        assert!(app.world().get_entity(routed).is_err());
+    }
+}
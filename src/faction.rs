@@ -0,0 +1,59 @@
+use bevy::prelude::{Color, Component, Resource};
+
+/// Side a boid or formation fights for: an index into [`Factions`]. Boids
+/// and formations without one are on the first faction (`require`);
+/// formations are spawned with their members' (see
+/// [`organize_hierarchy`](crate::formations::organize_hierarchy)).
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Faction(pub u8);
+
+/// Name and colour of a faction.
+#[derive(Clone, Debug)]
+pub struct FactionInfo {
+    pub name: String,
+    pub color: Color,
+}
+
+/// Registry of the factions in play, indexed by [`Faction`], and which of
+/// them the local player commands. Selection and orders are restricted to
+/// the local faction.
+#[derive(Resource, Clone, Debug)]
+pub struct Factions {
+    pub factions: Vec<FactionInfo>,
+    pub local: Faction,
+}
+
+impl Default for Factions {
+    fn default() -> Self {
+        Self {
+            factions: vec![
+                FactionInfo {
+                    name: "Blue".to_string(),
+                    color: Color::srgb(0.25, 0.45, 0.9),
+                },
+                FactionInfo {
+                    name: "Red".to_string(),
+                    color: Color::srgb(0.85, 0.25, 0.2),
+                },
+            ],
+            local: Faction(0),
+        }
+    }
+}
+
+impl Factions {
+    pub fn get(&self, faction: Faction) -> Option<&FactionInfo> {
+        self.factions.get(faction.0 as usize)
+    }
+
+    /// Colour of `faction`; grey for one not in the registry.
+    pub fn color(&self, faction: Faction) -> Color {
+        self.get(faction)
+            .map_or(Color::srgb(0.5, 0.5, 0.5), |info| info.color)
+    }
+
+    /// `faction` is the local player's.
+    pub fn is_local(&self, faction: Faction) -> bool {
+        faction == self.local
+    }
+}
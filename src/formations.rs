@@ -1,7 +1,12 @@
-use crate::kinematics::Velocity;
+use crate::boid::UnitStats;
+use crate::combat::Volley;
+use crate::faction::{Faction, Factions};
+use crate::kinematics::{NNTree, TrackedByTree, Velocity};
+use crate::morale::Morale;
 use crate::target::Target;
+use crate::vision::{FogOfWar, VIEW_RANGE};
 use bevy::prelude::*;
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
 
 /// A maneuver a formation executes, one at a time, front of the queue first.
 /// Player/ai code only *enqueues* tasks; the [`process_formation_orders`] system
@@ -12,9 +17,14 @@ pub enum FormationOrder {
     /// On start, if `facing_dir` differs from the current facing, members are
     /// re-mapped to slots in the new frame (symmetric formations re-orient
     /// without moving: different slot, same position). Finished when the
-    /// center of mass arrives within [`ARRIVE_TOLERANCE`] of `pos`.
-    Move { pos: Vec3, facing_dir: Vec3 },
-    /// Re-fill slots from current member positions (after a kind/column
+    /// center of mass arrives within [`ARRIVE_TOLERANCE`] of `pos`. On start
+    /// the formation also takes up `pace` (see [`Formation::pace`]).
+    Move {
+        pos: Vec3,
+        facing_dir: Vec3,
+        pace: Pace,
+    },
+    /// Re-fill slots from current member positions (after a kind/file
     /// change, or when a boid died or left). Finished once every member has
     /// a valid slot.
     Reform,
@@ -25,7 +35,43 @@ pub enum FormationOrder {
     /// the individual units can just change direction or after a formation changes.
     Rotate { to: Vec3 },
     /// Hold position. This is the default order that doesn't get removed.
-    Hold { pos: Vec3, facing_dir: Vec3 }
+    Hold { pos: Vec3, facing_dir: Vec3 },
+    /// Take open or close order. Members keep their slots and step out to
+    /// (or in from) the new intervals; finishes at once.
+    Intervals(Intervals),
+    /// Turn round in place: the facing reverses, everyone keeps their slot
+    /// and [`RankRole`], so the front rank now stands at the rear (see
+    /// [`Formation::faced_about`]). Finishes at once. Sub-formations face
+    /// about in turn.
+    AboutFace,
+    /// Reverse facing while keeping whoever is in front in front: members
+    /// keep their slots, the slot frame turns half round about the origin,
+    /// and the files pass through each other to their reflected places.
+    /// Finishes at once; members are still walking when the next order
+    /// starts. A container stays put and has its sub-formations
+    /// countermarch instead.
+    Countermarch,
+    /// Stand and shoot at `target` (a formation or a boid): the front ranks'
+    /// [`RangedAttack`](crate::combat::RangedAttack) members fire in volleys,
+    /// rank by rank (see [`ranged_fire_system`](crate::combat::ranged_fire_system)).
+    /// Lasts until the target is gone or another order replaces it. A
+    /// container hands the order to its sub-formations.
+    FireAt { target: Entity },
+    /// Call back the boids that fled when the formation routed (see
+    /// [`rally_system`](crate::morale::rally_system)): each rejoins once its
+    /// morale has recovered enough. Held until none is left fleeing. A
+    /// container hands the order to its sub-formations.
+    Rally,
+    /// Charge `target` (a formation or a boid): march on it, facing
+    /// `facing_dir`, at a pace that quickens as it closes, with `pos`
+    /// following the target (see [`charge_system`](crate::horse::charge_system)).
+    /// Finished like a [`FormationOrder::Move`] on reaching it, or once the
+    /// target is gone.
+    Charge {
+        target: Entity,
+        pos: Vec3,
+        facing_dir: Vec3,
+    },
 }
 
 /// A formation groups boids (and possibly sub-formations) and assigns each
@@ -34,21 +80,35 @@ pub enum FormationOrder {
 /// The origin is stored as the entity's [`Transform`]; the desired origin (used
 /// when this formation is itself a member of a parent formation) in [`Target`].
 #[derive(Component)]
-#[require(NeedsSpeedInit, Target)]
+#[require(
+    NeedsSpeedInit,
+    Target,
+    Cohesion,
+    PaceLog,
+    Lead,
+    Faction,
+    Volley,
+    Morale
+)]
 pub struct Formation {
     /// Maps member index -> desired position relative to the formation origin.
     /// Intended to become player-defined, with maneuvers transitioning
     /// between kinds (e.g. blending offsets over time).
     pub kind: FormationKind,
-    /// Column override for [`FormationKind::Grid`]: when set, the grid lays
-    /// out `columns` wide regardless of member count (rows grow instead).
-    /// Set by Ctrl+RMB frontage designation to fit the formation to the
-    /// dragged frontage width.
-    pub columns: Option<usize>,
-    /// Side length of a square (centered on the origin) that encloses all
-    /// member slots of the current kind/member count. Maintained by
+    /// Files, ranks and interval the kind is drawn up with.
+    pub params: FormationParams,
+    /// The layout is faced about: its front rank stands at the rear of
+    /// `dir`. Set by [`FormationOrder::AboutFace`], so a formation turns
+    /// round without anyone changing slot or [`RankRole`]; a reform dresses
+    /// the layout the right way round again.
+    pub faced_about: bool,
+    /// Oriented rectangle enclosing all member slots of the current
+    /// kind/params/member count, in the slot frame. Maintained by
     /// [`propagate_formation_targets`].
-    pub extent: f32,
+    pub footprint: Footprint,
+    /// Layout `footprint` was measured for (kind, params, member count,
+    /// faced about): it is only measured again once that changes.
+    pub(crate) footprint_for: Option<(FormationKind, FormationParams, usize, bool)>,
     /// Where members face (per frontage designation).
     pub dir: Vec3,
     /// The formation's maximum movement speed: the slowest member's max
@@ -56,6 +116,11 @@ pub struct Formation {
     /// [`init_formation_speed`] shortly after creation - creation sites
     /// never set it by hand. Drives the intermediate-goal lead distance.
     pub max_speed: f32,
+    /// Current marching pace: a fraction of `max_speed` that scales both the
+    /// lead distance and the members' speed cap. Taken from each
+    /// [`FormationOrder::Move`] as it starts and kept afterwards, so dressing
+    /// and holding happen at the last ordered pace.
+    pub pace: Pace,
     /// Pending maneuvers, executed front-to-back; an empty queue means
     /// plain marching.
     pub tasks: VecDeque<FormationOrder>,
@@ -68,29 +133,399 @@ pub struct Formation {
 #[derive(Component, Default)]
 pub struct NeedsSpeedInit;
 
+/// How well the loaded members of a formation keep their slots, measured
+/// against the slot frame at the formation origin (the center of mass) by
+/// [`measure_cohesion`]. A freshly spawned formation reads as perfectly
+/// cohesive until the first measurement.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct Cohesion {
+    /// Root-mean-square distance of members from their slots.
+    pub rms_error: f32,
+    /// Distance of the worst straggler from its slot.
+    pub max_straggler: f32,
+    /// Fraction of members within [`SLOT_TOLERANCE`] of their slot.
+    pub in_place: f32,
+    /// Seconds until the next automatic re-dress may be ordered.
+    redress_cooldown: f32,
+}
+
+impl Default for Cohesion {
+    fn default() -> Self {
+        Self {
+            rms_error: 0.0,
+            max_straggler: 0.0,
+            in_place: 1.0,
+            redress_cooldown: 0.0,
+        }
+    }
+}
+
+impl Cohesion {
+    /// Lead distance multiplier for a marching formation: full lead while
+    /// members keep their slots, shrinking linearly with the RMS slot error
+    /// down to [`MIN_COHESION_LEAD_FACTOR`] at [`COHESION_STALL_RMS`], so a
+    /// scattered formation slows down and lets stragglers catch up.
+    pub fn lead_factor(&self) -> f32 {
+        (1.0 - self.rms_error / COHESION_STALL_RMS).clamp(MIN_COHESION_LEAD_FACTOR, 1.0)
+    }
+
+    /// Too few members in place to call it a formation.
+    pub fn is_poor(&self) -> bool {
+        self.in_place < REDRESS_IN_PLACE
+    }
+}
+
 impl Default for Formation {
     fn default() -> Self {
         Self {
             kind: FormationKind::default(),
-            columns: None,
-            extent: FormationKind::SPACING,
+            params: FormationParams::default(),
+            faced_about: false,
+            footprint: Footprint::default(),
+            footprint_for: None,
             dir: Vec3::ZERO,
             max_speed: crate::kinematics::MAX_VELOCITY,
+            pace: Pace::default(),
             tasks: VecDeque::new(),
         }
     }
 }
 
 impl Formation {
-    /// Slot offset honoring the column override (Grid only).
+    /// Slot offset of the kind drawn up with this formation's params (half
+    /// turned when [faced about](Self::faced_about)).
     pub fn slot_offset(&self, index: usize, total: usize) -> Vec3 {
-        self.kind.offset_with_cols(index, total, self.columns)
+        self.about(self.kind.offset(index, total, &self.params))
+    }
+
+    /// Slot footprint of the kind drawn up with this formation's params.
+    pub fn slot_footprint(&self, total: usize) -> Footprint {
+        let footprint = self.kind.footprint(total, &self.params);
+        Footprint {
+            center: self.about(footprint.center),
+            ..footprint
+        }
+    }
+
+    /// Slot facing (in the slot frame) of the kind drawn up with this
+    /// formation's params.
+    pub fn slot_facing(&self, index: usize, total: usize) -> Vec3 {
+        self.about(self.kind.facing(index, total, &self.params))
+    }
+
+    /// Layout-frame vector as placed in the slot frame.
+    fn about(&self, v: Vec3) -> Vec3 {
+        if self.faced_about { -v } else { v }
+    }
+
+    /// Turning to face `facing` turns the formation round (more than
+    /// [`REVERSAL_ANGLE`]): a job for [`FormationOrder::AboutFace`] or
+    /// [`FormationOrder::Countermarch`] rather than a wheel or re-map.
+    pub fn reverses(&self, facing: Vec3) -> bool {
+        let (from, to) = (self.dir.normalize_or_zero(), facing.normalize_or_zero());
+        from != Vec3::ZERO && to != Vec3::ZERO && from.angle_between(to) > REVERSAL_ANGLE
+    }
+
+    /// Turning to face `facing` is no sharper than what a reversal leaves
+    /// over (the complement of [`REVERSAL_ANGLE`]): the slot frame wheels
+    /// round and everyone keeps their slot, as after an
+    /// [`FormationOrder::AboutFace`] followed by a `Move` to a facing that is
+    /// not quite opposite.
+    pub fn wheels(&self, facing: Vec3) -> bool {
+        let (from, to) = (self.dir.normalize_or_zero(), facing.normalize_or_zero());
+        from != Vec3::ZERO
+            && to != Vec3::ZERO
+            && from.angle_between(to) < std::f32::consts::PI - REVERSAL_ANGLE
+    }
+
+    /// [`RankRole`] of every slot, indexed by slot, for `total` members.
+    /// Roles follow the layout, not the facing: a formation faced about
+    /// keeps its front rank's roles on the men now at its rear.
+    pub fn rank_roles(&self, total: usize) -> Vec<RankRole> {
+        let officer = self.officer_slot(total);
+        (0..total)
+            .map(|i| {
+                let (rank, ranks) = self.kind.rank(i, total, &self.params);
+                RankRole::of(rank, ranks, Some(i) == officer)
+            })
+            .collect()
+    }
+
+    /// [`RankRole`] of slot `index` alone, as in [`Self::rank_roles`];
+    /// `None` past the last of `total` slots. The officer's post is only
+    /// looked for when `index` is in the front rank.
+    pub fn rank_role(&self, index: usize, total: usize) -> Option<RankRole> {
+        if index >= total {
+            return None;
+        }
+        let (rank, ranks) = self.kind.rank(index, total, &self.params);
+        let officer = rank == 0 && self.officer_slot(total) == Some(index);
+        Some(RankRole::of(rank, ranks, officer))
+    }
+
+    /// The officer's post: right of the front rank, foremost first.
+    fn officer_slot(&self, total: usize) -> Option<usize> {
+        (0..total)
+            .filter(|&i| self.kind.rank(i, total, &self.params).0 == 0)
+            .map(|i| (i, self.kind.offset(i, total, &self.params)))
+            .max_by(|(_, a), (_, b)| a.z.total_cmp(&b.z).then(a.x.total_cmp(&b.x)))
+            .map(|(i, _)| i)
+    }
+
+    /// Rank of slot `index` counted from the rank actually facing the front
+    /// (0 = front), and the number of ranks. Unlike [`Self::rank_roles`]
+    /// this follows the facing: faced about, the layout's rear rank is in
+    /// front.
+    pub fn rank_from_front(&self, index: usize, total: usize) -> (usize, usize) {
+        let (rank, ranks) = self.kind.rank(index, total, &self.params);
+        if self.faced_about {
+            (ranks - 1 - rank, ranks)
+        } else {
+            (rank, ranks)
+        }
+    }
+
+    /// Marching speed at the current pace.
+    pub fn pace_speed(&self) -> f32 {
+        self.max_speed * self.pace.speed_factor()
+    }
+}
+
+/// Turns sharper than this (radians) reverse a formation (see
+/// [`Formation::reverses`]).
+pub const REVERSAL_ANGLE: f32 = 3.0 * std::f32::consts::FRAC_PI_4;
+
+/// Formations at least this many ranks deep post file closers in their
+/// rear rank.
+pub const FILE_CLOSER_MIN_RANKS: usize = 3;
+
+/// Post of a slot in the formation's ranks (see [`Formation::rank_roles`]).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RankRole {
+    /// Right of the front rank.
+    Officer,
+    /// The rest of the front rank.
+    FrontRank,
+    /// Any rank between the front rank and the file closers.
+    Rank,
+    /// The rear rank of a formation at least [`FILE_CLOSER_MIN_RANKS`]
+    /// deep, keeping the files closed up.
+    FileCloser,
+}
+
+impl RankRole {
+    /// Role of a slot in `rank` (0 = front) of `ranks`, unless it holds the
+    /// officer's post.
+    fn of(rank: usize, ranks: usize, officer: bool) -> Self {
+        if officer {
+            RankRole::Officer
+        } else if rank == 0 {
+            RankRole::FrontRank
+        } else if ranks >= FILE_CLOSER_MIN_RANKS && rank == ranks - 1 {
+            RankRole::FileCloser
+        } else {
+            RankRole::Rank
+        }
+    }
+}
+
+/// Ground-plane rectangle occupied by a formation, in its slot frame
+/// (X = right, Z = forward): the bounding box of the slot offsets, padded by
+/// half an interval on every side so a single member covers one interval
+/// square. The formation origin is the slot centroid, which for asymmetric
+/// kinds (a [`FormationKind::Wedge`] is heavier toward its broad rank) is
+/// not the middle of the rectangle - hence `center`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Footprint {
+    /// Frontage: size across the facing.
+    pub width: f32,
+    /// Size from front to rear rank.
+    pub depth: f32,
+    /// Middle of the rectangle relative to the formation origin, in the
+    /// slot frame.
+    pub center: Vec3,
+}
+
+impl Default for Footprint {
+    /// One member's worth of ground.
+    fn default() -> Self {
+        Self {
+            width: FormationKind::SPACING,
+            depth: FormationKind::SPACING,
+            center: Vec3::ZERO,
+        }
+    }
+}
+
+impl Footprint {
+    /// The same rectangle grown by `margin` on every side.
+    pub fn inflated(self, margin: f32) -> Self {
+        Self {
+            width: self.width + 2.0 * margin,
+            depth: self.depth + 2.0 * margin,
+            ..self
+        }
+    }
+
+    /// World position of the rectangle's middle for a formation whose
+    /// origin is at `origin`, slot frame rotated by `rotation`.
+    pub fn world_center(&self, origin: Vec3, rotation: Quat) -> Vec3 {
+        origin + rotation * self.center
+    }
+
+    /// Distance from the formation origin to the rectangle's farthest
+    /// corner, whatever the rotation.
+    pub fn radius(&self) -> f32 {
+        self.center.length() + Vec2::new(self.width, self.depth).length() / 2.0
     }
 
-    /// Enclosing-square side honoring the column override (Grid only).
-    pub fn slot_extent(&self, total: usize) -> f32 {
-        self.kind.extent_with_cols(total, self.columns)
+    /// The rectangle at `origin`/`rotation` covers `point` (ground plane;
+    /// height is ignored).
+    pub fn contains(&self, origin: Vec3, rotation: Quat, point: Vec3) -> bool {
+        let local = rotation.inverse() * (point - origin) - self.center;
+        local.x.abs() <= self.width / 2.0 && local.z.abs() <= self.depth / 2.0
     }
+
+    /// Where the ground-plane segment `from`-`to` first enters the
+    /// rectangle at `origin`/`rotation`, as a fraction along it: 0 when
+    /// `from` is inside, `None` when the segment misses it.
+    pub fn segment_entry(&self, origin: Vec3, rotation: Quat, from: Vec3, to: Vec3) -> Option<f32> {
+        let inverse = rotation.inverse();
+        let start = inverse * (from - origin) - self.center;
+        let delta = inverse * (to - from);
+        let (mut enter, mut exit) = (0.0f32, 1.0f32);
+        for (start, delta, half) in [
+            (start.x, delta.x, self.width / 2.0),
+            (start.z, delta.z, self.depth / 2.0),
+        ] {
+            if delta.abs() < 1e-6 {
+                if start.abs() > half {
+                    return None;
+                }
+                continue;
+            }
+            let (a, b) = ((-half - start) / delta, (half - start) / delta);
+            enter = enter.max(a.min(b));
+            exit = exit.min(a.max(b));
+        }
+        (enter <= exit).then_some(enter)
+    }
+
+    /// Minimum ground-plane translation that moves this footprint (at
+    /// `origin`/`rotation`) out of `other`, or `None` if they do not
+    /// overlap. Separating-axis test over the four edge normals of the two
+    /// rectangles.
+    pub fn penetration(
+        &self,
+        origin: Vec3,
+        rotation: Quat,
+        other: &Footprint,
+        other_origin: Vec3,
+        other_rotation: Quat,
+    ) -> Option<Vec3> {
+        let between =
+            other.world_center(other_origin, other_rotation) - self.world_center(origin, rotation);
+        let between = Vec3::new(between.x, 0.0, between.z);
+        let axes = [
+            rotation * Vec3::X,
+            rotation * Vec3::Z,
+            other_rotation * Vec3::X,
+            other_rotation * Vec3::Z,
+        ];
+        let radius = |footprint: &Footprint, rotation: Quat, axis: Vec3| {
+            footprint.width / 2.0 * (rotation * Vec3::X).dot(axis).abs()
+                + footprint.depth / 2.0 * (rotation * Vec3::Z).dot(axis).abs()
+        };
+        let mut best: Option<Vec3> = None;
+        let mut best_depth = f32::INFINITY;
+        for axis in axes {
+            let distance = between.dot(axis);
+            let depth =
+                radius(self, rotation, axis) + radius(other, other_rotation, axis) - distance.abs();
+            if depth <= 0.0 {
+                return None;
+            }
+            if depth < best_depth {
+                best_depth = depth;
+                // Away from the other rectangle.
+                best = Some(-axis * depth * if distance < 0.0 { -1.0 } else { 1.0 });
+            }
+        }
+        best
+    }
+}
+
+/// Marching pace, from a parade walk to an all-out charge. Formations move
+/// at full speed until ordered to a slower pace, so the default is the
+/// full-speed [`Pace::March`]. A [`Pace::Charge`] is no faster, but is
+/// logged apart in [`PaceLog`]: it is what tires men most.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub enum Pace {
+    Walk,
+    QuickMarch,
+    DoubleQuick,
+    #[default]
+    March,
+    Charge,
+}
+
+impl Pace {
+    pub const ALL: [Pace; 5] = [
+        Pace::Walk,
+        Pace::QuickMarch,
+        Pace::DoubleQuick,
+        Pace::March,
+        Pace::Charge,
+    ];
+
+    /// Fraction of [`Formation::max_speed`] this pace moves at.
+    pub fn speed_factor(self) -> f32 {
+        match self {
+            Pace::Walk => 0.3,
+            Pace::QuickMarch => 0.5,
+            Pace::DoubleQuick => 0.75,
+            Pace::March | Pace::Charge => 1.0,
+        }
+    }
+}
+
+/// Where [`process_formation_orders`] last steered the formation from and
+/// to: its center of mass and the intermediate goal (the lead point) member
+/// targets propagate from. The two coincide while it holds. Kept for
+/// display only; nothing steers by it.
+#[derive(Component, Default, Debug, Clone, Copy)]
+pub struct Lead {
+    pub center_of_mass: Vec3,
+    pub goal: Vec3,
+}
+
+/// Seconds a formation has spent marching at each [`Pace`] (indexed by
+/// `Pace as usize`) and standing still. Only accumulated, never consumed
+/// yet: this is the input a fatigue model will drain from.
+#[derive(Component, Default, Debug)]
+pub struct PaceLog {
+    pub marching: [f32; Pace::ALL.len()],
+    pub halted: f32,
+}
+
+impl PaceLog {
+    pub fn time_at(&self, pace: Pace) -> f32 {
+        self.marching[pace as usize]
+    }
+}
+
+/// Road-march mode: while a [`FormationOrder::Move`] is active, only the
+/// front rank steers toward the formation goal; everyone else follows the
+/// path the front actually walked, at their slot's depth behind it, instead
+/// of translating a rigid slot frame (see [`follow_road`]). A facing change
+/// wheels the formation along that path rather than re-mapping slots.
+/// Meant for [`FormationKind::Column`], but works for any kind. Presence is
+/// the mode.
+#[derive(Component, Default, Debug)]
+pub struct RoadMarch {
+    /// Breadcrumbs of the front rank's center, newest first, spaced at least
+    /// [`BREADCRUMB_SPACING`] apart. Cleared whenever the formation halts.
+    trail: VecDeque<Vec3>,
 }
 
 /// Slot identity of a boid within its formation: member `FormationSlot(i)`
@@ -100,7 +535,7 @@ impl Formation {
 ///
 /// Persistence is deliberate: re-deriving slots every frame would reshuffle
 /// members (jitter), so slots only change when the current assignment is
-/// invalidated (membership, kind/column, or facing change). Because a slot is
+/// invalidated (membership, kind/file, or facing change). Because a slot is
 /// meaningless without membership, every detach path must remove this
 /// component together with `MemberOf` - a stale slot passes the validity
 /// check in [`assign_slots`] and pins the member to an arbitrary slot in its
@@ -137,7 +572,7 @@ pub struct FormationOf(pub Entity);
 /// either way, and containers propagate down whatever they are. The split
 /// remains because boids and sub-formations consume different mechanics:
 /// boids hold [`FormationSlot`]s and receive `Target`s, sub-formations have
-/// their own kind/extent and receive injected orders.
+/// their own kind/footprint and receive injected orders.
 /// todo: give sub-formations slots too (replacing the positional
 /// `members.len() + i` indexing in `process_formation_orders`), then merge
 /// `MemberOf`/`FormationOf` into one relationship - with dispatch
@@ -146,25 +581,135 @@ pub struct FormationOf(pub Entity);
 #[relationship_target(relationship = FormationOf)]
 pub struct Formations(Vec<Entity>);
 
-/// Quick-command-group slot (RTS hotkey groups 1-6).
+/// Quick command group numbers (RTS hotkey groups on the digit row).
+pub const QUICK_GROUPS: std::ops::RangeInclusive<u8> = 0..=9;
+
+/// Quick command group a formation belongs to (see [`QUICK_GROUPS`]). A
+/// group is every formation carrying its number, so it can hold several.
 #[derive(Component)]
 pub struct QuickCommandGroup(pub u8);
 
 /// Simple built-in formation functions. X = right, Z = forward, on the ground
-/// plane; the formation origin is at the centroid of its slots.
+/// plane; the formation origin is at the centroid of its slots. Kinds drawn
+/// up in files and ranks (see [`FormationKind::has_files`]) take their
+/// frontage and depth from [`FormationParams`]; all kinds take its interval.
 #[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
 pub enum FormationKind {
+    /// One rank by default; two or three via [`FormationParams::ranks`].
     Line,
+    /// One file by default; a column of fours via [`FormationParams::files`].
     Column,
     #[default]
     Grid,
     Wedge,
     Ring,
+    /// Infantry square: [`FormationParams::ranks`] deep (two by default) on
+    /// all four faces around an empty center, every face looking outward.
+    HollowSquare,
+    /// Quincunx: each rank fills every other file, the next rank covering
+    /// the gaps of the one ahead.
+    Checkerboard,
+    /// A line whose files fall back [`ECHELON_STEP`] intervals each from the
+    /// left, so the left flank leads.
+    EchelonLeft,
+    /// Mirror of [`FormationKind::EchelonLeft`]: the right flank leads.
+    EchelonRight,
+    /// Skirmish order: two loose ranks at [`SKIRMISH_LOOSENESS`] times the
+    /// interval, every slot jittered so nobody dresses into neat lines.
+    Skirmish,
+}
+
+/// Default depth of each face of a [`FormationKind::HollowSquare`].
+pub const HOLLOW_SQUARE_RANKS: usize = 2;
+
+/// How far each file of an echelon falls back behind its neighbour, in
+/// intervals.
+pub const ECHELON_STEP: f32 = 0.5;
+
+/// Interval multiplier of [`FormationKind::Skirmish`].
+pub const SKIRMISH_LOOSENESS: f32 = 3.0;
+
+/// Largest displacement of a skirmisher from its loose grid point, as a
+/// fraction of the loose interval.
+pub const SKIRMISH_JITTER: f32 = 0.3;
+
+/// Interval multiplier of [`Intervals::Open`].
+pub const OPEN_ORDER_FACTOR: f32 = 2.0;
+
+/// Close or open order (see [`FormationOrder::Intervals`]).
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub enum Intervals {
+    /// Shoulder to shoulder, at the base interval.
+    #[default]
+    Close,
+    /// [`OPEN_ORDER_FACTOR`] times the base interval, for broken ground or
+    /// to thin out a target for enemy fire.
+    Open,
+}
+
+impl Intervals {
+    pub fn factor(self) -> f32 {
+        match self {
+            Intervals::Close => 1.0,
+            Intervals::Open => OPEN_ORDER_FACTOR,
+        }
+    }
+}
+
+/// Layout parameters shared by all [`FormationKind`]s. Files and ranks only
+/// apply to kinds drawn up in them; everyone uses the interval.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FormationParams {
+    /// Members abreast. Wins over `ranks` when both are set; with neither,
+    /// the kind's default frontage applies. Set by Ctrl+RMB frontage
+    /// designation to fit the formation to the dragged width.
+    pub files: Option<usize>,
+    /// Members deep (per face, for a hollow square).
+    pub ranks: Option<usize>,
+    /// Close-order distance between neighbouring slots.
+    /// [`FormationKind::SPACING`] suits boids; a formation of sub-formations
+    /// spaces its slots by the sub-formations' footprint instead (see
+    /// [`organize_hierarchy`]).
+    pub interval: f32,
+    /// Close or open order; the interval is scaled accordingly.
+    pub intervals: Intervals,
+}
+
+impl Default for FormationParams {
+    fn default() -> Self {
+        Self {
+            files: None,
+            ranks: None,
+            interval: FormationKind::SPACING,
+            intervals: Intervals::Close,
+        }
+    }
+}
+
+impl FormationParams {
+    /// Distance between neighbouring slots in the current order.
+    pub fn pitch(&self) -> f32 {
+        self.interval * self.intervals.factor()
+    }
 }
 
 impl FormationKind {
+    /// Default close-order interval between boid slots.
     pub const SPACING: f32 = 2.0;
 
+    pub const ALL: [FormationKind; 10] = [
+        FormationKind::Line,
+        FormationKind::Column,
+        FormationKind::Grid,
+        FormationKind::Wedge,
+        FormationKind::Ring,
+        FormationKind::HollowSquare,
+        FormationKind::Checkerboard,
+        FormationKind::EchelonLeft,
+        FormationKind::EchelonRight,
+        FormationKind::Skirmish,
+    ];
+
     /// Number of wedge rows needed for `total` members (rows of 1, 2, 3, ...).
     fn wedge_rows(total: usize) -> usize {
         let mut rows = 1;
@@ -174,73 +719,152 @@ impl FormationKind {
         rows
     }
 
-    /// Grid column count for `total` members: the override when set (Ctrl+RMB
-    /// width fitting), else the near-square default.
-    fn grid_cols(&self, total: usize, cols: Option<usize>) -> usize {
-        cols.unwrap_or_else(|| (total as f32).sqrt().ceil().max(1.0) as usize)
+    /// Mean row index over all `total` wedge slots (the last row may be
+    /// partial): the wedge's centroid depth in row units.
+    fn wedge_mean_row(total: usize) -> f32 {
+        let mut remaining = total;
+        let mut sum = 0;
+        for row in 0..Self::wedge_rows(total) {
+            let in_row = (row + 1).min(remaining);
+            sum += row * in_row;
+            remaining -= in_row;
+        }
+        sum as f32 / total.max(1) as f32
+    }
+
+    /// Smallest outer face length of a hollow square holding `total` in
+    /// `ranks` concentric rings. A square of side `s` holds `4 * (s - 1)` on
+    /// its outer ring and 8 fewer on each ring within, `4 * ranks * (s -
+    /// ranks)` in all - or `s * s` once the rings meet in the middle.
+    fn square_side(total: usize, ranks: usize) -> usize {
+        let ranks = ranks.max(1);
+        if total <= 4 * ranks * ranks {
+            let side = total.isqrt();
+            (if side * side < total { side + 1 } else { side }).max(1)
+        } else {
+            ranks + total.div_ceil(4 * ranks)
+        }
+    }
+
+    /// Whether this kind is drawn up in files and ranks, honoring
+    /// [`FormationParams::files`] and [`FormationParams::ranks`].
+    pub fn has_files(&self) -> bool {
+        !matches!(
+            self,
+            FormationKind::Wedge | FormationKind::Ring | FormationKind::HollowSquare
+        )
+    }
+
+    /// Files for `total` members: the explicit file count, else derived
+    /// from the rank count, else the kind's default frontage.
+    pub fn files(&self, total: usize, params: &FormationParams) -> usize {
+        params
+            .files
+            .or_else(|| params.ranks.map(|ranks| total.div_ceil(ranks.max(1))))
+            .unwrap_or_else(|| match self {
+                FormationKind::Column => 1,
+                FormationKind::Grid | FormationKind::Checkerboard => {
+                    (total as f32).sqrt().ceil() as usize
+                }
+                FormationKind::Skirmish => total.div_ceil(2),
+                _ => total,
+            })
             .max(1)
     }
 
-    /// Side of the square (centered on the formation origin) that encloses
-    /// all member slots for this kind and member count.
-    pub fn extent(&self, total: usize) -> f32 {
-        self.extent_with_cols(total, None)
+    /// Lateral distance between neighbouring files: what Ctrl+RMB width
+    /// fitting divides the frontage by.
+    pub fn file_pitch(&self, params: &FormationParams) -> f32 {
+        let pitch = params.pitch();
+        match self {
+            FormationKind::Checkerboard => 2.0 * pitch,
+            FormationKind::Skirmish => SKIRMISH_LOOSENESS * pitch,
+            _ => pitch,
+        }
     }
 
-    /// [`extent`](Self::extent) honoring a Grid column override.
-    pub fn extent_with_cols(&self, total: usize, cols: Option<usize>) -> f32 {
-        const S: f32 = FormationKind::SPACING;
+    /// Rectangle enclosing all member slots for this kind and member count
+    /// (see [`Footprint`]). Closed-form for the regular kinds, so it stays
+    /// cheap for every formation every tick; the irregular ones measure
+    /// their slots.
+    pub fn footprint(&self, total: usize, params: &FormationParams) -> Footprint {
+        let pitch = params.pitch();
         if total == 0 {
-            return S;
-        }
-        let side = match self {
-            FormationKind::Line | FormationKind::Column => (total.saturating_sub(1)) as f32 * S,
-            FormationKind::Grid => {
-                let cols = self.grid_cols(total, cols);
-                let rows = total.div_ceil(cols);
-                ((cols - 1) as f32 * S).max((rows - 1) as f32 * S)
+            return Footprint {
+                width: pitch,
+                depth: pitch,
+                center: Vec3::ZERO,
+            };
+        }
+        // Slot span (center to center) across and along the facing, and the
+        // middle of that span relative to the origin.
+        let (span_x, span_z, center) = match self {
+            FormationKind::Line | FormationKind::Column | FormationKind::Grid => {
+                let files = self.files(total, params);
+                let ranks = total.div_ceil(files);
+                // A file count above the member count leaves the right-hand
+                // files empty.
+                let used = files.min(total);
+                let center_x = -((files - used) as f32) * pitch / 2.0;
+                (
+                    (used - 1) as f32 * pitch,
+                    (ranks - 1) as f32 * pitch,
+                    Vec3::new(center_x, 0.0, 0.0),
+                )
             }
             FormationKind::Wedge => {
-                // Last (widest) row has `rows` members, rows extend forward.
                 let rows = Self::wedge_rows(total);
-                (rows - 1) as f32 * S
+                let last_row = total - (rows - 1) * rows / 2;
+                let widest = last_row.max(rows - 1).max(1);
+                let center_z = ((rows - 1) as f32 / 2.0 - Self::wedge_mean_row(total)) * pitch;
+                (
+                    (widest - 1) as f32 * pitch,
+                    (rows - 1) as f32 * pitch,
+                    Vec3::new(0.0, 0.0, center_z),
+                )
+            }
+            FormationKind::Ring => {
+                let diameter = 2.0 * (total as f32 * pitch / std::f32::consts::TAU).max(pitch);
+                (diameter, diameter, Vec3::ZERO)
+            }
+            FormationKind::HollowSquare
+            | FormationKind::Checkerboard
+            | FormationKind::EchelonLeft
+            | FormationKind::EchelonRight
+            | FormationKind::Skirmish => {
+                let (min, max) = (0..total)
+                    .map(|i| self.offset(i, total, params))
+                    .fold((Vec3::MAX, Vec3::MIN), |(min, max), o| {
+                        (min.min(o), max.max(o))
+                    });
+                (max.x - min.x, max.z - min.z, (min + max) / 2.0)
             }
-            FormationKind::Ring => 2.0 * (total as f32 * S / std::f32::consts::TAU).max(S),
         };
-        side.max(S)
+        Footprint {
+            width: span_x + pitch,
+            depth: span_z + pitch,
+            center,
+        }
     }
 
     /// Desired position of the member with `index` (out of `total` members,
     /// counting both boids and sub-formations) relative to the formation origin.
-    pub fn offset(&self, index: usize, total: usize) -> Vec3 {
-        self.offset_with_cols(index, total, None)
-    }
-
-    /// [`offset`](Self::offset) honoring a Grid column override.
-    pub fn offset_with_cols(&self, index: usize, total: usize, cols: Option<usize>) -> Vec3 {
-        const S: f32 = FormationKind::SPACING;
+    pub fn offset(&self, index: usize, total: usize, params: &FormationParams) -> Vec3 {
+        let pitch = params.pitch();
+        // Files and ranks, rank 0 at the rear; the last rank may be partial.
+        let files = self.files(total, params);
+        let ranks = total.div_ceil(files).max(1);
+        let (rank, file) = (index / files, index % files);
+        let file_x = |pitch: f32| file as f32 * pitch - (files - 1) as f32 * pitch / 2.0;
+        let rank_z = |pitch: f32| rank as f32 * pitch - (ranks - 1) as f32 * pitch / 2.0;
         match self {
-            FormationKind::Line => {
-                let c = (total.saturating_sub(1)) as f32 * S / 2.0;
-                Vec3::new(index as f32 * S - c, 0.0, 0.0)
-            }
-            FormationKind::Column => {
-                let c = (total.saturating_sub(1)) as f32 * S / 2.0;
-                Vec3::new(0.0, 0.0, index as f32 * S - c)
-            }
-            FormationKind::Grid => {
-                let cols = self.grid_cols(total, cols);
-                let rows = total.div_ceil(cols);
-                let col = index % cols;
-                let row = index / cols;
-                Vec3::new(
-                    col as f32 * S - (cols - 1) as f32 * S / 2.0,
-                    0.0,
-                    row as f32 * S - (rows - 1) as f32 * S / 2.0,
-                )
+            FormationKind::Line | FormationKind::Column | FormationKind::Grid => {
+                Vec3::new(file_x(pitch), 0.0, rank_z(pitch))
             }
             FormationKind::Wedge => {
-                // Rows of 1, 2, 3, ... members, apex pointing +Z.
+                // Rows of 1, 2, 3, ... members, apex pointing +Z. Rows are
+                // centered laterally; depth is measured from the centroid,
+                // so a holding wedge does not drift toward its broad end.
                 let mut row = 0;
                 let mut before = 0; // members in rows before `row`
                 while before + (row + 1) <= index {
@@ -248,19 +872,191 @@ impl FormationKind {
                     row += 1;
                 }
                 let in_row = index - before;
-                let row_len = row + 1;
-                let rows = Self::wedge_rows(total);
+                // A partial last row is centered too.
+                let row_len = (row + 1).min(total.saturating_sub(before)).max(1);
                 Vec3::new(
-                    in_row as f32 * S - (row_len - 1) as f32 * S / 2.0,
+                    in_row as f32 * pitch - (row_len - 1) as f32 * pitch / 2.0,
                     0.0,
-                    row as f32 * S - (rows - 1) as f32 * S / 2.0,
+                    (row as f32 - Self::wedge_mean_row(total)) * pitch,
                 )
             }
             FormationKind::Ring => {
-                let radius = (total as f32 * S / std::f32::consts::TAU).max(S);
+                let radius = (total as f32 * pitch / std::f32::consts::TAU).max(pitch);
                 let angle = index as f32 / total as f32 * std::f32::consts::TAU;
                 Vec3::new(angle.cos() * radius, 0.0, angle.sin() * radius)
             }
+            FormationKind::HollowSquare => {
+                let (_, half, face, along) = Self::square_slot(index, total, params);
+                let t = along * pitch;
+                let half = half * pitch;
+                match face {
+                    0 => Vec3::new(-half + t, 0.0, half),
+                    1 => Vec3::new(half, 0.0, half - t),
+                    2 => Vec3::new(half - t, 0.0, -half),
+                    _ => Vec3::new(-half, 0.0, -half + t),
+                }
+            }
+            FormationKind::Checkerboard => {
+                // Each rank takes every other file position; odd ranks shift
+                // by one to cover the gaps.
+                let stagger = usize::from(ranks > 1);
+                let x = (2 * file + rank % 2) as f32 * pitch
+                    - (2 * (files - 1) + stagger) as f32 * pitch / 2.0;
+                Vec3::new(x, 0.0, rank_z(pitch))
+            }
+            FormationKind::EchelonLeft | FormationKind::EchelonRight => {
+                let back = if *self == FormationKind::EchelonLeft {
+                    file
+                } else {
+                    files - 1 - file
+                };
+                let step = ECHELON_STEP * pitch;
+                let z = rank_z(pitch) - back as f32 * step + (files - 1) as f32 * step / 2.0;
+                Vec3::new(file_x(pitch), 0.0, z)
+            }
+            FormationKind::Skirmish => {
+                let loose = SKIRMISH_LOOSENESS * pitch;
+                Vec3::new(file_x(loose), 0.0, rank_z(loose))
+                    + jitter(index) * SKIRMISH_JITTER * loose
+            }
+        }
+    }
+
+    /// Hollow-square slot `index`: its ring (0 outermost), half the ring's
+    /// face length (in intervals), its face (0 front, 1 right, 2 rear, 3
+    /// left) and its distance along the face from the face's leading
+    /// corner. Slots are dealt round the four faces in turn, so a partly
+    /// filled ring still covers every face.
+    fn square_slot(
+        index: usize,
+        total: usize,
+        params: &FormationParams,
+    ) -> (usize, f32, usize, f32) {
+        let ranks = params.ranks.unwrap_or(HOLLOW_SQUARE_RANKS).max(1);
+        let side = Self::square_side(total, ranks);
+        let mut before = 0;
+        for ring in 0..ranks {
+            let Some(m) = side.checked_sub(2 * ring).filter(|&m| m > 0) else {
+                break;
+            };
+            let capacity = if m == 1 { 1 } else { 4 * (m - 1) };
+            if index < before + capacity {
+                let p = index - before;
+                let half = (m - 1) as f32 / 2.0;
+                return (ring, half, p % 4, (p / 4) as f32);
+            }
+            before += capacity;
+        }
+        (0, 0.0, 0, 0.0)
+    }
+
+    /// Direction the member in slot `index` faces, in the slot frame: the
+    /// formation's front, except on the flank and rear faces of a hollow
+    /// square, which look outward.
+    pub fn facing(&self, index: usize, total: usize, params: &FormationParams) -> Vec3 {
+        match self {
+            // A lone center slot counts as face 0 and looks to the front.
+            FormationKind::HollowSquare => match Self::square_slot(index, total, params).2 {
+                1 => Vec3::X,
+                2 => Vec3::NEG_Z,
+                3 => Vec3::NEG_X,
+                _ => Vec3::Z,
+            },
+            _ => Vec3::Z,
+        }
+    }
+
+    /// Rank of slot `index` counted from the layout's front (0 = front
+    /// rank), and the number of ranks. A hollow square's rings are its
+    /// ranks, the outermost in front; a ring is a single rank.
+    pub fn rank(&self, index: usize, total: usize, params: &FormationParams) -> (usize, usize) {
+        match self {
+            FormationKind::Wedge => {
+                // Row 0 (the apex) is at the rear.
+                let rows = Self::wedge_rows(total);
+                let mut row = 0;
+                let mut before = 0;
+                while before + (row + 1) <= index {
+                    before += row + 1;
+                    row += 1;
+                }
+                (rows - 1 - row.min(rows - 1), rows)
+            }
+            FormationKind::Ring => (0, 1),
+            FormationKind::HollowSquare => {
+                let ranks = params.ranks.unwrap_or(HOLLOW_SQUARE_RANKS).max(1);
+                let side = Self::square_side(total, ranks);
+                let rings = ranks.min(side.div_ceil(2));
+                (Self::square_slot(index, total, params).0, rings)
+            }
+            _ => {
+                // Rank 0 of the file layout is at the rear.
+                let files = self.files(total, params);
+                let ranks = total.div_ceil(files).max(1);
+                (ranks - 1 - (index / files).min(ranks - 1), ranks)
+            }
+        }
+    }
+}
+
+/// Deterministic per-slot scatter in [-1, 1] on both ground axes, so a
+/// skirmisher keeps the same spot in the loose order (splitmix64 hash).
+fn jitter(index: usize) -> Vec3 {
+    let mut h = (index as u64).wrapping_add(0x9E37_79B9_7F4A_7C15);
+    h = (h ^ (h >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    h = (h ^ (h >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    h ^= h >> 31;
+    let unit = |bits: u64| (bits & 0xFFFF) as f32 / 32767.5 - 1.0;
+    Vec3::new(unit(h), 0.0, unit(h >> 16))
+}
+
+/// How [`split_members`] divides a formation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SplitBy {
+    /// The left half of the files (slot columns, by lateral offset); whole
+    /// files stay together. A single-file formation cannot split this way.
+    Files,
+    /// This many members, lowest slots first (rear ranks of a grid).
+    Count(usize),
+}
+
+/// Members (with their slot, if assigned) to detach for a split of
+/// `formation`; `total` counts all members as in slot offset lookups.
+/// Unslotted members fall back to list order, as in
+/// [`process_formation_orders`]. At least one member always stays behind.
+pub fn split_members(
+    formation: &Formation,
+    members: &[(Entity, Option<usize>)],
+    total: usize,
+    by: SplitBy,
+) -> Vec<Entity> {
+    let mut by_slot: Vec<(Entity, usize)> = members
+        .iter()
+        .zip(0..)
+        .map(|(&(member, slot), fallback)| (member, slot.unwrap_or(fallback)))
+        .collect();
+    by_slot.sort_by_key(|&(_, slot)| slot);
+    match by {
+        SplitBy::Count(count) => by_slot
+            .iter()
+            .take(count.min(members.len().saturating_sub(1)))
+            .map(|&(member, _)| member)
+            .collect(),
+        SplitBy::Files => {
+            let file_x = |slot: usize| formation.slot_offset(slot, total).x;
+            let mut files: Vec<f32> = by_slot.iter().map(|&(_, slot)| file_x(slot)).collect();
+            files.sort_by(f32::total_cmp);
+            files.dedup_by(|a, b| (*a - *b).abs() < RANK_EPSILON);
+            if files.len() < 2 {
+                return Vec::new();
+            }
+            // Everything left of the middle file boundary splits off.
+            let boundary = files[files.len() / 2];
+            by_slot
+                .iter()
+                .filter(|&&(_, slot)| file_x(slot) < boundary - RANK_EPSILON)
+                .map(|&(member, _)| member)
+                .collect()
         }
     }
 }
@@ -272,20 +1068,292 @@ impl FormationKind {
 /// [`propagate_formation_targets`]): it integrates like a boid, receives
 /// its commands through the ordinary task queue, and executes them on
 /// itself; levels above propagate orders downward instead.
+///
+/// Which formations are unloaded is decided by [`lod_manager`] from the
+/// distance to the [`LodViewer`].
 #[derive(Resource, Debug)]
 pub struct LODGuard {
     /// Propagate parent formation targets to *direct* members only.
     /// Nested levels converge on later frames (each level re-emits to its own
     /// members), so disabling this cheaply freezes distant detail.
     pub propagate_targets: bool,
+    /// Formations farther than this from the viewer unload their boids.
+    pub unload_distance: f32,
+    /// Unloaded formations closer than this reload. Below `unload_distance`,
+    /// so a formation on the boundary does not flip every frame.
+    pub reload_distance: f32,
 }
 
 impl Default for LODGuard {
     fn default() -> Self {
         Self {
             propagate_targets: true,
+            unload_distance: 250.0,
+            reload_distance: 200.0,
+        }
+    }
+}
+
+/// Marker: the entity LOD distances are measured from (the player camera).
+#[derive(Component, Default)]
+pub struct LodViewer;
+
+/// Marker: [`lod_manager`] has unloaded this formation's boids. They keep
+/// existing with their `MemberOf`, [`FormationSlot`] and all other state,
+/// but are hidden and stripped of `Velocity` and `TrackedByTree`, so
+/// nothing simulates, collides with or selects them. The formation itself
+/// takes their place in the spatial index, as one entry at its origin.
+#[derive(Component, Default)]
+pub struct LodUnloaded;
+
+/// Camera-distance LOD: unloads the boids of formations beyond
+/// [`LODGuard::unload_distance`] and reloads them inside
+/// [`LODGuard::reload_distance`]. Only formations with boid members are
+/// candidates (companies of an organised hierarchy, or flat formations);
+/// parents follow through [`propagate_formation_targets`], which flips
+/// `Velocity` onto an unloaded formation so it marches as one unit.
+///
+/// A formation the enemy may fight is never unloaded, wherever the camera
+/// is: one in sight of another faction ([`FogOfWar`]), or with another
+/// faction's boid or unloaded formation within [`VIEW_RANGE`] of its
+/// footprint. An unloaded formation that comes to either reloads at once,
+/// so combat only ever meets loaded boids.
+///
+/// Reloaded boids reappear at their slots around wherever the formation
+/// got to, moving at its velocity; slots, member count and per-boid state
+/// survive the round trip untouched.
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+pub fn lod_manager(
+    lod: Res<LODGuard>,
+    fog: Res<FogOfWar>,
+    factions: Res<Factions>,
+    tree: Res<NNTree>,
+    q_viewer: Query<&GlobalTransform, With<LodViewer>>,
+    q_formations: Query<(
+        Entity,
+        &Transform,
+        &Formation,
+        &Faction,
+        &Members,
+        Option<&Formations>,
+        Option<&Velocity>,
+        Has<LodUnloaded>,
+    )>,
+    q_factions: Query<&Faction>,
+    q_slots: Query<&FormationSlot>,
+    mut commands: Commands,
+) {
+    let Ok(viewer) = q_viewer.single() else {
+        return;
+    };
+    let viewer = viewer.translation();
+    let engaged = |faction: Faction, pos: Vec3, radius: f32| {
+        let in_sight = fog.enabled
+            && (0..factions.factions.len() as u8)
+                .map(Faction)
+                .any(|other| other != faction && fog.sees(other, pos));
+        in_sight
+            || tree
+                .within_distance(pos, radius + VIEW_RANGE)
+                .into_iter()
+                .filter_map(|(_, entity)| entity)
+                .any(|entity| q_factions.get(entity).is_ok_and(|f| *f != faction))
+    };
+    for (entity, transform, formation, faction, members, subs, velocity, unloaded) in &q_formations
+    {
+        let (pos, radius) = (transform.translation, formation.footprint.radius());
+        let distance = pos.distance(viewer);
+        if !unloaded && distance > lod.unload_distance {
+            if engaged(*faction, pos, radius) {
+                continue;
+            }
+            for member in members.iter() {
+                commands
+                    .entity(member)
+                    .remove::<(Velocity, TrackedByTree)>()
+                    .insert(Visibility::Hidden);
+            }
+            commands.entity(entity).insert((LodUnloaded, TrackedByTree));
+        } else if unloaded && (distance < lod.reload_distance || engaged(*faction, pos, radius)) {
+            let total = members.len() + subs.map_or(0, |s| s.len());
+            let rotation = yaw_quat(formation.dir).unwrap_or(Quat::IDENTITY);
+            let v = velocity.map_or(Vec3::ZERO, |v| v.v);
+            for (member, fallback) in members.iter().zip(0..) {
+                let slot = q_slots.get(member).map_or(fallback, |s| s.0);
+                commands.entity(member).insert((
+                    Transform::from_translation(
+                        pos + rotation * formation.slot_offset(slot, total),
+                    )
+                    .with_rotation(transform.rotation),
+                    Velocity { v, ..default() },
+                    TrackedByTree,
+                    Visibility::Inherited,
+                ));
+            }
+            commands
+                .entity(entity)
+                .remove::<(LodUnloaded, TrackedByTree)>();
+        }
+    }
+}
+
+/// Auto-organiser settings for large groups (see [`organize_hierarchy`]).
+/// With `auto_organize` off, grouping always builds one flat formation.
+#[derive(Resource, Debug)]
+pub struct CommandHierarchy {
+    pub auto_organize: bool,
+    /// Boids per company, the lowest formation level.
+    pub company_size: usize,
+    /// Companies per battalion; more companies than this get a battalion
+    /// level between them and the top formation.
+    pub battalion_size: usize,
+}
+
+impl Default for CommandHierarchy {
+    fn default() -> Self {
+        Self {
+            auto_organize: true,
+            company_size: 100,
+            battalion_size: 10,
+        }
+    }
+}
+
+/// Spawn a formation over `boids`, split into a hierarchy of companies (and
+/// battalions, when there are more companies than fit one) per `config`,
+/// and return the top formation. Companies are cut from the Morton order of
+/// the boids' positions, so each is a spatially compact chunk; every parent
+/// spaces its slots by the larger side of its sub-formations' footprint. Orders to the top
+/// propagate down through [`process_formation_orders`], and each company is
+/// a candidate lowest loaded level for LOD.
+///
+/// Groups that fit one company (or `auto_organize` off) stay flat. Boids
+/// leave whatever formation they were in. Every level fights for `faction`,
+/// the side the boids were gathered from.
+pub fn organize_hierarchy(
+    commands: &mut Commands,
+    boids: &[(Entity, Vec3)],
+    faction: Faction,
+    config: &CommandHierarchy,
+) -> Entity {
+    let centroid = boids.iter().map(|(_, p)| *p).sum::<Vec3>() / boids.len().max(1) as f32;
+    let company_size = config.company_size.max(1);
+    if !config.auto_organize || boids.len() <= company_size {
+        let top = commands
+            .spawn((
+                Formation::default(),
+                Transform::from_translation(centroid),
+                faction,
+            ))
+            .id();
+        for &(boid, _) in boids {
+            join(commands, boid, top);
+        }
+        return top;
+    }
+
+    let mut sorted = boids.to_vec();
+    sorted.sort_by_key(|&(_, p)| morton2(p));
+    let companies: Vec<(Entity, Vec3)> = sorted
+        .chunks(company_size)
+        .map(|chunk| {
+            let center = chunk.iter().map(|(_, p)| *p).sum::<Vec3>() / chunk.len() as f32;
+            let company = commands
+                .spawn((
+                    Formation::default(),
+                    Transform::from_translation(center),
+                    faction,
+                ))
+                .id();
+            for &(boid, _) in chunk {
+                join(commands, boid, company);
+            }
+            (company, center)
+        })
+        .collect();
+
+    // Parents draw up their sub-formations at an interval of the larger side
+    // of a sub-formation's footprint.
+    let spaced = |interval: f32| Formation {
+        params: FormationParams {
+            interval,
+            ..default()
+        },
+        ..default()
+    };
+    let company = FormationKind::default().footprint(company_size, &FormationParams::default());
+    let company_spacing = company.width.max(company.depth);
+    let battalion_size = config.battalion_size.max(1);
+    if companies.len() <= battalion_size {
+        let top = commands
+            .spawn((
+                spaced(company_spacing),
+                Transform::from_translation(centroid),
+                faction,
+            ))
+            .id();
+        for (company, _) in companies {
+            commands.entity(company).insert(FormationOf(top));
+        }
+        return top;
+    }
+
+    let battalion = spaced(company_spacing).slot_footprint(battalion_size);
+    let battalion_spacing = battalion.width.max(battalion.depth);
+    let top = commands
+        .spawn((
+            spaced(battalion_spacing),
+            Transform::from_translation(centroid),
+            faction,
+        ))
+        .id();
+    for chunk in companies.chunks(battalion_size) {
+        // Each battalion draws up where its own companies stand.
+        let center = chunk.iter().map(|(_, p)| *p).sum::<Vec3>() / chunk.len() as f32;
+        let battalion = commands
+            .spawn((
+                spaced(company_spacing),
+                Transform::from_translation(center),
+                faction,
+                FormationOf(top),
+            ))
+            .id();
+        for &(company, _) in chunk {
+            commands.entity(company).insert(FormationOf(battalion));
+        }
+    }
+    top
+}
+
+/// Attach `boid` to `formation`, dropping any slot it held in a previous
+/// formation (see [`FormationSlot`]).
+fn join(commands: &mut Commands, boid: Entity, formation: Entity) {
+    commands
+        .entity(boid)
+        .insert(MemberOf(formation))
+        .remove::<FormationSlot>();
+}
+
+/// Despawn `formation` and every sub-formation below it, detaching all
+/// member boids (`MemberOf` together with `FormationSlot`, see
+/// [`FormationSlot`]) so they become free boids.
+pub fn disband_formation(
+    commands: &mut Commands,
+    q_hierarchy: &Query<(Option<&Members>, Option<&Formations>), With<Formation>>,
+    formation: Entity,
+) {
+    if let Ok((members, subs)) = q_hierarchy.get(formation) {
+        for member in members.into_iter().flat_map(|m| m.iter()) {
+            commands
+                .entity(member)
+                .remove::<MemberOf>()
+                .remove::<FormationSlot>();
+        }
+        for sub in subs.into_iter().flat_map(|s| s.iter()) {
+            disband_formation(commands, q_hierarchy, sub);
         }
     }
+    commands.entity(formation).despawn();
 }
 
 /// Derive [`Formation::max_speed`] = min over member max speeds, once per
@@ -295,14 +1363,15 @@ impl Default for LODGuard {
 /// which is why this is a system in a later frame rather than a spawn hook.
 /// Sub-formations initialize bottom-up: while a child still carries the
 /// marker its `max_speed` is the default, so the parent waits a tick instead
-/// of reading a bogus speed. Plain boids have no per-entity speed (yet) and
-/// contribute `MAX_VELOCITY`.
+/// of reading a bogus speed. Boids contribute their [`UnitStats::max_speed`]
+/// (`MAX_VELOCITY` without stats).
 pub fn init_formation_speed(
     q_marked: Query<
         (Entity, Option<&Members>, Option<&Formations>),
         (With<Formation>, With<NeedsSpeedInit>),
     >,
     q_details: Query<(&Formation, Option<&NeedsSpeedInit>)>,
+    q_stats: Query<&UnitStats>,
     mut commands: Commands,
 ) {
     for (entity, members, subs) in &q_marked {
@@ -322,7 +1391,12 @@ pub fn init_formation_speed(
                     pending |= child_pending.is_some();
                     max_speed = max_speed.min(child.max_speed);
                 }
-                Err(_) => max_speed = max_speed.min(crate::kinematics::MAX_VELOCITY),
+                Err(_) => {
+                    let speed = q_stats
+                        .get(member)
+                        .map_or(crate::kinematics::MAX_VELOCITY, |s| s.max_speed);
+                    max_speed = max_speed.min(speed);
+                }
             }
         }
         if !any || pending {
@@ -338,7 +1412,7 @@ pub fn init_formation_speed(
     }
 }
 
-/// Maintain per-formation bookkeeping (extent) and the LOD Velocity split:
+/// Maintain per-formation bookkeeping (footprint) and the LOD Velocity split:
 /// a formation WITH `Velocity` is the lowest loaded level of its branch -
 /// nothing below it needs simulating, so it integrates like a single boid
 /// (`move_step` + `follow_target`) and [`process_formation_orders`] executes
@@ -371,7 +1445,16 @@ pub fn propagate_formation_targets(
     }
     for (entity, mut formation, members, subs, velocity) in &mut q_formations {
         let total = members.map_or(0, |m| m.len()) + subs.map_or(0, |s| s.len());
-        formation.extent = formation.slot_extent(total);
+        let layout = Some((
+            formation.kind,
+            formation.params,
+            total,
+            formation.faced_about,
+        ));
+        if formation.footprint_for != layout {
+            formation.footprint = formation.slot_footprint(total);
+            formation.footprint_for = layout;
+        }
 
         // Lowest loaded iff nothing below is simulated or propagates
         // further. Unresolvable members are gone; they simulate nothing.
@@ -397,7 +1480,7 @@ pub fn propagate_formation_targets(
 
 /// Automatic slot maintenance: (re)assigns members whenever the current
 /// assignment is invalid - group creation (no slots yet), a member dying or
-/// leaving (gap), or a kind/column change. Explicit player-driven reforms go
+/// leaving (gap), or a kind/file change. Explicit player-driven reforms go
 /// through [`FormationOrder::Reform`] in [`process_formation_orders`]; both paths
 /// share [`assign_slots_nearest`].
 pub fn assign_slots(
@@ -510,25 +1593,394 @@ fn yaw_quat(dir: Vec3) -> Option<Quat> {
     }
 }
 
+/// A member within this distance of its slot counts as in place.
+pub const SLOT_TOLERANCE: f32 = 0.5 * FormationKind::SPACING;
+
+/// RMS slot error at which a marching formation's lead is cut to
+/// [`MIN_COHESION_LEAD_FACTOR`].
+pub const COHESION_STALL_RMS: f32 = 4.0 * FormationKind::SPACING;
+
+/// Floor of [`Cohesion::lead_factor`]: even a badly scattered formation
+/// keeps creeping toward its goal.
+pub const MIN_COHESION_LEAD_FACTOR: f32 = 0.25;
+
+/// A holding formation with a smaller in-place fraction re-dresses.
+pub const REDRESS_IN_PLACE: f32 = 0.5;
+
+/// Minimum seconds between automatic re-dresses: a reform only re-maps
+/// slots, members need time to walk to them before cohesion is judged again.
+pub const REDRESS_COOLDOWN: f32 = 5.0;
+
+/// Measure [`Cohesion`] for every formation with loaded boid members, and
+/// order a [`FormationOrder::Reform`] when a holding formation (empty queue
+/// or [`FormationOrder::Hold`]) has come apart. The reform is pushed in front
+/// so a standing `Hold` resumes afterwards.
+///
+/// A lowest loaded formation (carrying `Velocity`) has nothing to measure -
+/// its members are abstracted away - and keeps its last reading.
+pub fn measure_cohesion(
+    time: Res<Time>,
+    mut q_formations: Query<
+        (
+            &Transform,
+            &mut Formation,
+            &mut Cohesion,
+            Option<&Members>,
+            Option<&Formations>,
+        ),
+        Without<Velocity>,
+    >,
+    q_members: Query<(&Transform, Option<&FormationSlot>), With<Velocity>>,
+) {
+    let dt = time.delta_secs();
+    for (transform, mut formation, mut cohesion, members, subs) in &mut q_formations {
+        cohesion.redress_cooldown = (cohesion.redress_cooldown - dt).max(0.0);
+        let Some(members) = members else {
+            continue;
+        };
+        let total = members.len() + subs.map_or(0, |s| s.len());
+        let rotation = yaw_quat(formation.dir).unwrap_or(Quat::IDENTITY);
+        let origin = transform.translation;
+
+        let mut sum_sq = 0.0;
+        let mut max_straggler: f32 = 0.0;
+        let mut in_place = 0usize;
+        let mut count = 0usize;
+        for (transform, slot) in members.iter().filter_map(|m| q_members.get(m).ok()) {
+            // Unslotted members are about to be assigned; not an error yet.
+            let Some(slot) = slot else {
+                continue;
+            };
+            let error = transform
+                .translation
+                .distance(origin + rotation * formation.slot_offset(slot.0, total));
+            sum_sq += error * error;
+            max_straggler = max_straggler.max(error);
+            if error <= SLOT_TOLERANCE {
+                in_place += 1;
+            }
+            count += 1;
+        }
+        if count == 0 {
+            continue;
+        }
+        cohesion.rms_error = (sum_sq / count as f32).sqrt();
+        cohesion.max_straggler = max_straggler;
+        cohesion.in_place = in_place as f32 / count as f32;
+
+        let holding = matches!(
+            formation.tasks.front(),
+            None | Some(FormationOrder::Hold { .. })
+        );
+        if holding && cohesion.is_poor() && cohesion.redress_cooldown == 0.0 {
+            info!(
+                "[cohesion] re-dressing: {:.0}% in place, rms {:.1}",
+                cohesion.in_place * 100.0,
+                cohesion.rms_error
+            );
+            formation.tasks.push_front(FormationOrder::Reform);
+            cohesion.redress_cooldown = REDRESS_COOLDOWN;
+        }
+    }
+}
+
+/// Accumulate [`PaceLog`]: time under an active `Move` or `Charge` counts
+/// toward the formation's current pace, anything else as halted.
+pub fn track_pace(time: Res<Time>, mut q_formations: Query<(&Formation, &mut PaceLog)>) {
+    let dt = time.delta_secs();
+    for (formation, mut log) in &mut q_formations {
+        match formation.tasks.front() {
+            Some(FormationOrder::Move { .. } | FormationOrder::Charge { .. }) => {
+                log.marching[formation.pace as usize] += dt
+            }
+            _ => log.halted += dt,
+        }
+    }
+}
+
+/// Gap kept between the footprints of neighbouring formations.
+pub const FORMATION_CLEARANCE: f32 = FormationKind::SPACING;
+
+/// Formation-level collision avoidance: top-level formations whose
+/// footprints (plus [`FORMATION_CLEARANCE`]) overlap sidestep out of each
+/// other. Only holding formations (empty queue or [`FormationOrder::Hold`])
+/// give way - a marching one is following orders - so two holding
+/// formations split the distance, and a holding formation steps aside for
+/// one marching through it. The sidestep is a [`FormationOrder::Move`]
+/// pushed in front at the formation's current pace and facing; a standing
+/// `Hold` resumes afterwards.
+///
+/// Formations of different [`Faction`]s do not give way to each other:
+/// they close to fight. Sub-formations are left to their parent's slot
+/// layout, whose spacing already keeps them apart (see
+/// [`organize_hierarchy`]). Candidate pairs
+/// come from a uniform grid over the footprints' bounding circles, with
+/// cells as wide as the largest of them, so only formations in
+/// neighbouring cells are tested.
+pub fn separate_formations(
+    mut q_formations: Query<
+        (
+            Entity,
+            &Transform,
+            &mut Formation,
+            Option<&Members>,
+            Option<&Formations>,
+            &Faction,
+        ),
+        Without<FormationOf>,
+    >,
+) {
+    let holding = |formation: &Formation| {
+        matches!(
+            formation.tasks.front(),
+            None | Some(FormationOrder::Hold { .. })
+        )
+    };
+    // Empty formations (all members gone or detached) occupy no ground.
+    let bodies: Vec<(Entity, Vec3, Quat, Footprint, bool, Faction)> = q_formations
+        .iter()
+        .filter(|(_, _, _, members, subs, _)| {
+            members.map_or(0, |m| m.len()) + subs.map_or(0, |s| s.len()) > 0
+        })
+        .map(|(entity, transform, formation, _, _, faction)| {
+            (
+                entity,
+                transform.translation,
+                yaw_quat(formation.dir).unwrap_or(Quat::IDENTITY),
+                formation.footprint.inflated(FORMATION_CLEARANCE / 2.0),
+                holding(formation),
+                *faction,
+            )
+        })
+        .collect();
+
+    // Broad phase: bounding circles binned by cell.
+    let circles: Vec<(Vec3, f32)> = bodies
+        .iter()
+        .map(|&(_, pos, rotation, footprint, ..)| {
+            (
+                footprint.world_center(pos, rotation),
+                footprint.width.hypot(footprint.depth) / 2.0,
+            )
+        })
+        .collect();
+    let cell_size = circles
+        .iter()
+        .map(|&(_, radius)| 2.0 * radius)
+        .fold(FORMATION_CLEARANCE, f32::max);
+    let cell_of = |center: Vec3| {
+        (
+            (center.x / cell_size).floor() as i32,
+            (center.z / cell_size).floor() as i32,
+        )
+    };
+    let mut grid: HashMap<(i32, i32), Vec<usize>> = HashMap::new();
+    for (i, &(center, _)) in circles.iter().enumerate() {
+        grid.entry(cell_of(center)).or_default().push(i);
+    }
+    let mut candidates: Vec<(usize, usize)> = Vec::new();
+    for (i, &(center, radius)) in circles.iter().enumerate() {
+        let (x, z) = cell_of(center);
+        for dz in -1..=1 {
+            for dx in -1..=1 {
+                for &j in grid.get(&(x + dx, z + dz)).into_iter().flatten() {
+                    let (other, other_radius) = circles[j];
+                    if j > i && center.xz().distance(other.xz()) < radius + other_radius {
+                        candidates.push((i, j));
+                    }
+                }
+            }
+        }
+    }
+
+    let mut pushes: HashMap<Entity, Vec3> = HashMap::new();
+    for (i, j) in candidates {
+        let (a, a_pos, a_rot, a_footprint, a_holding, a_faction) = bodies[i];
+        let (b, b_pos, b_rot, b_footprint, b_holding, b_faction) = bodies[j];
+        // Enemies close to fight, not to give way.
+        if a_faction != b_faction {
+            continue;
+        }
+        let Some(push) = a_footprint.penetration(a_pos, a_rot, &b_footprint, b_pos, b_rot) else {
+            continue;
+        };
+        let (a_share, b_share) = match (a_holding, b_holding) {
+            (true, true) => (0.5, 0.5),
+            (true, false) => (1.0, 0.0),
+            (false, true) => (0.0, 1.0),
+            (false, false) => continue,
+        };
+        *pushes.entry(a).or_default() += push * a_share;
+        *pushes.entry(b).or_default() -= push * b_share;
+    }
+
+    for (entity, push) in pushes {
+        // Pushes from opposite neighbours can cancel out.
+        if push.length() < 1e-3 {
+            continue;
+        }
+        let Ok((_, transform, mut formation, ..)) = q_formations.get_mut(entity) else {
+            continue;
+        };
+        let step = push + push.normalize() * ARRIVE_TOLERANCE;
+        let (facing_dir, pace) = (formation.dir, formation.pace);
+        formation.tasks.push_front(FormationOrder::Move {
+            pos: transform.translation + step,
+            facing_dir,
+            pace,
+        });
+        info!("[separate] {entity:?} sidesteps {:.1}", step.length());
+    }
+}
+
+/// Minimum distance between two [`RoadMarch`] breadcrumbs.
+pub const BREADCRUMB_SPACING: f32 = 0.5 * FormationKind::SPACING;
+
+/// Depth difference under which two slots count as the same rank.
+const RANK_EPSILON: f32 = 1e-3;
+
+/// Road march for formations carrying [`RoadMarch`]: overrides the rigid
+/// targets written by [`process_formation_orders`] for everyone behind the
+/// front rank, so the column snakes through the gaps its head found.
+///
+/// A slot's depth is its distance behind the front rank along the slot
+/// frame's forward axis; its lateral offset is kept perpendicular to the
+/// path (files of a multi-file column stay side by side around corners).
+/// The trail is seeded straight behind the head when a march starts and
+/// trimmed to the column's depth. Holding formations drop the trail and
+/// dress back into the rigid layout. Runs after `process_formation_orders`;
+/// speed caps are left as the pace set them.
+pub fn follow_road(
+    mut q_formations: Query<
+        (
+            &Formation,
+            &mut RoadMarch,
+            Option<&Members>,
+            Option<&Formations>,
+        ),
+        Without<Velocity>,
+    >,
+    q_members: Query<(&Transform, &FormationSlot), With<Velocity>>,
+    mut q_targets: Query<&mut Target, With<MemberOf>>,
+) {
+    for (formation, mut road, members, subs) in &mut q_formations {
+        let marching = matches!(formation.tasks.front(), Some(FormationOrder::Move { .. }));
+        let Some(members) = members.filter(|_| marching) else {
+            road.trail.clear();
+            continue;
+        };
+        let total = members.len() + subs.map_or(0, |s| s.len());
+        let slotted: Vec<(Entity, Vec3, Vec3)> = members
+            .iter()
+            .filter_map(|m| {
+                q_members
+                    .get(m)
+                    .ok()
+                    .map(|(t, slot)| (m, t.translation, formation.slot_offset(slot.0, total)))
+            })
+            .collect();
+        let Some(front) = slotted.iter().map(|(_, _, o)| o.z).reduce(f32::max) else {
+            continue;
+        };
+
+        // The head is the center of the front rank, where it actually stands.
+        let mut head = Vec3::ZERO;
+        let mut head_x = 0.0;
+        let mut front_rank = 0;
+        for (_, pos, offset) in &slotted {
+            if front - offset.z < RANK_EPSILON {
+                head += *pos;
+                head_x += offset.x;
+                front_rank += 1;
+            }
+        }
+        head /= front_rank as f32;
+        head_x /= front_rank as f32;
+        let depth = slotted.iter().map(|(_, _, o)| front - o.z).fold(0.0, f32::max);
+
+        if road.trail.is_empty() {
+            let forward = yaw_quat(formation.dir).unwrap_or(Quat::IDENTITY) * Vec3::Z;
+            road.trail.push_back(head);
+            road.trail.push_back(head - forward * depth.max(BREADCRUMB_SPACING));
+        } else if road.trail[0].distance(head) >= BREADCRUMB_SPACING {
+            road.trail.push_front(head);
+        }
+        trim_trail(&mut road.trail, head, depth + BREADCRUMB_SPACING);
+
+        for (member, _, offset) in &slotted {
+            let behind = front - offset.z;
+            if behind < RANK_EPSILON {
+                continue; // front rank steers by the rigid goal
+            }
+            let (point, tangent) = sample_trail(head, &road.trail, behind);
+            let right = Vec3::Y.cross(tangent);
+            if let Ok(mut target) = q_targets.get_mut(*member) {
+                target.pos = point + right * (offset.x - head_x);
+                target.dir = tangent;
+            }
+        }
+    }
+}
+
+/// Drop breadcrumbs beyond `length` path distance from `head`, keeping the
+/// one that straddles it so the path still covers the full length.
+fn trim_trail(trail: &mut VecDeque<Vec3>, head: Vec3, length: f32) {
+    let mut covered = 0.0;
+    let mut prev = head;
+    for (i, &crumb) in trail.iter().enumerate() {
+        covered += prev.distance(crumb);
+        prev = crumb;
+        if covered >= length {
+            trail.truncate(i + 1);
+            return;
+        }
+    }
+}
+
+/// The point `distance` along the polyline `head -> trail[0] -> trail[1]..`
+/// and the unit direction of travel there (pointing toward the head).
+/// Past the end of the trail the last segment is extrapolated.
+fn sample_trail(head: Vec3, trail: &VecDeque<Vec3>, distance: f32) -> (Vec3, Vec3) {
+    let mut remaining = distance;
+    let mut prev = head;
+    let mut tangent = Vec3::Z;
+    for &crumb in trail {
+        let segment = prev - crumb;
+        let length = segment.length();
+        if length < 1e-6 {
+            continue;
+        }
+        tangent = segment / length;
+        if remaining <= length {
+            return (prev - tangent * remaining, tangent);
+        }
+        remaining -= length;
+        prev = crumb;
+    }
+    (prev - tangent * remaining, tangent)
+}
+
 /// Task executor: runs the front of each formation's task queue until
 /// finished, then pops it and starts the next. With an empty queue the
 /// formation holds position: the origin tracks the members' center of mass
 /// and members hold their slots in the current facing.
 ///
 /// Every frame, for every formation:
-/// 1. Task transitions: `Rotate` and a `Move` whose facing differs turn the
-///    slot frame and flag the formation for member re-mapping (symmetric
-///    formations re-orient without moving: different slot, same position);
+/// 1. Task transitions: `Rotate` and a `Move` whose facing differs (by
+///    more than a [wheel](Formation::wheels)) turn the slot frame and flag
+///    the formation for member re-mapping (symmetric formations re-orient
+///    without moving: different slot, same position);
 ///    `Reform` flags it unconditionally. The assignment pass then re-maps
 ///    members to slots via Morton-order locality matching and pops the
 ///    finished `Rotate`/`Reform`.
 /// 2. The origin snaps to the center of mass of the members; a finished
 ///    `Move` (center of mass within [`ARRIVE_TOLERANCE`] of `pos`) pops.
 /// 3. Targets propagate from the *intermediate goal*: for `Move`, offset
-///    from the center of mass toward `pos` by `slowest_member_speed *
-///    LEAD_TIME`, clamped to the remaining distance - members keep
-///    formation along the path and are never asked to cover more than the
-///    lead distance. Otherwise the goal is the center of mass itself (hold).
+///    from the center of mass toward `pos` by `pace_speed * LEAD_TIME`
+///    (the slowest member's speed at the formation's [`Pace`]), scaled down by [`Cohesion::lead_factor`] and clamped to the
+///    remaining distance - members keep formation along the path and are
+///    never asked to cover more than the lead distance. Otherwise the goal is
+///    the center of mass itself (hold).
 ///
 /// A lowest loaded formation (carrying `Velocity`, see
 /// [`propagate_formation_targets`]) is instead simulated as one unit: it
@@ -562,14 +2014,16 @@ pub fn process_formation_orders(
         Query<(&Transform, &Velocity, Option<&FormationSlot>)>,
         Query<&mut Target>,
     )>,
+    mut q_cohesion: Query<&mut Cohesion>,
+    mut q_lead: Query<&mut Lead>,
+    q_road_march: Query<(), With<RoadMarch>>,
     mut commands: Commands,
-    mut gizmos: Gizmos,
 ) {
     // Pass A - task transitions. Mutates Formation state only; slot
     // assignment follows right after (it needs read-only access to both
     // formations and member transforms).
     let mut needs_assign: Vec<Entity> = Vec::new();
-    for (formation_entity, _, mut formation, _, _, _) in params.p0().iter_mut() {
+    for (formation_entity, _, mut formation, _, subs, _) in params.p0().iter_mut() {
         let Some(task) = formation.tasks.front().copied() else {
             continue;
         };
@@ -580,19 +2034,83 @@ pub fn process_formation_orders(
                 // Popped in the assignment pass below once members re-map.
             }
             FormationOrder::Reform => {
+                // A fresh dress puts the front rank back in front.
+                formation.faced_about = false;
                 needs_assign.push(formation_entity);
                 // Popped in the assignment pass below when members re-map.
             }
-            FormationOrder::Move { facing_dir, .. } => {
-                // Facing change: re-map slots into the new frame before marching.
+            // A charge sets its own pace as it goes.
+            FormationOrder::Move { facing_dir, .. } | FormationOrder::Charge { facing_dir, .. } => {
+                if let FormationOrder::Move { pace, .. } = task {
+                    formation.pace = pace;
+                }
+                // Facing change: re-map slots into the new frame before
+                // marching. A road-marching formation keeps its slots and
+                // wheels instead: the front turns, the rest follow its path.
+                // So does any formation for a slight turn, which keeps the
+                // rank roles of an about-face that came before.
                 if formation.dir.distance_squared(facing_dir) > 1e-4 {
+                    let wheels = formation.wheels(facing_dir);
                     formation.dir = facing_dir;
-                    needs_assign.push(formation_entity);
+                    if !wheels && !q_road_march.contains(formation_entity) {
+                        needs_assign.push(formation_entity);
+                    }
                 }
             }
             // Hold formalizes the idle state; the passes below treat it
             // exactly like an empty queue (hold at the center of mass).
             FormationOrder::Hold { .. } => {}
+            // Shooting and rallying are done standing, as a hold. A
+            // container's sub-formations take over the order, replacing
+            // theirs.
+            FormationOrder::FireAt { .. } | FormationOrder::Rally => {
+                if let Some(subs) = subs {
+                    formation.tasks.pop_front();
+                    for sub in subs.iter() {
+                        commands.queue(move |world: &mut World| {
+                            if let Some(mut formation) = world.get_mut::<Formation>(sub) {
+                                formation.tasks.clear();
+                                formation.tasks.push_back(task);
+                            }
+                        });
+                    }
+                }
+            }
+            // Slots scale about the origin, so nobody needs re-mapping:
+            // members walk out to (or in to) their slots at the new interval.
+            // Until they get there the formation reads as scattered; hold
+            // off the automatic re-dress, which would shuffle slots.
+            FormationOrder::Intervals(intervals) => {
+                formation.params.intervals = intervals;
+                formation.tasks.pop_front();
+                if let Ok(mut cohesion) = q_cohesion.get_mut(formation_entity) {
+                    cohesion.redress_cooldown = REDRESS_COOLDOWN;
+                }
+            }
+            // Both keep every slot (and so every rank role); they differ in
+            // whether the layout turns with the facing. Faced about it
+            // stays where it stands; countermarching it turns half round
+            // about the origin and the members walk to their reflected
+            // places - again without a re-dress shuffling them meanwhile.
+            // A container faces about itself so its sub-formations keep
+            // their ground, and passes the order down.
+            FormationOrder::AboutFace | FormationOrder::Countermarch => {
+                formation.dir = -formation.dir;
+                let countermarch = matches!(task, FormationOrder::Countermarch);
+                if !countermarch || subs.is_some() {
+                    formation.faced_about = !formation.faced_about;
+                } else if let Ok(mut cohesion) = q_cohesion.get_mut(formation_entity) {
+                    cohesion.redress_cooldown = REDRESS_COOLDOWN;
+                }
+                formation.tasks.pop_front();
+                for sub in subs.into_iter().flat_map(|s| s.iter()) {
+                    commands.queue(move |world: &mut World| {
+                        if let Some(mut formation) = world.get_mut::<Formation>(sub) {
+                            formation.tasks.push_front(task);
+                        }
+                    });
+                }
+            }
         }
     }
 
@@ -666,10 +2184,11 @@ pub fn process_formation_orders(
         }
     }
 
-    // Pass B - snapshot members (slots), the active task, max speed, and
-    // whether the formation itself is the lowest loaded level (carries
+    // Pass B - snapshot members (slots), the active task, marching speed,
+    // and whether the formation itself is the lowest loaded level (carries
     // `Velocity`). `max_speed` (derived by `init_formation_speed` after
-    // creation) drives the lead distance; the slowest-member scan is gone.
+    // creation) at the current pace drives the lead distance; the
+    // slowest-member scan is gone.
     struct Snapshot {
         entity: Entity,
         own_pos: Vec3,
@@ -677,7 +2196,8 @@ pub fn process_formation_orders(
         member_slots: Vec<(Entity, Option<usize>)>,
         subs: Vec<Entity>,
         task: Option<FormationOrder>,
-        max_speed: f32,
+        pace_speed: f32,
+        lead_factor: f32,
     }
     let mut snapshots: Vec<Snapshot> = params
         .p0()
@@ -693,7 +2213,8 @@ pub fn process_formation_orders(
                 .collect(),
             subs: subs.into_iter().flat_map(|s| s.iter()).collect(),
             task: formation.tasks.front().copied(),
-            max_speed: formation.max_speed,
+            pace_speed: formation.pace_speed(),
+            lead_factor: q_cohesion.get(entity).map_or(1.0, Cohesion::lead_factor),
         })
         .collect();
     for snapshot in &mut snapshots {
@@ -710,11 +2231,16 @@ pub fn process_formation_orders(
         facing: Vec3,
         task_pos: Option<Vec3>,
     }
+    let origins: HashMap<Entity, Vec3> =
+        snapshots.iter().map(|s| (s.entity, s.own_pos)).collect();
     let mut plans: Vec<Option<Plan>> = Vec::with_capacity(snapshots.len());
     for snapshot in &snapshots {
         // A formation simulated as a unit IS its own center of mass; a
         // container's is the center of mass of everything simulated below
-        // it: loaded boids and lowest loaded sub-formations alike.
+        // it: loaded boids and lowest loaded sub-formations alike. A
+        // sub-formation that is itself a container counts at its origin
+        // (its own center of mass, as of its last snap), so orders to the
+        // top of a deep hierarchy still get a plan.
         let com = if snapshot.self_simulated {
             snapshot.own_pos
         } else {
@@ -729,6 +2255,9 @@ pub fn process_formation_orders(
                 if let Ok((transform, _, _)) = params.p1().get(member) {
                     com += transform.translation;
                     count += 1;
+                } else if let Some(origin) = origins.get(&member) {
+                    com += *origin;
+                    count += 1;
                 }
             }
             if count == 0 {
@@ -738,13 +2267,21 @@ pub fn process_formation_orders(
             com / count as f32
         };
 
-        // Active Move: goal is the intermediate point toward the task
-        // position. Anything else (idle, Reform, pre-Move): hold at COM.
+        // Active Move (or Charge): goal is the intermediate point toward
+        // the task position. Anything else (idle, Reform, pre-Move): hold
+        // at COM.
         let (goal, facing, task_pos) = match snapshot.task {
-            Some(FormationOrder::Move { pos, facing_dir }) => {
+            Some(
+                FormationOrder::Move {
+                    pos, facing_dir, ..
+                }
+                | FormationOrder::Charge {
+                    pos, facing_dir, ..
+                },
+            ) => {
                 let to_target = pos - com;
                 let distance = to_target.length();
-                let lead = (snapshot.max_speed * LEAD_TIME)
+                let lead = (snapshot.pace_speed * LEAD_TIME * snapshot.lead_factor)
                     .max(MIN_LEAD)
                     .min(distance);
                 let goal = if distance > 1e-4 {
@@ -767,13 +2304,18 @@ pub fn process_formation_orders(
 
     // Pass C - snap the origin to the center of mass (containers only; a
     // self-simulated formation's transform belongs to `move_step`); marker
-    // rotation follows the effective facing; pop finished Move tasks.
-    for ((_, mut transform, mut formation, _, _, velocity), plan) in
+    // rotation follows the effective facing; record the lead; pop finished
+    // Move tasks.
+    for ((entity, mut transform, mut formation, _, _, velocity), plan) in
         params.p0().iter_mut().zip(&plans)
     {
         let Some(plan) = plan else {
             continue;
         };
+        if let Ok(mut lead) = q_lead.get_mut(entity) {
+            lead.center_of_mass = plan.center_of_mass;
+            lead.goal = plan.goal;
+        }
         if velocity.is_none() {
             transform.translation = plan.center_of_mass;
         }
@@ -798,8 +2340,9 @@ pub fn process_formation_orders(
     // Otherwise members are placed by slot identity (list order fallback
     // before the first assignment): boids get Target directly;
     // sub-formations receive a Move task (the parent fully dictates the
-    // child's placement) and propagate next frame.
-    let mut assignments: Vec<(Entity, Vec3, Vec3)> = Vec::new();
+    // child's placement and pace) and propagate next frame. Everything
+    // driven from here is capped at the formation's pace speed.
+    let mut assignments: Vec<(Entity, Vec3, Vec3, f32)> = Vec::new();
     let mut sub_tasks: Vec<(Entity, FormationOrder)> = Vec::new();
     for (((entity, _, formation, _, subs, velocity), snapshot), plan) in
         params.p0().iter().zip(&snapshots).zip(&plans)
@@ -813,17 +2356,26 @@ pub fn process_formation_orders(
             plan.facing
         };
         let rotation = yaw_quat(facing).unwrap_or(Quat::IDENTITY);
+        let speed = formation.pace_speed();
         if velocity.is_some() {
-            assignments.push((entity, plan.goal, facing));
+            assignments.push((entity, plan.goal, facing, speed));
         } else {
             let member_slots = &snapshot.member_slots;
             let total = member_slots.len() + subs.map_or(0, |s| s.len());
             for ((member, slot), fallback) in member_slots.iter().zip(0..) {
                 let slot = slot.unwrap_or(fallback);
+                // Everyone marches to the front; at the halt each slot faces
+                // its own way (the outer faces of a hollow square).
+                let member_facing = if plan.task_pos.is_some() {
+                    facing
+                } else {
+                    rotation * formation.slot_facing(slot, total)
+                };
                 assignments.push((
                     *member,
                     plan.goal + rotation * formation.slot_offset(slot, total),
-                    facing,
+                    member_facing,
+                    speed,
                 ));
             }
             if let Some(subs) = subs {
@@ -840,25 +2392,19 @@ pub fn process_formation_orders(
                                     + rotation
                                         * formation.slot_offset(member_slots.len() + i, total),
                                 facing_dir: facing,
+                                pace: formation.pace,
                             },
                         ));
                     }
                 }
             }
         }
-        // Debug: center of mass -> goal, and goal -> final task target.
-        gizmos.line(plan.center_of_mass, plan.goal, Color::srgb(0.2, 0.6, 1.0));
-        if let Some(pos) = plan.task_pos {
-            let lifted = pos + Vec3::new(0.0, 0.2, 0.0);
-            if plan.goal.distance(lifted) > 1e-3 {
-                gizmos.line(plan.goal, lifted, Color::srgb(0.5, 0.5, 0.5));
-            }
-        }
     }
-    for (entity, pos, dir) in assignments {
+    for (entity, pos, dir, speed) in assignments {
         if let Ok(mut target) = params.p2().get_mut(entity) {
             target.pos = pos;
             target.dir = dir;
+            target.speed = Some(speed);
         }
     }
     for (sub, task) in sub_tasks {
@@ -875,10 +2421,8 @@ pub fn process_formation_orders(
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::kinematics::{TrackedByTree, move_step};
+    use crate::kinematics::move_step;
     use crate::target::follow_target;
-    use bevy::gizmos::AppGizmoBuilder;
-    use bevy::gizmos::config::{DefaultGizmoConfigGroup, GizmoConfigStore};
     use bevy::time::Time;
     use std::time::Duration;
 
@@ -890,9 +2434,8 @@ mod tests {
         let mut app = App::new();
         app.init_resource::<Time>()
             .init_resource::<LODGuard>()
-            .init_resource::<GizmoConfigStore>()
-            .init_gizmo_group::<DefaultGizmoConfigGroup>()
-            .init_resource::<Assets<GizmoAsset>>()
+            .init_resource::<FogOfWar>()
+            .init_resource::<Factions>()
             .add_plugins(
                 AutomaticUpdate::<TrackedByTree>::new()
                     .with_frequency(Duration::from_secs_f32(1.0 / 20.0))
@@ -901,10 +2444,15 @@ mod tests {
             .add_systems(
                 Update,
                 (
+                    lod_manager,
                     init_formation_speed,
                     propagate_formation_targets,
                     assign_slots,
+                    measure_cohesion,
+                    track_pace,
+                    separate_formations,
                     process_formation_orders,
+                    follow_road,
                     follow_target,
                     move_step,
                 )
@@ -972,6 +2520,7 @@ mod tests {
             .push_back(FormationOrder::Move {
                 pos: dest,
                 facing_dir: Vec3::new(0.0, 0.0, 1.0),
+                pace: Pace::default(),
             });
 
         for _ in 0..900 {
@@ -988,6 +2537,43 @@ mod tests {
         assert!(tasks.is_empty(), "Move task should have finished");
     }
 
+    #[test]
+    fn lead_point_runs_ahead_toward_the_move_destination() {
+        let mut app = test_app();
+        let positions: Vec<Vec3> = (0..9)
+            .map(|i| Vec3::new((i % 3) as f32 * 2.0 - 2.0, 0.0, (i / 3) as f32 * 2.0 - 2.0))
+            .collect();
+        let formation = spawn_formation(&mut app, &positions);
+        for _ in 0..10 {
+            tick(&mut app, 1.0 / 60.0);
+        }
+        let lead = *app.world().get::<Lead>(formation).unwrap();
+        assert!(
+            lead.goal.distance(lead.center_of_mass) < 1e-4,
+            "a holding formation leads to where it stands"
+        );
+
+        let dest = Vec3::new(0.0, 0.0, 60.0);
+        app.world_mut()
+            .get_mut::<Formation>(formation)
+            .unwrap()
+            .tasks
+            .push_back(FormationOrder::Move {
+                pos: dest,
+                facing_dir: Vec3::Z,
+                pace: Pace::default(),
+            });
+        tick(&mut app, 1.0 / 60.0);
+        let lead = *app.world().get::<Lead>(formation).unwrap();
+        let ahead = lead.goal - lead.center_of_mass;
+        assert!(
+            ahead.length() >= MIN_LEAD - 1e-4,
+            "lead too short: {ahead:?}"
+        );
+        assert!(ahead.normalize().z > 0.99, "lead should point at {dest:?}");
+        assert!(lead.goal.distance(dest) < lead.center_of_mass.distance(dest));
+    }
+
     #[test]
     fn move_task_reorients_without_moving_symmetric_formation() {
         let mut app = test_app();
@@ -1021,6 +2607,7 @@ mod tests {
             .push_back(FormationOrder::Move {
                 pos: com,
                 facing_dir: Vec3::new(0.0, 0.0, -1.0),
+                pace: Pace::default(),
             });
 
         for _ in 0..240 {
@@ -1132,33 +2719,893 @@ mod tests {
     }
 
     #[test]
-    fn init_formation_speed_derives_from_slowest_subformation() {
+    fn walk_pace_caps_member_speed_and_is_logged() {
         let mut app = test_app();
-        // Sub-formation with boid members initializes to MAX_VELOCITY on the
-        // first tick; the parent must wait for that before deriving its own
-        // speed (a pending child still reports the default).
-        let sub = spawn_formation(
+        let formation = spawn_formation(
             &mut app,
-            &[Vec3::new(-1.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0)],
+            &[Vec3::new(-2.0, 0.0, 0.0), Vec3::ZERO, Vec3::new(2.0, 0.0, 0.0)],
         );
-        let parent = app
+        for _ in 0..10 {
+            tick(&mut app, 1.0 / 60.0);
+        }
+        app.world_mut()
+            .get_mut::<Formation>(formation)
+            .unwrap()
+            .tasks
+            .push_back(FormationOrder::Move {
+                pos: Vec3::new(0.0, 0.0, 200.0),
+                facing_dir: Vec3::new(0.0, 0.0, 1.0),
+                pace: Pace::Walk,
+            });
+        for _ in 0..300 {
+            tick(&mut app, 1.0 / 60.0);
+        }
+
+        let world = app.world_mut();
+        let formation_ref = world.get::<Formation>(formation).unwrap();
+        assert_eq!(formation_ref.pace, Pace::Walk, "Move must set the pace");
+        let walk_speed = formation_ref.pace_speed();
+        let mut query = world.query_filtered::<&Velocity, With<MemberOf>>();
+        for velocity in query.iter(world) {
+            assert!(
+                velocity.v.length() <= walk_speed + 0.1,
+                "member at {} exceeds walking speed {walk_speed}",
+                velocity.v.length()
+            );
+        }
+        let log = world.get::<PaceLog>(formation).unwrap();
+        assert!(log.time_at(Pace::Walk) > 4.0, "march time not logged: {log:?}");
+        assert_eq!(log.time_at(Pace::Charge), 0.0);
+    }
+
+    #[test]
+    fn charging_is_logged_at_the_charge_pace() {
+        let mut app = App::new();
+        app.init_resource::<Time>().add_systems(Update, track_pace);
+        let formation = app
             .world_mut()
-            .spawn((Formation::default(), Transform::default()))
+            .spawn((
+                Formation {
+                    pace: Pace::Charge,
+                    ..default()
+                },
+                Transform::default(),
+            ))
             .id();
-        app.world_mut().entity_mut(sub).insert(FormationOf(parent));
-        tick(&mut app, 1.0 / 60.0);
+        app.world_mut()
+            .get_mut::<Formation>(formation)
+            .unwrap()
+            .tasks
+            .push_back(FormationOrder::Charge {
+                target: Entity::PLACEHOLDER,
+                pos: Vec3::new(0.0, 0.0, 100.0),
+                facing_dir: Vec3::Z,
+            });
+        for _ in 0..60 {
+            tick(&mut app, 1.0 / 60.0);
+        }
+        let log = app.world().get::<PaceLog>(formation).unwrap();
         assert!(
-            app.world().get::<NeedsSpeedInit>(parent).is_some(),
-            "parent must wait while the sub-formation is pending"
+            log.time_at(Pace::Charge) > 0.9,
+            "charge not logged: {log:?}"
         );
+        assert!(log.halted < 0.1, "{log:?}");
+    }
 
-        // Slow the sub below the default and let the parent derive.
-        app.world_mut().get_mut::<Formation>(sub).unwrap().max_speed = 3.0;
-        tick(&mut app, 1.0 / 60.0);
+    #[test]
+    fn road_march_column_follows_its_head_around_a_corner() {
+        let mut app = test_app();
+        let positions: Vec<Vec3> = (0..5)
+            .map(|i| Vec3::new(0.0, 0.0, i as f32 * 2.0 - 4.0))
+            .collect();
+        let formation = spawn_formation(&mut app, &positions);
+        {
+            let mut entity = app.world_mut().entity_mut(formation);
+            entity.insert(RoadMarch::default());
+            entity.get_mut::<Formation>().unwrap().kind = FormationKind::Column;
+        }
+        for _ in 0..10 {
+            tick(&mut app, 1.0 / 60.0);
+        }
+        let tail = {
+            let world = app.world_mut();
+            let mut query = world.query::<(Entity, &FormationSlot)>();
+            query.iter(world).find(|(_, s)| s.0 == 0).unwrap().0
+        };
 
-        let world = app.world_mut();
-        assert_eq!(world.get::<Formation>(parent).unwrap().max_speed, 3.0);
-        assert!(world.get::<NeedsSpeedInit>(parent).is_none());
+        // North, then wheel east at the corner.
+        let mut formation_mut = app.world_mut().get_mut::<Formation>(formation).unwrap();
+        formation_mut.tasks.push_back(FormationOrder::Move {
+            pos: Vec3::new(0.0, 0.0, 30.0),
+            facing_dir: Vec3::Z,
+            pace: Pace::default(),
+        });
+        formation_mut.tasks.push_back(FormationOrder::Move {
+            pos: Vec3::new(40.0, 0.0, 30.0),
+            facing_dir: Vec3::X,
+            pace: Pace::default(),
+        });
+
+        let mut tail_max_z = f32::MIN;
+        for _ in 0..1200 {
+            tick(&mut app, 1.0 / 60.0);
+            let z = app.world().get::<Transform>(tail).unwrap().translation.z;
+            tail_max_z = tail_max_z.max(z);
+        }
+        // The head turned beyond the first leg's goal; a rigid column's
+        // tail would cut the corner and never get past it.
+        assert!(
+            tail_max_z > 31.0,
+            "tail peaked at z={tail_max_z}, it did not follow the head's path"
+        );
+        assert_eq!(
+            app.world().get::<FormationSlot>(tail).unwrap().0,
+            0,
+            "wheeling must keep slots"
+        );
+    }
+
+    #[test]
+    fn trail_sampling_walks_back_along_the_polyline() {
+        let head = Vec3::new(4.0, 0.0, 10.0);
+        let trail: VecDeque<Vec3> =
+            [Vec3::new(0.0, 0.0, 10.0), Vec3::new(0.0, 0.0, 0.0)].into();
+        let (point, tangent) = sample_trail(head, &trail, 2.0);
+        assert!(point.distance(Vec3::new(2.0, 0.0, 10.0)) < 1e-5);
+        assert!(tangent.distance(Vec3::X) < 1e-5);
+        let (point, tangent) = sample_trail(head, &trail, 9.0);
+        assert!(point.distance(Vec3::new(0.0, 0.0, 5.0)) < 1e-5);
+        assert!(tangent.distance(Vec3::Z) < 1e-5);
+        // Past the end: extrapolate the last segment.
+        let (point, _) = sample_trail(head, &trail, 16.0);
+        assert!(point.distance(Vec3::new(0.0, 0.0, -2.0)) < 1e-5);
+
+        let mut trimmed = trail.clone();
+        trim_trail(&mut trimmed, head, 5.0);
+        assert_eq!(trimmed.len(), 2, "the straddling crumb stays");
+        trim_trail(&mut trimmed, head, 3.0);
+        assert_eq!(trimmed.len(), 1);
+    }
+
+    #[test]
+    fn split_members_by_files_and_by_count() {
+        let entity = |i: u32| Entity::from_raw_u32(i).unwrap();
+        // Listed in reverse so the split must go by slot, not list order.
+        let members: Vec<(Entity, Option<usize>)> =
+            (0..9).rev().map(|i| (entity(i), Some(i as usize))).collect();
+        let grid = Formation::default();
+        let mut left = split_members(&grid, &members, 9, SplitBy::Files);
+        left.sort();
+        assert_eq!(left, vec![entity(0), entity(3), entity(6)], "left file of 3x3");
+
+        let rear = split_members(&grid, &members, 9, SplitBy::Count(4));
+        assert_eq!(rear, vec![entity(0), entity(1), entity(2), entity(3)]);
+        let all = split_members(&grid, &members, 9, SplitBy::Count(20));
+        assert_eq!(all.len(), 8, "one member always stays");
+
+        let column = Formation {
+            kind: FormationKind::Column,
+            ..Formation::default()
+        };
+        assert!(split_members(&column, &members, 9, SplitBy::Files).is_empty());
+    }
+
+    /// Every kind, with and without file/rank overrides and in open order.
+    fn kind_cases() -> Vec<(FormationKind, FormationParams)> {
+        let drawn_up = |files, ranks, intervals| FormationParams {
+            files,
+            ranks,
+            intervals,
+            ..default()
+        };
+        let mut cases = Vec::new();
+        for kind in FormationKind::ALL {
+            cases.push((kind, FormationParams::default()));
+            cases.push((kind, drawn_up(None, Some(3), Intervals::Close)));
+            cases.push((kind, drawn_up(Some(4), None, Intervals::Open)));
+            cases.push((kind, drawn_up(Some(30), None, Intervals::Close)));
+        }
+        cases
+    }
+
+    #[test]
+    fn square_side_is_the_smallest_that_holds_everyone() {
+        let capacity = |side: usize, ranks: usize| -> usize {
+            (0..ranks)
+                .map_while(|ring| side.checked_sub(2 * ring).filter(|&m| m > 0))
+                .map(|m| if m == 1 { 1 } else { 4 * (m - 1) })
+                .sum()
+        };
+        for ranks in 1..=4 {
+            for total in 1..=300 {
+                let side = FormationKind::square_side(total, ranks);
+                let case = format!("{total} in {ranks} ranks: side {side}");
+                assert!(capacity(side, ranks) >= total, "{case}");
+                assert!(capacity(side - 1, ranks) < total, "{case}");
+            }
+        }
+    }
+
+    #[test]
+    fn footprint_encloses_slots_around_their_centroid() {
+        for (kind, params) in kind_cases() {
+            for total in [1, 5, 9, 10, 23] {
+                let offsets: Vec<Vec3> =
+                    (0..total).map(|i| kind.offset(i, total, &params)).collect();
+                let min = offsets.iter().copied().reduce(Vec3::min).unwrap();
+                let max = offsets.iter().copied().reduce(Vec3::max).unwrap();
+                let footprint = kind.footprint(total, &params);
+                let size = Vec2::new(footprint.width, footprint.depth);
+                let bounds = Vec2::new(max.x - min.x, max.z - min.z) + params.pitch();
+                let case = format!("{kind:?} {params:?} x{total}: {footprint:?}");
+                if kind == FormationKind::Ring {
+                    // Slots sit on the circle, not always at its extremes.
+                    assert!(size.cmpge(bounds - 1e-4).all(), "{case}");
+                    continue;
+                }
+                assert!(size.distance(bounds) < 1e-4, "{case}");
+                let middle = (min + max) / 2.0;
+                assert!(footprint.center.distance(middle) < 1e-4, "{case}");
+            }
+        }
+        // The origin is the slot centroid: a wedge's sits toward its broad
+        // rank, off the middle of its rectangle.
+        let params = FormationParams::default();
+        let wedge: Vec3 = (0..10)
+            .map(|i| FormationKind::Wedge.offset(i, 10, &params))
+            .sum();
+        assert!(wedge.length() < 1e-4, "wedge centroid at {wedge:?}");
+        assert!(FormationKind::Wedge.footprint(10, &params).center.z < -0.5);
+    }
+
+    #[test]
+    fn every_kind_gives_each_member_its_own_slot() {
+        for (kind, params) in kind_cases() {
+            for total in [1, 7, 24, 50] {
+                let offsets: Vec<Vec3> =
+                    (0..total).map(|i| kind.offset(i, total, &params)).collect();
+                for (i, a) in offsets.iter().enumerate() {
+                    for b in &offsets[i + 1..] {
+                        assert!(
+                            a.distance(*b) > 0.25 * params.interval,
+                            "{kind:?} {params:?} x{total}: slots {a:?} and {b:?} collide"
+                        );
+                    }
+                }
+            }
+        }
+        // Each face of a hollow square looks away from the center.
+        let params = FormationParams::default();
+        let square = FormationKind::HollowSquare;
+        for i in 0..40 {
+            let (facing, offset) = (square.facing(i, 40, &params), square.offset(i, 40, &params));
+            assert!(facing.dot(offset) > 0.0, "square slot {i} faces inward");
+        }
+    }
+
+    #[test]
+    fn open_order_extends_intervals_and_keeps_slots() {
+        let mut app = test_app();
+        let positions: Vec<Vec3> = (0..9)
+            .map(|i| Vec3::new((i % 3) as f32 * 2.0 - 2.0, 0.0, (i / 3) as f32 * 2.0 - 2.0))
+            .collect();
+        let formation = spawn_formation(&mut app, &positions);
+        for _ in 0..10 {
+            tick(&mut app, 1.0 / 60.0);
+        }
+        let slots = |world: &mut World| {
+            let mut query = world.query::<(Entity, &FormationSlot)>();
+            let mut slots: Vec<(Entity, usize)> =
+                query.iter(world).map(|(e, s)| (e, s.0)).collect();
+            slots.sort();
+            slots
+        };
+        let before = slots(app.world_mut());
+        let close = app.world().get::<Formation>(formation).unwrap().footprint;
+
+        app.world_mut()
+            .get_mut::<Formation>(formation)
+            .unwrap()
+            .tasks
+            .push_back(FormationOrder::Intervals(Intervals::Open));
+        for _ in 0..300 {
+            tick(&mut app, 1.0 / 60.0);
+        }
+
+        let world = app.world_mut();
+        assert_eq!(slots(world), before, "opening order must not re-map slots");
+        let formation = world.get::<Formation>(formation).unwrap();
+        assert_eq!(formation.params.intervals, Intervals::Open);
+        assert!(formation.tasks.is_empty(), "the order finishes at once");
+        assert!((formation.footprint.width - OPEN_ORDER_FACTOR * close.width).abs() < 1e-4);
+        // The corner members stand a full open interval out from the center.
+        let mut query = world.query_filtered::<&Transform, With<MemberOf>>();
+        let spread = query
+            .iter(world)
+            .map(|t| t.translation.x.abs())
+            .fold(0.0, f32::max);
+        assert!(spread > 3.0, "members did not step out: widest at {spread}");
+    }
+
+    #[test]
+    fn countermarch_leads_with_front_rank_and_about_face_keeps_ground() {
+        let mut app = test_app();
+        let positions: Vec<Vec3> = (0..9)
+            .map(|i| Vec3::new((i % 3) as f32 * 2.0 - 2.0, 0.0, (i / 3) as f32 * 2.0 - 2.0))
+            .collect();
+        let formation = spawn_formation(&mut app, &positions);
+        app.world_mut().get_mut::<Formation>(formation).unwrap().dir = Vec3::Z;
+        for _ in 0..300 {
+            tick(&mut app, 1.0 / 60.0);
+        }
+        let members = |world: &mut World| {
+            let mut query = world.query::<(Entity, &FormationSlot, &Transform)>();
+            let mut members: Vec<(Entity, usize, Vec3)> = query
+                .iter(world)
+                .map(|(e, s, t)| (e, s.0, t.translation))
+                .collect();
+            members.sort_by_key(|&(e, _, _)| e);
+            members
+        };
+        let roles = app
+            .world()
+            .get::<Formation>(formation)
+            .unwrap()
+            .rank_roles(9);
+        let front = |members: &[(Entity, usize, Vec3)]| {
+            let (mut front, mut rest) = (f32::INFINITY, f32::NEG_INFINITY);
+            for &(_, slot, pos) in members {
+                if matches!(roles[slot], RankRole::FrontRank | RankRole::Officer) {
+                    front = front.min(pos.z);
+                } else {
+                    rest = rest.max(pos.z);
+                }
+            }
+            (front, rest)
+        };
+        let before = members(app.world_mut());
+        let (front_z, rest_z) = front(&before);
+        assert!(front_z > rest_z, "front rank should lead along +Z");
+
+        // Countermarch: facing -Z, the same men still in front.
+        app.world_mut()
+            .get_mut::<Formation>(formation)
+            .unwrap()
+            .tasks
+            .push_back(FormationOrder::Countermarch);
+        for _ in 0..900 {
+            tick(&mut app, 1.0 / 60.0);
+        }
+        let marched = members(app.world_mut());
+        let slots = |members: &[(Entity, usize, Vec3)]| -> Vec<(Entity, usize)> {
+            members.iter().map(|&(e, slot, _)| (e, slot)).collect()
+        };
+        assert_eq!(slots(&marched), slots(&before), "countermarch keeps slots");
+        let (mut front_max, mut rest_min) = (f32::NEG_INFINITY, f32::INFINITY);
+        for &(_, slot, pos) in &marched {
+            if matches!(roles[slot], RankRole::FrontRank | RankRole::Officer) {
+                front_max = front_max.max(pos.z);
+            } else {
+                rest_min = rest_min.min(pos.z);
+            }
+        }
+        assert!(
+            front_max < rest_min,
+            "front rank should lead along -Z: {front_max} vs {rest_min}"
+        );
+
+        // About-face: facing +Z again, nobody moves, roles stay put.
+        app.world_mut()
+            .get_mut::<Formation>(formation)
+            .unwrap()
+            .tasks
+            .push_back(FormationOrder::AboutFace);
+        for _ in 0..300 {
+            tick(&mut app, 1.0 / 60.0);
+        }
+        let world = app.world_mut();
+        let faced = members(world);
+        assert_eq!(slots(&faced), slots(&before), "about-face keeps slots");
+        for (&(_, _, a), &(_, _, b)) in marched.iter().zip(&faced) {
+            assert!(a.distance(b) < 0.5, "member moved from {a:?} to {b:?}");
+        }
+        let formation_ref = world.get::<Formation>(formation).unwrap();
+        assert!(formation_ref.faced_about && formation_ref.dir.z > 0.99);
+        assert_eq!(formation_ref.rank_roles(9), roles);
+
+        // A march off at a slight angle wheels: slots and roles survive.
+        let facing = Vec3::new(0.3, 0.0, 1.0).normalize();
+        let com = center_of_mass(app.world_mut(), formation);
+        app.world_mut()
+            .get_mut::<Formation>(formation)
+            .unwrap()
+            .tasks
+            .push_back(FormationOrder::Move {
+                pos: com + facing * 4.0,
+                facing_dir: facing,
+                pace: Pace::default(),
+            });
+        for _ in 0..300 {
+            tick(&mut app, 1.0 / 60.0);
+        }
+        let world = app.world_mut();
+        assert_eq!(slots(&members(world)), slots(&before), "wheel keeps slots");
+        assert_eq!(
+            world.get::<Formation>(formation).unwrap().rank_roles(9),
+            roles
+        );
+    }
+
+    #[test]
+    fn overlapping_holding_formations_sidestep_apart() {
+        let mut app = test_app();
+        let grid = |x0: f32| -> Vec<Vec3> {
+            (0..9)
+                .map(|i| Vec3::new(x0 + (i % 3) as f32 * 2.0, 0.0, (i / 3) as f32 * 2.0))
+                .collect()
+        };
+        // Two 3x3 grids (6x6 footprints) with their middle files interleaved.
+        let a = spawn_formation(&mut app, &grid(0.0));
+        let b = spawn_formation(&mut app, &grid(3.0));
+        for _ in 0..900 {
+            tick(&mut app, 1.0 / 60.0);
+        }
+
+        let world = app.world_mut();
+        let footprint = |world: &World, f: Entity| {
+            world
+                .get::<Formation>(f)
+                .unwrap()
+                .footprint
+                .inflated(FORMATION_CLEARANCE / 4.0)
+        };
+        let (fa, fb) = (footprint(world, a), footprint(world, b));
+        let (pa, pb) = (
+            world.get::<Transform>(a).unwrap().translation,
+            world.get::<Transform>(b).unwrap().translation,
+        );
+        assert!(
+            fa.penetration(pa, Quat::IDENTITY, &fb, pb, Quat::IDENTITY)
+                .is_none(),
+            "formations at {pa:?} and {pb:?} still overlap"
+        );
+        assert!(pa.x < pb.x, "each should step away from the other");
+        for f in [a, b] {
+            let tasks = &world.get::<Formation>(f).unwrap().tasks;
+            assert!(tasks.is_empty(), "sidestep should have finished: {tasks:?}");
+        }
+    }
+
+    #[test]
+    fn organized_hierarchy_marches_on_orders_to_the_top() {
+        let mut app = test_app();
+        let boids: Vec<(Entity, Vec3)> = (0..16)
+            .map(|i| {
+                let pos = Vec3::new((i % 4) as f32 * 2.0 - 3.0, 0.0, (i / 4) as f32 * 2.0 - 3.0);
+                let boid = app
+                    .world_mut()
+                    .spawn((
+                        Transform::from_translation(pos),
+                        Velocity::default(),
+                        TrackedByTree,
+                        Target::default(),
+                    ))
+                    .id();
+                (boid, pos)
+            })
+            .collect();
+        let config = CommandHierarchy {
+            auto_organize: true,
+            company_size: 4,
+            battalion_size: 2,
+        };
+        let top = organize_hierarchy(&mut app.world_mut().commands(), &boids, Faction(0), &config);
+        app.world_mut().flush();
+
+        // 16 boids -> 4 companies of 4 -> 2 battalions of 2 -> top.
+        let world = app.world();
+        let battalions: Vec<Entity> = world.get::<Formations>(top).unwrap().iter().collect();
+        assert_eq!(battalions.len(), 2);
+        for &battalion in &battalions {
+            let companies = world.get::<Formations>(battalion).unwrap();
+            assert_eq!(companies.len(), 2);
+            for company in companies.iter() {
+                assert_eq!(world.get::<Members>(company).unwrap().len(), 4);
+            }
+            // A battalion starts where its own companies stand, not at the
+            // centroid of the whole group.
+            let center = companies
+                .iter()
+                .map(|company| world.get::<Transform>(company).unwrap().translation)
+                .sum::<Vec3>()
+                / companies.len() as f32;
+            let origin = world.get::<Transform>(battalion).unwrap().translation;
+            assert!(
+                origin.distance(center) < 1e-4,
+                "battalion at {origin:?}, its companies around {center:?}"
+            );
+        }
+        let spread = world.get::<Transform>(battalions[0]).unwrap().translation
+            - world.get::<Transform>(battalions[1]).unwrap().translation;
+        assert!(
+            spread.length() > 1.0,
+            "battalions should not share one origin"
+        );
+
+        for _ in 0..10 {
+            tick(&mut app, 1.0 / 60.0);
+        }
+        let dest = Vec3::new(50.0, 0.0, 30.0);
+        app.world_mut()
+            .get_mut::<Formation>(top)
+            .unwrap()
+            .tasks
+            .push_back(FormationOrder::Move {
+                pos: dest,
+                facing_dir: Vec3::new(0.0, 0.0, 1.0),
+                pace: Pace::default(),
+            });
+        for _ in 0..1200 {
+            tick(&mut app, 1.0 / 60.0);
+        }
+
+        let world = app.world();
+        let com = boids
+            .iter()
+            .map(|&(boid, _)| world.get::<Transform>(boid).unwrap().translation)
+            .sum::<Vec3>()
+            / boids.len() as f32;
+        assert!(
+            com.distance(dest) < 5.0,
+            "boids' center of mass {com:?} did not reach {dest:?}"
+        );
+    }
+
+    #[test]
+    fn lod_round_trip_unloads_and_reloads_members_at_their_slots() {
+        let mut app = test_app();
+        let formation = spawn_formation(
+            &mut app,
+            &[Vec3::new(-2.0, 0.0, 0.0), Vec3::ZERO, Vec3::new(2.0, 0.0, 0.0)],
+        );
+        let viewer = app
+            .world_mut()
+            .spawn((LodViewer, GlobalTransform::default()))
+            .id();
+        for _ in 0..10 {
+            tick(&mut app, 1.0 / 60.0);
+        }
+        let members = members_of(app.world_mut(), formation);
+        let slots_before: Vec<usize> = members
+            .iter()
+            .map(|&m| app.world().get::<FormationSlot>(m).unwrap().0)
+            .collect();
+
+        // Out of range: members stop simulating, the formation takes over.
+        *app.world_mut().get_mut::<GlobalTransform>(viewer).unwrap() =
+            GlobalTransform::from_translation(Vec3::new(1000.0, 0.0, 0.0));
+        tick(&mut app, 1.0 / 60.0);
+        tick(&mut app, 1.0 / 60.0);
+        for &member in &members {
+            assert!(app.world().get::<Velocity>(member).is_none());
+            assert_eq!(
+                app.world().get::<Visibility>(member),
+                Some(&Visibility::Hidden)
+            );
+        }
+        assert!(app.world().get::<Velocity>(formation).is_some());
+        assert!(
+            app.world().get::<TrackedByTree>(formation).is_some(),
+            "an unloaded formation stands in the tree for its boids"
+        );
+
+        let dest = Vec3::new(20.0, 0.0, 20.0);
+        app.world_mut()
+            .get_mut::<Formation>(formation)
+            .unwrap()
+            .tasks
+            .push_back(FormationOrder::Move {
+                pos: dest,
+                facing_dir: Vec3::new(0.0, 0.0, 1.0),
+                pace: Pace::default(),
+            });
+        for _ in 0..600 {
+            tick(&mut app, 1.0 / 60.0);
+        }
+
+        // Back in range: members reappear at their slots around the
+        // formation's new position, with their old slots.
+        *app.world_mut().get_mut::<GlobalTransform>(viewer).unwrap() =
+            GlobalTransform::default();
+        tick(&mut app, 1.0 / 60.0);
+        tick(&mut app, 1.0 / 60.0);
+        let world = app.world_mut();
+        assert_eq!(members_of(world, formation).len(), 3, "member count persists");
+        for (&member, slot) in members.iter().zip(slots_before) {
+            assert!(world.get::<Velocity>(member).is_some());
+            assert_eq!(world.get::<FormationSlot>(member).unwrap().0, slot);
+            let pos = world.get::<Transform>(member).unwrap().translation;
+            assert!(
+                pos.distance(dest) < 5.0,
+                "member {member:?} reloaded at {pos:?}, far from {dest:?}"
+            );
+        }
+        assert!(
+            world.get::<Velocity>(formation).is_none(),
+            "a reloaded formation is a container again"
+        );
+        assert!(world.get::<TrackedByTree>(formation).is_none());
+    }
+
+    #[test]
+    fn lod_keeps_formations_the_enemy_may_fight_loaded() {
+        let mut app = test_app();
+        let formation = spawn_formation(
+            &mut app,
+            &[Vec3::new(-2.0, 0.0, 0.0), Vec3::ZERO, Vec3::new(2.0, 0.0, 0.0)],
+        );
+        let enemy = app
+            .world_mut()
+            .spawn((
+                Transform::from_xyz(0.0, 0.0, VIEW_RANGE - 5.0),
+                TrackedByTree,
+                Faction(1),
+            ))
+            .id();
+        app.world_mut().spawn((
+            LodViewer,
+            GlobalTransform::from_translation(Vec3::new(1000.0, 0.0, 0.0)),
+        ));
+        for _ in 0..10 {
+            tick(&mut app, 1.0 / 60.0);
+        }
+        assert!(
+            app.world().get::<LodUnloaded>(formation).is_none(),
+            "an enemy within view range keeps the formation loaded"
+        );
+
+        // Nothing near: the formation unloads like any far from the camera.
+        app.world_mut().despawn(enemy);
+        for _ in 0..10 {
+            tick(&mut app, 1.0 / 60.0);
+        }
+        assert!(app.world().get::<LodUnloaded>(formation).is_some());
+
+        // Seen from afar: it reloads although the camera stays away.
+        app.world_mut().resource_mut::<FogOfWar>().update(
+            2,
+            [],
+            [(Faction(1), Vec3::new(0.0, 0.0, 150.0), 200.0)],
+        );
+        tick(&mut app, 1.0 / 60.0);
+        tick(&mut app, 1.0 / 60.0);
+        assert!(app.world().get::<LodUnloaded>(formation).is_none());
+        for member in members_of(app.world_mut(), formation) {
+            assert!(app.world().get::<TrackedByTree>(member).is_some());
+        }
+    }
+
+    #[test]
+    fn holding_formation_with_scrambled_slots_redresses() {
+        let mut app = test_app();
+        let positions: Vec<Vec3> = (0..3)
+            .flat_map(|r| {
+                (0..3).map(move |c| Vec3::new(c as f32 * 2.0 - 2.0, 0.0, r as f32 * 2.0 - 2.0))
+            })
+            .collect();
+        let formation = spawn_formation(&mut app, &positions);
+        for _ in 0..10 {
+            tick(&mut app, 1.0 / 60.0);
+        }
+        let cohesion = *app.world().get::<Cohesion>(formation).unwrap();
+        assert_eq!(cohesion.in_place, 1.0, "members start on their slots");
+
+        // Scramble: point-mirror every slot, so all but the center member
+        // are assigned to a slot across the formation. The assignment stays
+        // valid (in-range, unique), so only cohesion can catch it.
+        let original: Vec<(Entity, usize)> = {
+            let world = app.world_mut();
+            let mut query = world.query::<(Entity, &FormationSlot)>();
+            query.iter(world).map(|(e, s)| (e, s.0)).collect()
+        };
+        for &(member, slot) in &original {
+            app.world_mut()
+                .entity_mut(member)
+                .insert(FormationSlot(8 - slot));
+        }
+        tick(&mut app, 1.0 / 60.0);
+
+        let world = app.world();
+        let cohesion = world.get::<Cohesion>(formation).unwrap();
+        assert!(
+            cohesion.is_poor(),
+            "scrambled slots must read as poor cohesion: {cohesion:?}"
+        );
+        assert!(cohesion.max_straggler > 5.0);
+        assert!(
+            world.get::<Formation>(formation).unwrap().tasks.is_empty(),
+            "the automatic Reform should have executed and popped"
+        );
+        for (member, slot) in original {
+            assert_eq!(
+                world.get::<FormationSlot>(member).unwrap().0,
+                slot,
+                "re-dress must map member {member:?} back onto the slot it stands on"
+            );
+        }
+    }
+
+    #[test]
+    fn init_formation_speed_derives_from_slowest_subformation() {
+        let mut app = test_app();
+        // Sub-formation with boid members initializes to MAX_VELOCITY on the
+        // first tick; the parent must wait for that before deriving its own
+        // speed (a pending child still reports the default).
+        let sub = spawn_formation(
+            &mut app,
+            &[Vec3::new(-1.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0)],
+        );
+        let parent = app
+            .world_mut()
+            .spawn((Formation::default(), Transform::default()))
+            .id();
+        app.world_mut().entity_mut(sub).insert(FormationOf(parent));
+        tick(&mut app, 1.0 / 60.0);
+        assert!(
+            app.world().get::<NeedsSpeedInit>(parent).is_some(),
+            "parent must wait while the sub-formation is pending"
+        );
+
+        // Slow the sub below the default and let the parent derive.
+        app.world_mut().get_mut::<Formation>(sub).unwrap().max_speed = 3.0;
+        tick(&mut app, 1.0 / 60.0);
+
+        let world = app.world_mut();
+        assert_eq!(world.get::<Formation>(parent).unwrap().max_speed, 3.0);
+        assert!(world.get::<NeedsSpeedInit>(parent).is_none());
+    }
+
+    #[test]
+    fn init_formation_speed_takes_the_slowest_unit_stats() {
+        let mut app = test_app();
+        let formation = spawn_formation(
+            &mut app,
+            &[Vec3::new(-1.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0)],
+        );
+        let slow = members_of(app.world_mut(), formation)[0];
+        app.world_mut().entity_mut(slow).insert(UnitStats {
+            max_speed: 4.0,
+            ..default()
+        });
+        tick(&mut app, 1.0 / 60.0);
+        let max_speed = app.world().get::<Formation>(formation).unwrap().max_speed;
+        assert_eq!(max_speed, 4.0);
+    }
+
+    #[test]
+    fn rank_from_front_follows_an_about_face() {
+        let mut formation = Formation {
+            kind: FormationKind::Grid,
+            params: FormationParams {
+                files: Some(3),
+                ..default()
+            },
+            ..default()
+        };
+        let front: Vec<usize> = (0..9)
+            .filter(|&i| formation.rank_from_front(i, 9) == (0, 3))
+            .collect();
+        assert_eq!(front.len(), 3, "one rank of three files in front");
+        formation.faced_about = true;
+        for i in front {
+            assert_eq!(
+                formation.rank_from_front(i, 9),
+                (2, 3),
+                "slot {i} now at the rear"
+            );
+        }
+    }
+
+    #[test]
+    fn rank_role_of_one_slot_matches_the_roles_of_all() {
+        let formation = Formation {
+            kind: FormationKind::Grid,
+            params: FormationParams {
+                files: Some(4),
+                ..default()
+            },
+            ..default()
+        };
+        let roles = formation.rank_roles(14);
+        assert!(roles.contains(&RankRole::Officer));
+        assert!(roles.contains(&RankRole::FileCloser));
+        for (i, &role) in roles.iter().enumerate() {
+            assert_eq!(formation.rank_role(i, 14), Some(role), "slot {i}");
+        }
+        assert_eq!(formation.rank_role(14, 14), None);
+    }
+
+    #[test]
+    fn organized_formations_fight_for_the_faction_they_are_given() {
+        let mut app = test_app();
+        let boids: Vec<(Entity, Vec3)> = (0..12)
+            .map(|i| {
+                let pos = Vec3::new(i as f32, 0.0, 0.0);
+                let boid = app
+                    .world_mut()
+                    .spawn((Transform::from_translation(pos), Faction(1)))
+                    .id();
+                (boid, pos)
+            })
+            .collect();
+        let config = CommandHierarchy {
+            auto_organize: true,
+            company_size: 2,
+            battalion_size: 2,
+        };
+        organize_hierarchy(&mut app.world_mut().commands(), &boids, Faction(1), &config);
+        app.world_mut().flush();
+
+        // Every level from the start, not once its members are counted.
+        let world = app.world_mut();
+        let mut query = world.query_filtered::<&Faction, With<Formation>>();
+        let factions: Vec<Faction> = query.iter(world).copied().collect();
+        // 6 companies, 3 battalions, the top.
+        assert_eq!(factions.len(), 10);
+        assert!(factions.iter().all(|&f| f == Faction(1)));
+    }
+
+    #[test]
+    fn segment_entry_finds_where_a_path_meets_the_footprint() {
+        let footprint = Footprint {
+            width: 4.0,
+            depth: 2.0,
+            center: Vec3::ZERO,
+        };
+        let origin = Vec3::new(10.0, 0.0, 0.0);
+        let entry = footprint.segment_entry(
+            origin,
+            Quat::IDENTITY,
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(20.0, 0.0, 0.0),
+        );
+        assert!((entry.unwrap() - 0.4).abs() < 1e-5, "{entry:?}");
+        let passing_by = footprint.segment_entry(
+            origin,
+            Quat::IDENTITY,
+            Vec3::new(0.0, 0.0, 1.5),
+            Vec3::new(20.0, 0.0, 1.5),
+        );
+        assert_eq!(passing_by, None);
+        // Turned a quarter, the long side lies across the path.
+        let turned = footprint.segment_entry(
+            origin,
+            Quat::from_rotation_y(std::f32::consts::FRAC_PI_2),
+            Vec3::new(0.0, 0.0, 1.5),
+            Vec3::new(20.0, 0.0, 1.5),
+        );
+        assert!((turned.unwrap() - 0.45).abs() < 1e-5, "{turned:?}");
+        let short =
+            footprint.segment_entry(origin, Quat::IDENTITY, Vec3::ZERO, Vec3::new(5.0, 0.0, 0.0));
+        assert_eq!(short, None, "stops short of it");
+        let inside = footprint.segment_entry(origin, Quat::IDENTITY, origin, Vec3::ZERO);
+        assert_eq!(inside, Some(0.0));
+    }
+
+    #[test]
+    fn footprint_contains_points_in_its_rotated_frame() {
+        let footprint = Footprint {
+            width: 10.0,
+            depth: 2.0,
+            center: Vec3::new(0.0, 0.0, -1.0),
+        };
+        let origin = Vec3::new(5.0, 0.0, 5.0);
+        // Facing +X: the width runs along Z, the depth (behind the origin)
+        // toward -X.
+        let rotation = Quat::from_rotation_y(std::f32::consts::FRAC_PI_2);
+        assert!(footprint.contains(origin, rotation, origin + Vec3::new(-1.5, 0.0, 4.5)));
+        assert!(!footprint.contains(origin, rotation, origin + Vec3::new(1.5, 0.0, 0.0)));
+        assert!(!footprint.contains(origin, rotation, origin + Vec3::new(-1.0, 0.0, 5.5)));
     }
 
     /// Members of a formation, queried from the world (helper).
@@ -1188,6 +3635,7 @@ mod tests {
             .push_back(FormationOrder::Move {
                 pos: dest,
                 facing_dir: Vec3::new(0.0, 0.0, 1.0),
+                pace: Pace::default(),
             });
         // Unload the members: they keep existing but stop simulating (no
         // Velocity), so nothing below the formation is simulated.
@@ -1259,6 +3707,7 @@ mod tests {
             .push_back(FormationOrder::Move {
                 pos: dest,
                 facing_dir: Vec3::new(0.0, 0.0, 1.0),
+                pace: Pace::default(),
             });
 
         // The parent commands the sub through its task queue: a one-wide
@@ -1,15 +1,716 @@
+use crate::boid::{Boid, BoidBundle, UnitStats, UnitType};
+use crate::combat::{CombatEvent, Health, MeleeAttack, strike};
+use crate::faction::Faction;
+use crate::formations::{
+    Cohesion, Footprint, Formation, FormationOrder, FormationSlot, Intervals, MemberOf, Members,
+    Pace,
+};
+use crate::kinematics::{MAX_ACCELERATION, NNTree, TrackedByTree, Velocity};
+use crate::player::Selected;
+use crate::resources::{Materials, Meshes};
+use crate::target::Target;
 use bevy::prelude::*;
+use bevy_spatial::SpatialAccess;
 
-#[derive(Component, Default)]
+/// A mounted boid. Horses will not ride into a [braced](is_braced)
+/// formation unless `blinkered` (see [`avoid_tight_formations`]), hit
+/// whatever they ride down at speed (see [`charge_impact_system`]), and
+/// throw their rider when stopped short (see [`throw_rider`]).
+#[derive(Component)]
 pub struct Horse {
+    /// Where the horse is going while [`Target`] holds a detour waypoint.
     pub(crate) target: Vec3,
+    /// The detour waypoint last put in `Target`, if any.
+    waypoint: Option<Vec3>,
+    /// Blinkered horses do not see the bayonets: they ride straight on.
+    pub blinkered: bool,
+    /// Still carries its rider.
+    pub mounted: bool,
+    /// Speed at the end of the last frame, for [`throw_rider`].
+    last_speed: f32,
+    /// Seconds until the next [charge impact](charge_impact_system).
+    impact_ready_in: f32,
 }
 
-pub fn avoid_tight_formations(mut query: Query<(&Transform)>) {
-    //todo: insert waypoints to avoid tight formations, if not blinded
-    //see waterloo film
+impl Default for Horse {
+    fn default() -> Self {
+        Self {
+            target: Vec3::ZERO,
+            waypoint: None,
+            blinkered: false,
+            mounted: true,
+            last_speed: 0.0,
+            impact_ready_in: 0.0,
+        }
+    }
 }
 
-pub fn throw_rider() {
-    //todo: throw rider if decelerated quickly
+/// Horses keep this far clear of a braced formation's footprint.
+pub const AVOID_MARGIN: f32 = 3.0;
+
+/// Fraction of its members in place (see [`Cohesion::in_place`]) above
+/// which a formation stands braced.
+pub const BRACED_IN_PLACE: f32 = 0.8;
+
+/// Speed lost within a frame, without being reined in and beyond what the
+/// horse sheds braking ([`MAX_ACCELERATION`]), that throws the rider:
+/// more than riding a man down costs even at full speed.
+pub const THROW_SPEED_LOSS: f32 = 6.0;
+
+/// Damage a thrown rider takes from the fall.
+pub const THROW_DAMAGE: f32 = 20.0;
+
+/// Below this speed a horse rides nobody down.
+pub const IMPACT_MIN_SPEED: f32 = 8.0;
+
+/// A horse rides down enemies this close.
+pub const IMPACT_REACH: f32 = 1.5;
+
+/// Impact damage per unit of the horse's speed.
+pub const IMPACT_DAMAGE_PER_SPEED: f32 = 3.0;
+
+/// Fraction of its speed a horse loses riding a man down ...
+const IMPACT_SPEED_LOSS: f32 = 0.25;
+
+/// ... and riding into a braced formation: enough to throw the rider at a
+/// gallop.
+const BRACED_IMPACT_SPEED_LOSS: f32 = 0.7;
+
+/// Seconds between a horse's impacts.
+const IMPACT_COOLDOWN: f32 = 0.5;
+
+/// A charge quickens from double-quick to the full charge this far from
+/// its target ...
+pub const CHARGE_SPRINT_DISTANCE: f32 = 40.0;
+
+/// ... and from the quick march to double-quick this far.
+pub const CHARGE_TROT_DISTANCE: f32 = 100.0;
+
+/// `formation` stands braced: infantry in close order holding its ground,
+/// members in place. Horses shy from it. Its unit type is its first
+/// member's, members being gathered from one type.
+pub fn is_braced(
+    formation: &Formation,
+    cohesion: &Cohesion,
+    members: Option<&Members>,
+    q_stats: &Query<&UnitStats>,
+) -> bool {
+    let holding = matches!(
+        formation.tasks.front(),
+        None | Some(FormationOrder::Hold { .. } | FormationOrder::FireAt { .. })
+    );
+    let infantry = members.and_then(|m| m.iter().next()).is_some_and(|m| {
+        q_stats.get(m).map_or(UnitType::Infantry, |s| s.unit_type) == UnitType::Infantry
+    });
+    holding
+        && infantry
+        && formation.params.intervals == Intervals::Close
+        && cohesion.in_place >= BRACED_IN_PLACE
+}
+
+/// Steer horses around braced formations (see [`is_braced`]) in their way.
+/// A horse whose path to its destination crosses one (kept
+/// [`AVOID_MARGIN`] clear) rides for a waypoint off its side instead,
+/// toward whichever side it already is on; a horse whose destination lies
+/// inside one pulls up at its edge. The destination is kept in
+/// [`Horse::target`] and restored once the way is clear; a new `Target`
+/// replaces it. Blinkered horses, and a formation's own horses, ride
+/// straight on. Runs after the targets are set each frame.
+#[allow(clippy::type_complexity)]
+pub fn avoid_tight_formations(
+    q_formations: Query<(Entity, &Transform, &Formation, &Cohesion, Option<&Members>)>,
+    q_stats: Query<&UnitStats>,
+    mut q_horses: Query<(&Transform, &mut Horse, &mut Target, Option<&MemberOf>)>,
+) {
+    let braced: Vec<(Entity, Vec3, Quat, Footprint)> = q_formations
+        .iter()
+        .filter(|(_, _, formation, cohesion, members)| {
+            is_braced(formation, cohesion, *members, &q_stats)
+        })
+        .map(|(entity, transform, formation, _, _)| {
+            let footprint = formation.footprint.inflated(AVOID_MARGIN);
+            (entity, transform.translation, transform.rotation, footprint)
+        })
+        .collect();
+
+    for (transform, mut horse, mut target, member_of) in &mut q_horses {
+        let destination = match horse.waypoint {
+            Some(waypoint) if target.pos == waypoint => horse.target,
+            _ => target.pos,
+        };
+        horse.target = destination;
+        let pos = transform.translation.with_y(0.0);
+        let detour = (!horse.blinkered)
+            .then(|| {
+                braced
+                    .iter()
+                    .filter(|(entity, ..)| member_of.is_none_or(|m| m.0 != *entity))
+                    .filter_map(|(_, origin, rotation, footprint)| {
+                        let entry =
+                            footprint.segment_entry(*origin, *rotation, pos, destination)?;
+                        (entry > 0.0).then_some((entry, origin, rotation, footprint))
+                    })
+                    .min_by(|(a, ..), (b, ..)| a.total_cmp(b))
+            })
+            .flatten()
+            .map(|(entry, origin, rotation, footprint)| {
+                if footprint.contains(*origin, *rotation, destination) {
+                    return pos.lerp(destination, entry);
+                }
+                let center = footprint.world_center(*origin, *rotation).with_y(0.0);
+                let ahead = (destination - pos).with_y(0.0).normalize_or_zero();
+                let across = Vec3::new(-ahead.z, 0.0, ahead.x);
+                let side = if (pos - center).dot(across) < 0.0 {
+                    -1.0
+                } else {
+                    1.0
+                };
+                let radius = Vec2::new(footprint.width, footprint.depth).length() / 2.0;
+                center + across * side * radius
+            });
+        match detour {
+            Some(waypoint) => {
+                target.pos = waypoint;
+                horse.waypoint = Some(waypoint);
+            }
+            None => {
+                if horse.waypoint.take().is_some() {
+                    target.pos = destination;
+                }
+            }
+        }
+    }
+}
+
+/// A rider is thrown when the horse loses more than [`THROW_SPEED_LOSS`]
+/// within a frame without being reined in (its wanted speed is still above
+/// what it is left with): ridden into a wall, or stopped dead by a braced
+/// formation. Those stop it at once, so the loss is a speed, not a rate,
+/// and the same at any frame rate. Runs in `Update` after the velocity is
+/// integrated and walls are hit ([`move_step`](crate::kinematics::move_step),
+/// [`hard_collisions`](crate::boid::hard_collisions)), so it measures
+/// every change of a frame, impacts in `FixedUpdate` included.
+///
+/// The rider lands as a free infantry boid of the same faction, hurt by
+/// the fall ([`THROW_DAMAGE`]). The riderless horse is no one's: it leaves
+/// its formation (`MemberOf` together with `FormationSlot`, see
+/// [`FormationSlot`]) and the selection, and loses its boid, faction,
+/// health, arms and place in the spatial index, so nothing selects,
+/// orders, counts or strikes it. It stands where it is.
+pub fn throw_rider(
+    time: Res<Time>,
+    mut q_horses: Query<(
+        Entity,
+        &Transform,
+        &Velocity,
+        &Faction,
+        &mut Horse,
+        &mut Target,
+    )>,
+    meshes: Res<Meshes>,
+    materials: Res<Materials>,
+    mut commands: Commands,
+) {
+    for (entity, transform, velocity, faction, mut horse, mut target) in &mut q_horses {
+        let speed = velocity.v.length();
+        let lost = horse.last_speed - speed;
+        horse.last_speed = speed;
+        let thrown = lost > THROW_SPEED_LOSS + MAX_ACCELERATION * time.delta_secs();
+        if !horse.mounted || !thrown || velocity.target_v <= speed {
+            continue;
+        }
+        horse.mounted = false;
+        let pos = transform.translation.with_y(0.0);
+        target.pos = pos;
+        commands.entity(entity).remove::<(
+            MemberOf,
+            FormationSlot,
+            Selected,
+            Boid,
+            Faction,
+            UnitStats,
+            Health,
+            MeleeAttack,
+            TrackedByTree,
+        )>();
+        let rider = commands
+            .spawn(BoidBundle::with_target(
+                Target {
+                    pos,
+                    dir: Vec3::ZERO,
+                    speed: None,
+                },
+                meshes.capsule.clone(),
+                materials.faction(*faction),
+            ))
+            .insert((
+                Transform::from_translation(transform.translation),
+                *faction,
+                Health {
+                    current: Health::default().max - THROW_DAMAGE,
+                    ..default()
+                },
+            ))
+            .id();
+        info!("[horse] {entity:?} threw its rider {rider:?} (lost {lost:.1} speed)");
+    }
+}
+
+/// A mounted horse at [`IMPACT_MIN_SPEED`] or faster rides down the
+/// nearest living enemy within [`IMPACT_REACH`], for damage scaled by its
+/// speed ([`IMPACT_DAMAGE_PER_SPEED`]), and loses some of that speed doing
+/// it - most of it against a [braced](is_braced) formation.
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+pub fn charge_impact_system(
+    time: Res<Time>,
+    tree: Res<NNTree>,
+    mut q_horses: Query<(Entity, &Transform, &Faction, &mut Horse, &mut Velocity)>,
+    mut q_targets: Query<(&Transform, &Faction, &mut Health, Option<&MemberOf>)>,
+    q_formations: Query<(&Formation, &Cohesion, Option<&Members>)>,
+    q_stats: Query<&UnitStats>,
+    mut events: MessageWriter<CombatEvent>,
+) {
+    let dt = time.delta_secs();
+    for (horse_entity, transform, faction, mut horse, mut velocity) in &mut q_horses {
+        horse.impact_ready_in = (horse.impact_ready_in - dt).max(0.0);
+        let speed = velocity.v.length();
+        if !horse.mounted || horse.impact_ready_in > 0.0 || speed < IMPACT_MIN_SPEED {
+            continue;
+        }
+        let pos = transform.translation;
+        let nearest = tree
+            .within_distance(pos, IMPACT_REACH)
+            .into_iter()
+            .filter_map(|(at, entity)| Some((entity?, at.distance(pos))))
+            .filter(|&(entity, _)| entity != horse_entity)
+            .filter(|&(entity, _)| {
+                q_targets
+                    .get(entity)
+                    .is_ok_and(|(_, f, h, _)| f != faction && !h.is_dead())
+            })
+            .min_by(|(_, a), (_, b)| a.total_cmp(b));
+        let Some((target, _)) = nearest else {
+            continue;
+        };
+
+        let (target_transform, target_faction, mut health, member_of) =
+            q_targets.get_mut(target).expect("target was just found");
+        let formation = member_of.map(|m| m.0);
+        let braced = formation
+            .and_then(|f| q_formations.get(f).ok())
+            .is_some_and(|(f, cohesion, members)| is_braced(f, cohesion, members, &q_stats));
+        strike(
+            &mut events,
+            horse_entity,
+            target,
+            IMPACT_DAMAGE_PER_SPEED * speed,
+            &mut health,
+            *target_faction,
+            formation,
+            target_transform.translation,
+        );
+        let loss = if braced {
+            BRACED_IMPACT_SPEED_LOSS
+        } else {
+            IMPACT_SPEED_LOSS
+        };
+        velocity.v *= 1.0 - loss;
+        horse.impact_ready_in = IMPACT_COOLDOWN;
+    }
+}
+
+/// Keep [`FormationOrder::Charge`] on its target: its position follows the
+/// target, and the pace quickens as the distance closes - quick march,
+/// double-quick inside [`CHARGE_TROT_DISTANCE`], the full charge inside
+/// [`CHARGE_SPRINT_DISTANCE`]. The order is dropped once the target is gone
+/// (or, for a boid, dead).
+pub fn charge_system(
+    mut q_formations: Query<(&mut Formation, &Transform)>,
+    q_positions: Query<&GlobalTransform, Or<(With<Formation>, With<Health>)>>,
+) {
+    for (mut formation, transform) in &mut q_formations {
+        let Some(FormationOrder::Charge { target, .. }) = formation.tasks.front().copied() else {
+            continue;
+        };
+        let Ok(target_pos) = q_positions.get(target).map(|t| t.translation()) else {
+            formation.tasks.pop_front();
+            continue;
+        };
+        let distance = transform.translation.distance(target_pos);
+        let pace = if distance < CHARGE_SPRINT_DISTANCE {
+            Pace::Charge
+        } else if distance < CHARGE_TROT_DISTANCE {
+            Pace::DoubleQuick
+        } else {
+            Pace::QuickMarch
+        };
+        if formation.pace != pace {
+            formation.pace = pace;
+        }
+        if let Some(FormationOrder::Charge { pos, .. }) = formation.tasks.front_mut() {
+            *pos = target_pos.with_y(0.0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kinematics::move_step;
+    use bevy_spatial::{AutomaticUpdate, TransformMode};
+    use std::time::Duration;
+
+    /// Fixed step the game runs impacts and throws at.
+    const STEP: f32 = 1.0 / 64.0;
+
+    fn test_app() -> App {
+        let mut app = App::new();
+        app.init_resource::<Time>()
+            .init_resource::<Meshes>()
+            .init_resource::<Materials>()
+            .add_message::<CombatEvent>()
+            .add_plugins(
+                AutomaticUpdate::<TrackedByTree>::new()
+                    .with_frequency(Duration::from_secs_f32(1.0 / 20.0))
+                    .with_transform(TransformMode::Transform),
+            );
+        app
+    }
+
+    fn tick(app: &mut App, dt: f32) {
+        app.world_mut()
+            .resource_mut::<Time>()
+            .advance_by(Duration::from_secs_f32(dt));
+        app.update();
+    }
+
+    /// A formation of one infantryman, standing in close order with a
+    /// `width` by `depth` footprint at `pos`: braced while it holds.
+    fn spawn_braced(app: &mut App, pos: Vec3, width: f32, depth: f32) -> (Entity, Entity) {
+        let formation = app
+            .world_mut()
+            .spawn((
+                Formation {
+                    footprint: Footprint {
+                        width,
+                        depth,
+                        center: Vec3::ZERO,
+                    },
+                    ..default()
+                },
+                Transform::from_translation(pos),
+                GlobalTransform::from_translation(pos),
+                Faction(1),
+            ))
+            .id();
+        let member = app
+            .world_mut()
+            .spawn((
+                Transform::from_translation(pos),
+                TrackedByTree,
+                Health::default(),
+                Faction(1),
+                MemberOf(formation),
+            ))
+            .id();
+        (formation, member)
+    }
+
+    fn spawn_horse(app: &mut App, pos: Vec3, destination: Vec3) -> Entity {
+        app.world_mut()
+            .spawn((
+                Horse::default(),
+                Transform::from_translation(pos),
+                Target {
+                    pos: destination,
+                    ..default()
+                },
+                Velocity::default(),
+                Faction(0),
+            ))
+            .id()
+    }
+
+    fn target_of(app: &App, horse: Entity) -> Vec3 {
+        app.world().get::<Target>(horse).unwrap().pos
+    }
+
+    #[test]
+    fn horses_ride_around_a_braced_formation_and_on_once_clear() {
+        let mut app = test_app();
+        app.add_systems(Update, avoid_tight_formations);
+        let (formation, _) = spawn_braced(&mut app, Vec3::ZERO, 10.0, 4.0);
+        let destination = Vec3::new(30.0, 0.0, 0.0);
+        let horse = spawn_horse(&mut app, Vec3::new(-30.0, 0.0, 1.0), destination);
+
+        tick(&mut app, STEP);
+        let waypoint = target_of(&app, horse);
+        assert!(waypoint.z > 4.0, "off the side it is on: {waypoint}");
+        assert_eq!(app.world().get::<Horse>(horse).unwrap().target, destination);
+
+        // Marching off, it no longer stands braced.
+        app.world_mut()
+            .get_mut::<Formation>(formation)
+            .unwrap()
+            .tasks
+            .push_back(FormationOrder::Move {
+                pos: Vec3::new(0.0, 0.0, 100.0),
+                facing_dir: Vec3::Z,
+                pace: Pace::default(),
+            });
+        tick(&mut app, STEP);
+        assert_eq!(target_of(&app, horse), destination);
+    }
+
+    #[test]
+    fn horses_pull_up_short_of_a_braced_formation_they_are_sent_into() {
+        let mut app = test_app();
+        app.add_systems(Update, avoid_tight_formations);
+        spawn_braced(&mut app, Vec3::ZERO, 10.0, 4.0);
+        let horse = spawn_horse(&mut app, Vec3::new(-30.0, 0.0, 0.0), Vec3::ZERO);
+        let blinkered = spawn_horse(&mut app, Vec3::new(-30.0, 0.0, 0.0), Vec3::ZERO);
+        app.world_mut()
+            .get_mut::<Horse>(blinkered)
+            .unwrap()
+            .blinkered = true;
+
+        tick(&mut app, STEP);
+        let edge = -(10.0 / 2.0 + AVOID_MARGIN);
+        assert!((target_of(&app, horse).x - edge).abs() < 1e-3);
+        assert_eq!(target_of(&app, blinkered), Vec3::ZERO, "sees no bayonets");
+    }
+
+    /// A mounted horse of a formation, at full gallop last step, now at
+    /// `speed` and still wanting `wanted`.
+    fn galloping_horse(app: &mut App, speed: f32, wanted: f32) -> (Entity, Entity) {
+        let formation = app.world_mut().spawn(Formation::default()).id();
+        let horse = spawn_horse(app, Vec3::new(5.0, 0.5, 5.0), Vec3::ZERO);
+        let mut entity = app.world_mut().entity_mut(horse);
+        entity.insert((
+            MemberOf(formation),
+            FormationSlot(0),
+            MeleeAttack::default(),
+            Velocity {
+                v: Vec3::X * speed,
+                target_v: wanted,
+                ..default()
+            },
+        ));
+        entity.get_mut::<Horse>().unwrap().last_speed = 15.0;
+        (formation, horse)
+    }
+
+    fn riders(app: &mut App) -> Vec<(Faction, f32)> {
+        let world = app.world_mut();
+        let mut query = world.query_filtered::<(&Faction, &Health), Without<Horse>>();
+        query.iter(world).map(|(f, h)| (*f, h.current)).collect()
+    }
+
+    #[test]
+    fn a_horse_stopped_dead_throws_its_rider() {
+        let mut app = test_app();
+        app.add_systems(Update, (move_step, throw_rider).chain());
+        let (_, horse) = galloping_horse(&mut app, 0.0, 15.0);
+        app.world_mut().entity_mut(horse).insert((
+            Boid::default(),
+            UnitStats::default(),
+            Health::default(),
+            TrackedByTree,
+        ));
+
+        tick(&mut app, STEP);
+        let entity = app.world().entity(horse);
+        assert!(!entity.get::<Horse>().unwrap().mounted);
+        assert!(!entity.contains::<MemberOf>());
+        assert!(!entity.contains::<FormationSlot>());
+        assert!(!entity.contains::<MeleeAttack>());
+        // No one's: nothing selects, orders, counts or strikes it.
+        assert!(!entity.contains::<Boid>());
+        assert!(!entity.contains::<Faction>());
+        assert!(!entity.contains::<UnitStats>());
+        assert!(!entity.contains::<Health>());
+        assert!(!entity.contains::<TrackedByTree>());
+        assert_eq!(riders(&mut app), [(Faction(0), 100.0 - THROW_DAMAGE)]);
+    }
+
+    #[test]
+    fn a_horse_reined_in_or_slowing_keeps_its_rider() {
+        let mut app = test_app();
+        app.add_systems(Update, (move_step, throw_rider).chain());
+        let (_, reined_in) = galloping_horse(&mut app, 0.0, 0.0);
+        let (_, slowing) = galloping_horse(&mut app, 14.0, 15.0);
+
+        tick(&mut app, STEP);
+        for horse in [reined_in, slowing] {
+            assert!(app.world().get::<Horse>(horse).unwrap().mounted);
+            assert!(app.world().get::<MemberOf>(horse).is_some());
+        }
+        assert!(riders(&mut app).is_empty());
+    }
+
+    #[test]
+    fn braking_through_a_long_frame_is_no_throw() {
+        // A loss that throws the rider within a short frame is only what
+        // the horse sheds braking over half a second.
+        let lost = THROW_SPEED_LOSS + MAX_ACCELERATION * 0.4;
+        for (frame, thrown) in [(STEP, true), (0.5, false)] {
+            let mut app = test_app();
+            app.add_systems(Update, (move_step, throw_rider).chain());
+            let (_, horse) = galloping_horse(&mut app, 15.0 - lost, 15.0);
+
+            tick(&mut app, frame);
+            let mounted = app.world().get::<Horse>(horse).unwrap().mounted;
+            assert_eq!(mounted, !thrown, "over a {frame} s frame");
+        }
+    }
+
+    /// An app scheduling impacts and throws as the game does: impacts in
+    /// `FixedUpdate`, throws after the velocity is integrated in `Update`,
+    /// at `frame` seconds a frame.
+    fn scheduled_app(frame: f32) -> App {
+        use bevy::time::{TimePlugin, TimeUpdateStrategy};
+        let mut app = App::new();
+        app.add_plugins(TimePlugin)
+            .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f32(
+                frame,
+            )))
+            .init_resource::<Meshes>()
+            .init_resource::<Materials>()
+            .add_message::<CombatEvent>()
+            .add_plugins(
+                AutomaticUpdate::<TrackedByTree>::new()
+                    .with_frequency(Duration::from_secs_f32(1.0 / 20.0))
+                    .with_transform(TransformMode::Transform),
+            )
+            .add_systems(FixedUpdate, charge_impact_system)
+            .add_systems(Update, (move_step, throw_rider).chain());
+        app
+    }
+
+    #[test]
+    fn a_braced_formation_throws_riders_at_any_frame_rate() {
+        for fps in [30.0, 60.0, 144.0] {
+            let mut app = scheduled_app(1.0 / fps);
+            spawn_braced(&mut app, Vec3::new(50.0, 0.0, 0.0), 2.0, 2.0);
+            let loose = app
+                .world_mut()
+                .spawn((
+                    Transform::default(),
+                    TrackedByTree,
+                    Health {
+                        current: 1000.0,
+                        ..default()
+                    },
+                    Faction(1),
+                ))
+                .id();
+            for _ in 0..10 {
+                app.update();
+            }
+            let gallop = |app: &mut App, x: f32| {
+                let horse =
+                    spawn_horse(app, Vec3::new(x, 0.0, 0.0), Vec3::new(x + 100.0, 0.0, 0.0));
+                app.world_mut().entity_mut(horse).insert(Velocity {
+                    v: Vec3::X * 15.0,
+                    target_v: 15.0,
+                    ..default()
+                });
+                app.world_mut().get_mut::<Horse>(horse).unwrap().last_speed = 15.0;
+                horse
+            };
+            let into_braced = gallop(&mut app, 47.0);
+            let into_loose = gallop(&mut app, -3.0);
+
+            for _ in 0..(fps / 4.0) as usize {
+                app.update();
+            }
+            let mounted = |horse| app.world().get::<Horse>(horse).unwrap().mounted;
+            assert!(!mounted(into_braced), "thrown at {fps} fps");
+            assert!(mounted(into_loose), "rode the man down at {fps} fps");
+            assert!(app.world().get::<Health>(loose).unwrap().current < 1000.0);
+        }
+    }
+
+    #[test]
+    fn charging_horses_ride_down_enemies_and_break_on_braced_ones() {
+        let mut app = test_app();
+        app.add_systems(Update, charge_impact_system);
+        let (_, braced) = spawn_braced(&mut app, Vec3::new(50.0, 0.0, 0.0), 2.0, 2.0);
+        let loose = app
+            .world_mut()
+            .spawn((
+                Transform::default(),
+                TrackedByTree,
+                Health::default(),
+                Faction(1),
+            ))
+            .id();
+        let into_loose = spawn_horse(&mut app, Vec3::new(-1.0, 0.0, 0.0), Vec3::ZERO);
+        let into_braced = spawn_horse(&mut app, Vec3::new(49.0, 0.0, 0.0), Vec3::ZERO);
+        let trotting = spawn_horse(&mut app, Vec3::new(1.0, 0.0, 0.0), Vec3::ZERO);
+        tick(&mut app, 1.0);
+        for (horse, speed) in [(into_loose, 10.0), (into_braced, 10.0), (trotting, 5.0)] {
+            app.world_mut().get_mut::<Velocity>(horse).unwrap().v = Vec3::X * speed;
+        }
+
+        tick(&mut app, STEP);
+        let world = app.world();
+        let speed = |horse| world.get::<Velocity>(horse).unwrap().v.length();
+        let health = |unit| world.get::<Health>(unit).unwrap().current;
+        let damage = IMPACT_DAMAGE_PER_SPEED * 10.0;
+        assert_eq!(health(loose), 100.0 - damage, "ridden down once");
+        assert_eq!(health(braced), 100.0 - damage);
+        assert!((speed(into_loose) - 10.0 * (1.0 - IMPACT_SPEED_LOSS)).abs() < 1e-4);
+        assert!((speed(into_braced) - 10.0 * (1.0 - BRACED_IMPACT_SPEED_LOSS)).abs() < 1e-4);
+        assert_eq!(speed(trotting), 5.0, "too slow to ride anyone down");
+
+        // Still fast enough, but not again until the cooldown is over.
+        app.world_mut().get_mut::<Velocity>(into_loose).unwrap().v = Vec3::X * 10.0;
+        tick(&mut app, STEP);
+        assert_eq!(
+            app.world().get::<Health>(loose).unwrap().current,
+            100.0 - damage
+        );
+    }
+
+    #[test]
+    fn charges_follow_their_target_and_quicken_as_they_close() {
+        let mut app = test_app();
+        app.add_systems(Update, charge_system);
+        let (target, _) = spawn_braced(&mut app, Vec3::new(0.0, 0.0, 150.0), 2.0, 2.0);
+        let formation = app
+            .world_mut()
+            .spawn((Formation::default(), Transform::default()))
+            .id();
+        app.world_mut()
+            .get_mut::<Formation>(formation)
+            .unwrap()
+            .tasks
+            .push_back(FormationOrder::Charge {
+                target,
+                pos: Vec3::ZERO,
+                facing_dir: Vec3::Z,
+            });
+        let charge = |app: &mut App, distance: f32| {
+            let pos = Vec3::new(0.0, 0.0, distance);
+            *app.world_mut().get_mut::<GlobalTransform>(target).unwrap() =
+                GlobalTransform::from_translation(pos);
+            tick(app, STEP);
+            let formation = app.world().get::<Formation>(formation).unwrap();
+            let Some(FormationOrder::Charge { pos: aim, .. }) = formation.tasks.front() else {
+                panic!("charge dropped");
+            };
+            assert_eq!(*aim, pos, "follows the target");
+            formation.pace
+        };
+
+        assert_eq!(charge(&mut app, 150.0), Pace::QuickMarch);
+        assert_eq!(charge(&mut app, 60.0), Pace::DoubleQuick);
+        assert_eq!(charge(&mut app, 20.0), Pace::Charge);
+
+        app.world_mut().despawn(target);
+        tick(&mut app, STEP);
+        let formation = app.world().get::<Formation>(formation).unwrap();
+        assert!(formation.tasks.is_empty(), "target gone");
+    }
 }
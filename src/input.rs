@@ -0,0 +1,490 @@
+use bevy::ecs::system::SystemParam;
+use bevy::input::keyboard::NativeKeyCode;
+use bevy::prelude::{ButtonInput, KeyCode, MouseButton, Query, Res, Resource, info, warn};
+use bevy_rts_camera::RtsCameraControls;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Player bindings file, read once at startup by [`InputMap::load`]. RON, a
+/// map from [`Action`] to its chords, e.g.
+/// `{ Select: [[Mouse(Left)]], AssignGroup(1): [[Key(ControlLeft), Key(Digit1)]] }`.
+/// Actions it leaves out keep their default bindings.
+pub const INPUT_MAP_PATH: &str = "assets/input.ron";
+
+/// A named thing the player can do. Input systems ask [`Actions`] about
+/// these instead of reading keys and buttons, so every binding lives in the
+/// [`InputMap`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Action {
+    /// Box select (press, drag, release).
+    Select,
+    /// Held while selecting: add to the selection instead of replacing it.
+    AddToSelection,
+    /// Held on a double-click: select every on-screen unit of the clicked
+    /// one's type instead of its formation.
+    SelectSameType,
+    /// Select everything.
+    SelectAll,
+    /// Drag out a frontage for the selection (press, drag, release).
+    DesignateFrontage,
+    /// Held on frontage release: fit formation width to the frontage.
+    FitWidth,
+    /// Held on frontage release: march at a walk.
+    Walk,
+    /// Held on frontage release: march at the quick march.
+    QuickMarch,
+    /// Held on frontage release: march at double-quick.
+    DoubleQuick,
+    /// Held on frontage release: charge.
+    Charge,
+    /// Held on frontage release: countermarch rather than face about.
+    Countermarch,
+    /// Held on frontage release: turn to face the frontage in place rather
+    /// than move onto it.
+    RotateInPlace,
+    /// Make quick command group N the selection.
+    AssignGroup(u8),
+    /// Add the selection to quick command group N.
+    AddToGroup(u8),
+    /// Select quick command group N.
+    RecallGroup(u8),
+    /// Split the selected formations by files.
+    Split,
+    /// Held with [`Action::Split`]: split by count instead.
+    SplitByCount,
+    /// Merge the selected formations.
+    Merge,
+    /// Toggle road-march mode.
+    RoadMarch,
+    /// Toggle open/close order.
+    Intervals,
+    /// Toggle the order overlay.
+    OrderOverlay,
+    CameraUp,
+    CameraDown,
+    CameraLeft,
+    CameraRight,
+    /// Mouse button held to orbit the camera.
+    CameraRotate,
+    CameraRotateLeft,
+    CameraRotateRight,
+    /// Mouse button held to drag-pan the camera (while nothing is selected).
+    CameraDrag,
+}
+
+/// A single key or mouse button.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Button {
+    Key(KeyCode),
+    Mouse(MouseButton),
+}
+
+/// Buttons that trigger an action together, like Ctrl+1. A chord is held
+/// while all of its buttons are.
+pub type Chord = Vec<Button>;
+
+/// Bindings of every [`Action`]: any one of its chords triggers it. Loaded
+/// from [`INPUT_MAP_PATH`] over the [defaults](InputMap::default), so layouts
+/// other than QWERTY only need to rebind what moved.
+#[derive(Resource, Debug, Clone, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct InputMap {
+    bindings: HashMap<Action, Vec<Chord>>,
+}
+
+/// Quick command groups with default bindings on the digit row.
+const DEFAULT_GROUPS: [(u8, KeyCode); 10] = [
+    (0, KeyCode::Digit0),
+    (1, KeyCode::Digit1),
+    (2, KeyCode::Digit2),
+    (3, KeyCode::Digit3),
+    (4, KeyCode::Digit4),
+    (5, KeyCode::Digit5),
+    (6, KeyCode::Digit6),
+    (7, KeyCode::Digit7),
+    (8, KeyCode::Digit8),
+    (9, KeyCode::Digit9),
+];
+
+impl Default for InputMap {
+    fn default() -> Self {
+        use Button::{Key, Mouse};
+        let shift = vec![
+            vec![Key(KeyCode::ShiftLeft)],
+            vec![Key(KeyCode::ShiftRight)],
+        ];
+        let ctrl = vec![
+            vec![Key(KeyCode::ControlLeft)],
+            vec![Key(KeyCode::ControlRight)],
+        ];
+        let key = |code| vec![vec![Key(code)]];
+        let mut bindings = HashMap::from([
+            (Action::Select, vec![vec![Mouse(MouseButton::Left)]]),
+            (Action::AddToSelection, shift.clone()),
+            (Action::SelectSameType, ctrl.clone()),
+            (
+                Action::DesignateFrontage,
+                vec![vec![Mouse(MouseButton::Right)]],
+            ),
+            (Action::FitWidth, ctrl.clone()),
+            (Action::Walk, key(KeyCode::KeyZ)),
+            (Action::QuickMarch, key(KeyCode::KeyX)),
+            (Action::DoubleQuick, shift.clone()),
+            (Action::Charge, key(KeyCode::Space)),
+            (Action::Countermarch, key(KeyCode::KeyC)),
+            (
+                Action::RotateInPlace,
+                vec![vec![Key(KeyCode::AltLeft)], vec![Key(KeyCode::AltRight)]],
+            ),
+            (Action::Split, key(KeyCode::KeyV)),
+            (Action::SplitByCount, shift.clone()),
+            (Action::Merge, key(KeyCode::KeyM)),
+            (Action::RoadMarch, key(KeyCode::KeyR)),
+            (Action::Intervals, key(KeyCode::KeyO)),
+            (Action::OrderOverlay, key(KeyCode::KeyT)),
+            (Action::CameraUp, key(KeyCode::KeyW)),
+            (Action::CameraDown, key(KeyCode::KeyS)),
+            (Action::CameraLeft, key(KeyCode::KeyA)),
+            (Action::CameraRight, key(KeyCode::KeyD)),
+            (Action::CameraRotate, vec![vec![Mouse(MouseButton::Middle)]]),
+            (Action::CameraRotateLeft, key(KeyCode::KeyQ)),
+            (Action::CameraRotateRight, key(KeyCode::KeyE)),
+            (Action::CameraDrag, vec![vec![Mouse(MouseButton::Right)]]),
+        ]);
+        let select_all = ctrl
+            .iter()
+            .map(|c| [c.clone(), vec![Key(KeyCode::KeyA)]].concat());
+        bindings.insert(Action::SelectAll, select_all.collect());
+        for (group, digit) in DEFAULT_GROUPS {
+            let assign = ctrl.iter().map(|c| [c.clone(), vec![Key(digit)]].concat());
+            let add = shift.iter().map(|c| [c.clone(), vec![Key(digit)]].concat());
+            bindings.insert(Action::AssignGroup(group), assign.collect());
+            bindings.insert(Action::AddToGroup(group), add.collect());
+            bindings.insert(Action::RecallGroup(group), key(digit));
+        }
+        Self { bindings }
+    }
+}
+
+impl InputMap {
+    /// Defaults overridden by the bindings in the file at `path`. A missing
+    /// file is not an error (nothing rebound); an unreadable one is reported
+    /// and ignored.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load(path: &str) -> Self {
+        let mut map = Self::default();
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return map,
+            Err(e) => {
+                warn!("[input] cannot read {path}: {e}; using default bindings");
+                return map;
+            }
+        };
+        match ron::from_str::<InputMap>(&text) {
+            Ok(loaded) => {
+                info!(
+                    "[input] {} actions rebound from {path}",
+                    loaded.bindings.len()
+                );
+                map.bindings.extend(loaded.bindings);
+            }
+            Err(e) => warn!("[input] cannot parse {path}: {e}; using default bindings"),
+        }
+        map
+    }
+
+    /// The defaults: there is no file system on the web to rebind from.
+    #[cfg(target_arch = "wasm32")]
+    pub fn load(_path: &str) -> Self {
+        Self::default()
+    }
+
+    /// Chords bound to `action` (none if unbound).
+    pub fn chords(&self, action: Action) -> &[Chord] {
+        self.bindings.get(&action).map_or(&[], Vec::as_slice)
+    }
+
+    /// The first lone key bound to `action`, for consumers that take a
+    /// single [`KeyCode`].
+    pub fn key(&self, action: Action) -> Option<KeyCode> {
+        self.chords(action)
+            .iter()
+            .find_map(|chord| match chord.as_slice() {
+                [Button::Key(key)] => Some(*key),
+                _ => None,
+            })
+    }
+
+    /// The first lone mouse button bound to `action`, for consumers that
+    /// take a single [`MouseButton`].
+    pub fn button(&self, action: Action) -> Option<MouseButton> {
+        self.chords(action)
+            .iter()
+            .find_map(|chord| match chord.as_slice() {
+                [Button::Mouse(button)] => Some(*button),
+                _ => None,
+            })
+    }
+
+    /// Write the camera bindings into `controls`. The camera takes single
+    /// keys and buttons, so chords are skipped; an action with no lone
+    /// binding leaves the control as it was.
+    pub fn bind_camera(&self, controls: &mut RtsCameraControls) {
+        for (action, control) in camera_keys(controls) {
+            if let Some(key) = self.key(action) {
+                *control = key;
+            }
+        }
+        if let Some(button) = self.button(Action::CameraRotate) {
+            controls.button_rotate = button;
+        }
+        controls.button_drag = self.button(Action::CameraDrag);
+    }
+}
+
+/// The camera's key controls and the actions bound to them.
+fn camera_keys(controls: &mut RtsCameraControls) -> [(Action, &mut KeyCode); 6] {
+    [
+        (Action::CameraUp, &mut controls.key_up),
+        (Action::CameraDown, &mut controls.key_down),
+        (Action::CameraLeft, &mut controls.key_left),
+        (Action::CameraRight, &mut controls.key_right),
+        (Action::CameraRotateLeft, &mut controls.key_rotate_left),
+        (Action::CameraRotateRight, &mut controls.key_rotate_right),
+    ]
+}
+
+/// Action state for input systems: the [`InputMap`] read against this
+/// frame's keyboard and mouse.
+#[derive(SystemParam)]
+pub struct Actions<'w> {
+    map: Res<'w, InputMap>,
+    keys: Res<'w, ButtonInput<KeyCode>>,
+    mouse: Res<'w, ButtonInput<MouseButton>>,
+}
+
+impl Actions<'_> {
+    /// Some chord of `action` is held.
+    pub fn pressed(&self, action: Action) -> bool {
+        self.any_chord(action, |chord| chord.iter().all(|&b| self.held(b)))
+    }
+
+    /// Some chord of `action` was completed this frame: all of it held, a
+    /// button of it pressed just now.
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.any_chord(action, |chord| {
+            chord.iter().all(|&b| self.held(b)) && chord.iter().any(|&b| self.pressed_now(b))
+        })
+    }
+
+    /// Some chord of `action` was let go this frame: a button of it released
+    /// just now while the rest were still held.
+    pub fn just_released(&self, action: Action) -> bool {
+        self.any_chord(action, |chord| {
+            chord.iter().any(|&b| self.released_now(b))
+                && chord.iter().all(|&b| self.held(b) || self.released_now(b))
+        })
+    }
+
+    /// The bindings themselves.
+    pub fn map(&self) -> &InputMap {
+        &self.map
+    }
+
+    /// `button` would complete a chord of several buttons, all the others
+    /// of which are held: it is taken by that chord rather than acting
+    /// alone (A while Ctrl is held, for Ctrl+A).
+    pub fn chorded(&self, button: Button) -> bool {
+        self.map.bindings.values().flatten().any(|chord| {
+            chord.len() > 1
+                && chord.contains(&button)
+                && chord.iter().all(|&b| b == button || self.held(b))
+        })
+    }
+
+    /// Write the camera keys into `controls` as [`InputMap::bind_camera`]
+    /// does, but unbind those [`chorded`](Self::chorded) right now, so the
+    /// camera does not pan on a key that completes another action.
+    pub fn bind_camera_keys(&self, controls: &mut RtsCameraControls) {
+        for (action, control) in camera_keys(controls) {
+            let Some(key) = self.map.key(action) else {
+                continue;
+            };
+            *control = if self.chorded(Button::Key(key)) {
+                KeyCode::Unidentified(NativeKeyCode::Unidentified)
+            } else {
+                key
+            };
+        }
+    }
+
+    fn any_chord(&self, action: Action, test: impl Fn(&Chord) -> bool) -> bool {
+        self.map
+            .chords(action)
+            .iter()
+            .any(|chord| !chord.is_empty() && test(chord))
+    }
+
+    fn held(&self, button: Button) -> bool {
+        match button {
+            Button::Key(key) => self.keys.pressed(key),
+            Button::Mouse(button) => self.mouse.pressed(button),
+        }
+    }
+
+    fn pressed_now(&self, button: Button) -> bool {
+        match button {
+            Button::Key(key) => self.keys.just_pressed(key),
+            Button::Mouse(button) => self.mouse.just_pressed(button),
+        }
+    }
+
+    fn released_now(&self, button: Button) -> bool {
+        match button {
+            Button::Key(key) => self.keys.just_released(key),
+            Button::Mouse(button) => self.mouse.just_released(button),
+        }
+    }
+}
+
+/// Keeps the camera from panning on keys held for a chord: Ctrl+A selects
+/// everything without scrolling the view left. Runs after the input is
+/// read and before the camera moves.
+pub fn camera_keys_system(actions: Actions, mut q_controls: Query<&mut RtsCameraControls>) {
+    for mut controls in &mut q_controls {
+        actions.bind_camera_keys(&mut controls);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::system::SystemState;
+    use bevy::prelude::World;
+
+    /// A world with the default bindings and no button held.
+    fn input_world() -> (World, SystemState<Actions<'static>>) {
+        let mut world = World::new();
+        world.insert_resource(InputMap::default());
+        world.insert_resource(ButtonInput::<KeyCode>::default());
+        world.insert_resource(ButtonInput::<MouseButton>::default());
+        let state = SystemState::new(&mut world);
+        (world, state)
+    }
+
+    /// The next frame: what was pressed or released is no longer new.
+    fn next_frame(world: &mut World) {
+        world.resource_mut::<ButtonInput<KeyCode>>().clear();
+        world.resource_mut::<ButtonInput<MouseButton>>().clear();
+    }
+
+    #[test]
+    fn chord_needs_its_modifier_and_completes_on_any_button() {
+        let (mut world, mut state) = input_world();
+        let assign = Action::AssignGroup(1);
+        let mut keys = world.resource_mut::<ButtonInput<KeyCode>>();
+        keys.press(KeyCode::Digit1);
+        let actions = state.get(&world);
+        assert!(!actions.pressed(assign), "the digit alone is no chord");
+        assert!(actions.just_pressed(Action::RecallGroup(1)));
+
+        // Ctrl after the digit completes the chord all the same.
+        next_frame(&mut world);
+        world
+            .resource_mut::<ButtonInput<KeyCode>>()
+            .press(KeyCode::ControlLeft);
+        let actions = state.get(&world);
+        assert!(actions.pressed(assign) && actions.just_pressed(assign));
+        assert!(!actions.just_pressed(Action::RecallGroup(1)));
+
+        next_frame(&mut world);
+        let actions = state.get(&world);
+        assert!(actions.pressed(assign), "held");
+        assert!(!actions.just_pressed(assign), "completed a frame ago");
+    }
+
+    #[test]
+    fn chord_is_released_by_letting_go_of_any_button() {
+        let (mut world, mut state) = input_world();
+        let assign = Action::AssignGroup(1);
+        let mut keys = world.resource_mut::<ButtonInput<KeyCode>>();
+        keys.press(KeyCode::ControlRight);
+        keys.press(KeyCode::Digit1);
+        next_frame(&mut world);
+
+        world
+            .resource_mut::<ButtonInput<KeyCode>>()
+            .release(KeyCode::ControlRight);
+        let actions = state.get(&world);
+        assert!(actions.just_released(assign), "modifier let go first");
+        assert!(!actions.pressed(assign));
+
+        next_frame(&mut world);
+        world
+            .resource_mut::<ButtonInput<KeyCode>>()
+            .release(KeyCode::Digit1);
+        let actions = state.get(&world);
+        assert!(!actions.just_released(assign), "already released");
+        assert!(actions.just_released(Action::RecallGroup(1)));
+    }
+
+    #[test]
+    fn mouse_buttons_bind_like_keys() {
+        let (mut world, mut state) = input_world();
+        world
+            .resource_mut::<ButtonInput<MouseButton>>()
+            .press(MouseButton::Left);
+        assert!(state.get(&world).just_pressed(Action::Select));
+        next_frame(&mut world);
+        world
+            .resource_mut::<ButtonInput<MouseButton>>()
+            .release(MouseButton::Left);
+        let actions = state.get(&world);
+        assert!(actions.just_released(Action::Select) && !actions.pressed(Action::Select));
+    }
+
+    #[test]
+    fn ctrl_a_selects_all_without_panning_the_camera() {
+        let (mut world, mut state) = input_world();
+        let mut controls = RtsCameraControls::default();
+        state.get(&world).bind_camera_keys(&mut controls);
+        assert_eq!(controls.key_left, KeyCode::KeyA, "A alone pans");
+
+        world
+            .resource_mut::<ButtonInput<KeyCode>>()
+            .press(KeyCode::ControlLeft);
+        let actions = state.get(&world);
+        actions.bind_camera_keys(&mut controls);
+        assert_ne!(controls.key_left, KeyCode::KeyA, "Ctrl held: A is Ctrl+A's");
+        assert_eq!(controls.key_up, KeyCode::KeyW, "no chord takes W");
+
+        next_frame(&mut world);
+        world
+            .resource_mut::<ButtonInput<KeyCode>>()
+            .press(KeyCode::KeyA);
+        let actions = state.get(&world);
+        assert!(actions.just_pressed(Action::SelectAll));
+        actions.bind_camera_keys(&mut controls);
+        assert_ne!(controls.key_left, KeyCode::KeyA);
+
+        next_frame(&mut world);
+        world
+            .resource_mut::<ButtonInput<KeyCode>>()
+            .release(KeyCode::ControlLeft);
+        state.get(&world).bind_camera_keys(&mut controls);
+        assert_eq!(
+            controls.key_left,
+            KeyCode::KeyA,
+            "Ctrl let go: A pans again"
+        );
+    }
+
+    #[test]
+    fn missing_bindings_file_keeps_the_defaults() {
+        let map = InputMap::load("no/such/input.ron");
+        let defaults = InputMap::default();
+        assert_eq!(map.chords(Action::Select), defaults.chords(Action::Select));
+        assert_eq!(map.key(Action::CameraUp), Some(KeyCode::KeyW));
+    }
+}
@@ -1,25 +1,64 @@
+mod ai;
 mod boid;
+mod combat;
+mod faction;
 mod formations;
 mod horse;
+mod input;
 mod kinematics;
+mod morale;
 mod player;
 mod resources;
+mod scenario;
 mod target;
 mod terrain;
+mod ui;
 mod util;
+mod vision;
 
+use crate::ai::{AiCommander, AiPlugin, Difficulty, ai_commander_system};
 use crate::boid::*;
-use crate::formations::{LODGuard, assign_slots, init_formation_speed, propagate_formation_targets, process_formation_orders};
+use crate::combat::{
+    CombatEvent, RangedAttack, corpse_system, death_system, melee_system, projectile_system,
+    ranged_fire_system,
+};
+use crate::faction::{Faction, Factions};
+use crate::formations::{
+    CommandHierarchy, LODGuard, LodViewer, assign_slots, follow_road, init_formation_speed,
+    lod_manager, measure_cohesion, organize_hierarchy, process_formation_orders,
+    propagate_formation_targets, separate_formations, track_pace,
+};
+use crate::horse::{
+    Horse, avoid_tight_formations, charge_impact_system, charge_system, throw_rider,
+};
+use crate::input::{INPUT_MAP_PATH, InputMap, camera_keys_system};
 use crate::kinematics::*;
+use crate::morale::{
+    MoraleEvent, flee_system, formation_morale_system, morale_system, rally_system,
+};
 use crate::player::{
-    FormationSelectionGizmo, Player, SelectionGizmo, draw_cursor, frontage_position_system,
-    mouse_click_system, quick_group_system, selection_indicator_face,
+    FormationSelectionGizmo, OrderGizmos, Player, SelectionGizmo, draw_cursor,
+    frontage_position_system, intervals_toggle_system, mouse_click_system, order_overlay_system,
+    order_overlay_toggle_system, quick_group_system, road_march_toggle_system, select_all_system,
+    selection_indicator_face, split_merge_system,
 };
 use crate::resources::{Materials, Meshes};
+use crate::scenario::{
+    BattleState, Objective, ObjectiveKind, Scenario, ScenarioEvent, ScenarioId, scenario_system,
+};
 use crate::target::{Target, follow_target};
 use crate::terrain::{Obstacle, ObstacleBundle, TerrainBundle};
+use crate::ui::{
+    command_card_status_system, command_card_system, minimap_click_system, minimap_draw_system,
+    spawn_command_card, spawn_minimap,
+};
 use crate::util::*;
+use crate::vision::{
+    FogOfWar, Ghosts, draw_ghosts, fit_fog_to_terrain, fog_visibility_system, ghost_system,
+    vision_system,
+};
 use bevy::asset::RenderAssetUsages;
+use bevy::input::InputSystems;
 use bevy::math::bounding::Aabb2d;
 use bevy::prelude::*;
 use bevy::render::RenderPlugin;
@@ -39,10 +78,20 @@ fn main() {
     }
 
     App::new()
+        .init_resource::<Factions>()
         .init_resource::<Materials>()
         .init_resource::<Meshes>()
         .init_resource::<Player>()
         .init_resource::<LODGuard>()
+        .init_resource::<CommandHierarchy>()
+        .insert_resource(InputMap::load(INPUT_MAP_PATH))
+        .add_message::<CombatEvent>()
+        .add_message::<MoraleEvent>()
+        .init_resource::<Scenario>()
+        .init_resource::<BattleState>()
+        .add_message::<ScenarioEvent>()
+        .init_resource::<FogOfWar>()
+        .init_resource::<Ghosts>()
         .add_plugins(
             DefaultPlugins
                 .set(ImagePlugin::default_nearest())
@@ -52,14 +101,18 @@ fn main() {
                 }),
         )
         .add_plugins(RtsCameraPlugin)
+        .add_plugins(AiPlugin)
         .init_resource::<SelectionGizmo>()
         .init_resource::<FormationSelectionGizmo>()
+        .init_gizmo_group::<OrderGizmos>()
         .add_plugins(
             AutomaticUpdate::<TrackedByTree>::new()
                 .with_frequency(Duration::from_secs_f32(1.0))
                 .with_transform(TransformMode::Transform),
         )
-        .add_systems(Startup, setup)
+        .add_systems(Startup, (setup, spawn_command_card, spawn_minimap))
+        .add_systems(Startup, fit_fog_to_terrain.after(setup))
+        .add_systems(PreUpdate, camera_keys_system.after(InputSystems))
         .add_systems(
             Update,
             (
@@ -68,10 +121,19 @@ fn main() {
                 bob,
                 draw_cursor,
                 mouse_click_system,
+                select_all_system,
                 quick_group_system,
                 frontage_position_system,
+                road_march_toggle_system,
+                intervals_toggle_system,
+                split_merge_system,
                 selection_indicator_face,
+                order_overlay_toggle_system,
+                order_overlay_system,
+                command_card_system,
+                command_card_status_system,
                 hard_collisions.after(soft_collisions),
+                throw_rider.after(move_step).after(hard_collisions),
             ),
         )
         .add_systems(
@@ -80,10 +142,43 @@ fn main() {
                 init_formation_speed,
                 assign_slots,
                 propagate_formation_targets,
+                measure_cohesion,
+                track_pace,
                 follow_target,
+                (
+                    melee_system,
+                    charge_impact_system,
+                    ranged_fire_system,
+                    projectile_system,
+                    morale_system,
+                    death_system,
+                    formation_morale_system,
+                    rally_system,
+                    flee_system,
+                    scenario_system,
+                )
+                    .chain(),
             ),
         )
-        .add_systems(Update, process_formation_orders)
+        .add_systems(
+            Update,
+            (
+                separate_formations,
+                charge_system,
+                process_formation_orders,
+                follow_road,
+                avoid_tight_formations,
+            )
+                .chain(),
+        )
+        .add_systems(Update, (minimap_click_system, minimap_draw_system))
+        .add_systems(Update, corpse_system)
+        .add_systems(Update, lod_manager)
+        .add_systems(FixedUpdate, vision_system.before(ai_commander_system))
+        .add_systems(
+            Update,
+            (fog_visibility_system, ghost_system, draw_ghosts).chain(),
+        )
         .run();
 }
 
@@ -131,6 +226,10 @@ fn setup(
     mut materials: ResMut<Assets<StandardMaterial>>,
     mut mesh_list: ResMut<Meshes>,
     mut mat_list: ResMut<Materials>,
+    factions: Res<Factions>,
+    hierarchy: Res<CommandHierarchy>,
+    mut scenario: ResMut<Scenario>,
+    input_map: Res<InputMap>,
 ) {
     mat_list.black = materials.add(StandardMaterial::from_color(Color::BLACK));
     mat_list.white = materials.add(StandardMaterial::from_color(Color::WHITE));
@@ -138,26 +237,82 @@ fn setup(
         base_color_texture: Some(images.add(uv_debug_texture())),
         ..default()
     });
+    mat_list.factions = factions
+        .factions
+        .iter()
+        .map(|info| materials.add(StandardMaterial::from_color(info.color)))
+        .collect();
 
     mesh_list.cube = meshes.add(Cuboid::default());
     mesh_list.capsule = meshes.add(Capsule3d::default());
 
+    // Two sides facing each other across the middle of the grid. The
+    // second is the computer's, mustered into formations: its archers, its
+    // cavalry and three blocks of foot.
+    let ai_faction = Faction(1);
+    let mut ai_groups: Vec<Vec<(Entity, Vec3)>> = vec![Vec::new(); 5];
     for i in 1..100 {
         for j in 1..100 {
+            let faction = Faction(if j < 50 { 0 } else { 1 });
             let mut ent = commands
-                .spawn(BoidBundle::with_target(
-                    Target {
-                        pos: Vec3::from_array([(i - 50) as f32, 0.0, (j - 50) as f32]),
-                        dir: Default::default(),
-                    },
-                    mesh_list.capsule.clone(),
-                    mat_list.debug_material.clone(),
+                .spawn((
+                    BoidBundle::with_target(
+                        Target {
+                            pos: Vec3::from_array([(i - 50) as f32, 0.0, (j - 50) as f32]),
+                            dir: Default::default(),
+                            speed: None,
+                        },
+                        mesh_list.capsule.clone(),
+                        mat_list.faction(faction),
+                    ),
+                    faction,
                 ))
                 .id();
+            // Each side's rearmost rows are archers; the left wing of the
+            // rest rides.
+            if !(10..90).contains(&j) {
+                commands.entity(ent).insert(RangedAttack::default());
+            } else if i < 10 {
+                commands.entity(ent).insert((
+                    Horse::default(),
+                    UnitStats {
+                        unit_type: UnitType::Cavalry,
+                        ..default()
+                    },
+                ));
+            }
+            if faction == ai_faction {
+                let group = match (i, j) {
+                    (_, 90..) => 0,
+                    (..10, _) => 1,
+                    _ => 2 + (i - 10) as usize / 30,
+                };
+                let pos = Vec3::new((i - 50) as f32, 0.0, (j - 50) as f32);
+                ai_groups[group].push((ent, pos));
+            }
 
             // commands.entity(ent).insert(NoAutomaticBatching{});
         }
     }
+    for (id, group) in ai_groups.iter().enumerate().filter(|(_, g)| !g.is_empty()) {
+        let top = organize_hierarchy(&mut commands, group, ai_faction, &hierarchy);
+        commands.entity(top).insert(ScenarioId(id as u32));
+    }
+    commands.spawn(AiCommander::new(ai_faction, Difficulty::NORMAL));
+
+    // The player has to break most of the enemy before it holds the
+    // ground in front of its line for three minutes.
+    scenario.objectives = vec![
+        Objective::new(Faction(0), ObjectiveKind::Rout { fraction: 0.75 }),
+        Objective::new(
+            ai_faction,
+            ObjectiveKind::HoldZone {
+                center: Vec3::new(0.0, 0.0, 25.0),
+                radius: 20.0,
+                secs: 180.0,
+            },
+        ),
+    ];
 
     for i in 1..100 {
         let mut rng = rand::rng();
@@ -192,8 +347,25 @@ fn setup(
         &mut materials,
     ));
 
+    // Keys and buttons come from the input map. The drag button pans when
+    // nothing is selected; frontage_position_system disables it while a
+    // selection exists.
+    let mut controls = RtsCameraControls {
+        key_rotate_speed: 0.5,
+        lock_on_rotate: false,
+        lock_on_drag: false,
+        edge_pan_width: 0.00,
+        edge_pan_restrict_to_viewport: false,
+        pan_speed: 15.0,
+        zoom_sensitivity: 0.5,
+        enabled: true,
+        ..default()
+    };
+    input_map.bind_camera(&mut controls);
+
     commands.spawn((
         Camera3d::default(),
+        LodViewer,
         RtsCamera {
             bounds: Aabb2d::new(Vec2::ZERO, Vec2::new(10000.0, 10000.0)),
             height_min: 2.0,
@@ -209,25 +381,6 @@ fn setup(
             target_zoom: 0.0,
             snap: false,
         },
-        RtsCameraControls {
-            key_up: KeyCode::KeyW,
-            key_down: KeyCode::KeyS,
-            key_left: KeyCode::KeyA,
-            key_right: KeyCode::KeyD,
-            button_rotate: MouseButton::Middle,
-            key_rotate_left: KeyCode::KeyQ,
-            key_rotate_right: KeyCode::KeyE,
-            key_rotate_speed: 0.5,
-            lock_on_rotate: false,
-            // RMB is camera drag-pan when nothing is selected;
-            // frontage_position_system disables it while a selection exists.
-            button_drag: Option::from(MouseButton::Right),
-            lock_on_drag: false,
-            edge_pan_width: 0.00,
-            edge_pan_restrict_to_viewport: false,
-            pan_speed: 15.0,
-            zoom_sensitivity: 0.5,
-            enabled: true,
-        },
+        controls,
     ));
 }
@@ -0,0 +1,648 @@
+use crate::boid::Boid;
+use crate::combat::{CombatEvent, Health};
+use crate::faction::Faction;
+use crate::formations::{
+    Formation, FormationOrder, FormationSlot, Formations, MemberOf, Members, RankRole,
+};
+use crate::kinematics::NNTree;
+use crate::target::Target;
+use bevy::prelude::*;
+use bevy_spatial::SpatialAccess;
+use std::collections::HashMap;
+
+/// Will to fight, from 0 (gone) to `max`. Every boid has its own
+/// (`require`), worn down by [`morale_system`] and recovered at rest; a
+/// formation's is the average over every boid below it, kept by
+/// [`formation_morale_system`], which routs it below [`ROUT_MORALE`].
+#[derive(Component, Clone, Copy, Debug)]
+pub struct Morale {
+    pub value: f32,
+    pub max: f32,
+}
+
+impl Default for Morale {
+    fn default() -> Self {
+        Self {
+            value: 100.0,
+            max: 100.0,
+        }
+    }
+}
+
+impl Morale {
+    pub fn state(&self) -> MoraleState {
+        if self.value < ROUT_MORALE {
+            MoraleState::Routing
+        } else if self.value < SHAKEN_MORALE {
+            MoraleState::Shaken
+        } else {
+            MoraleState::Steady
+        }
+    }
+
+    fn change(&mut self, delta: f32) {
+        self.value = (self.value + delta).clamp(0.0, self.max);
+    }
+}
+
+/// How a unit's [`Morale`] stands.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MoraleState {
+    Steady,
+    /// Wavering: one more blow may break it.
+    Shaken,
+    /// Broken; a formation this low routs.
+    Routing,
+}
+
+/// Below this a formation is [shaken](MoraleState::Shaken).
+pub const SHAKEN_MORALE: f32 = 50.0;
+
+/// Below this a formation routs.
+pub const ROUT_MORALE: f32 = 25.0;
+
+/// A fleeing boid answers a [`FormationOrder::Rally`] once its morale is
+/// back up to this.
+pub const RALLY_MORALE: f32 = 50.0;
+
+/// Morale every member of a formation loses to the death of one of them,
+/// shared out by the formation's size: losing a third of the men costs
+/// about half the morale.
+pub const CASUALTY_MORALE: f32 = 150.0;
+
+/// Extra morale every member loses when the formation's officer falls.
+pub const OFFICER_LOSS_MORALE: f32 = 20.0;
+
+/// Enemies this close to a member are in contact with it.
+pub const CONTACT_RANGE: f32 = 3.0;
+
+/// Morale lost per second for each enemy in contact on a member's flank
+/// (relative to [`Formation::dir`]); double from the rear.
+pub const FLANK_MORALE_PER_SEC: f32 = 4.0;
+
+/// `cos` of the angle off the formation's facing beyond which contact is
+/// on the flank (60 degrees) ...
+const FLANK_COS: f32 = 0.5;
+
+/// ... and beyond which it is in the rear (120 degrees).
+const REAR_COS: f32 = -0.5;
+
+/// Morale friendly boids lose when a formation within
+/// [`ROUT_PANIC_RADIUS`] of them routs.
+pub const ROUT_PANIC_MORALE: f32 = 15.0;
+pub const ROUT_PANIC_RADIUS: f32 = 30.0;
+
+/// Morale regained per second out of contact (for a fleeing boid: with no
+/// enemy within [`FLEE_SAFE_DISTANCE`]).
+pub const MORALE_RECOVERY_PER_SEC: f32 = 2.0;
+
+/// Recovery of a fleeing boid whose formation is rallying.
+pub const RALLY_RECOVERY_PER_SEC: f32 = 10.0;
+
+/// Fleeing boids run from enemies closer than this.
+pub const FLEE_SAFE_DISTANCE: f32 = 30.0;
+
+/// How far ahead of itself a fleeing boid aims.
+const FLEE_STEP: f32 = 10.0;
+
+/// Relationship: this boid fled from the formation it routed out of, which
+/// may [rally](FormationOrder::Rally) it. Fleeing boids are free boids:
+/// without `MemberOf` or a slot.
+#[derive(Component)]
+#[relationship(relationship_target = Routers)]
+pub struct RoutedFrom(pub Entity);
+
+/// Reverse relationship: the boids fleeing from this formation.
+#[derive(Component)]
+#[relationship_target(relationship = RoutedFrom)]
+pub struct Routers(Vec<Entity>);
+
+/// Changes in a formation's morale, for systems that react to it (AI,
+/// objectives).
+#[derive(Message, Clone, Copy, Debug)]
+pub enum MoraleEvent {
+    Shaken {
+        formation: Entity,
+    },
+    Routed {
+        formation: Entity,
+        faction: Faction,
+        pos: Vec3,
+    },
+    Rallied {
+        formation: Entity,
+        boids: usize,
+    },
+}
+
+/// Wear down the morale of every boid:
+/// - a fallen member costs every member of its formation a share of
+///   [`CASUALTY_MORALE`], and [`OFFICER_LOSS_MORALE`] more if it held the
+///   officer's post (see [`Formation::rank_role`]);
+/// - enemies in contact on a member's flank or rear, judged against the
+///   formation's facing, cost [`FLANK_MORALE_PER_SEC`] each;
+/// - a friendly formation routing nearby costs [`ROUT_PANIC_MORALE`].
+///
+/// Boids out of contact recover [`MORALE_RECOVERY_PER_SEC`]; fleeing boids
+/// are left to [`flee_system`]. Runs before [`death_system`](crate::combat::death_system)
+/// so the fallen still hold their slots.
+#[allow(clippy::type_complexity)]
+pub fn morale_system(
+    time: Res<Time>,
+    tree: Res<NNTree>,
+    mut combat: MessageReader<CombatEvent>,
+    mut morale_events: MessageReader<MoraleEvent>,
+    q_formations: Query<(&Formation, Option<&Members>, Option<&Formations>)>,
+    q_slots: Query<&FormationSlot>,
+    q_sides: Query<&Faction, With<Health>>,
+    mut q_boids: Query<
+        (
+            Entity,
+            &Transform,
+            &Faction,
+            &mut Morale,
+            Option<&MemberOf>,
+            Has<RoutedFrom>,
+        ),
+        With<Boid>,
+    >,
+) {
+    let dt = time.delta_secs();
+    let mut shock: HashMap<Entity, f32> = HashMap::new();
+    for event in combat.read() {
+        let CombatEvent::Killed {
+            target,
+            formation: Some(formation_entity),
+            ..
+        } = *event
+        else {
+            continue;
+        };
+        let Ok((formation, members, subs)) = q_formations.get(formation_entity) else {
+            continue;
+        };
+        let size = members.map_or(0, |m| m.len());
+        let mut loss = CASUALTY_MORALE / size.max(1) as f32;
+        let total = size + subs.map_or(0, |s| s.len());
+        let officer = q_slots
+            .get(target)
+            .is_ok_and(|slot| formation.rank_role(slot.0, total) == Some(RankRole::Officer));
+        if officer {
+            loss += OFFICER_LOSS_MORALE;
+            info!("[morale] officer of {formation_entity:?} down");
+        }
+        *shock.entry(formation_entity).or_default() += loss;
+    }
+
+    let mut panic: HashMap<Entity, f32> = HashMap::new();
+    for event in morale_events.read() {
+        let MoraleEvent::Routed { faction, pos, .. } = *event else {
+            continue;
+        };
+        for (_, entity) in tree.within_distance(pos, ROUT_PANIC_RADIUS) {
+            let Some(entity) = entity else {
+                continue;
+            };
+            if q_sides.get(entity).is_ok_and(|f| *f == faction) {
+                *panic.entry(entity).or_default() += ROUT_PANIC_MORALE;
+            }
+        }
+    }
+
+    for (entity, transform, faction, mut morale, member_of, fleeing) in &mut q_boids {
+        let mut loss = panic.get(&entity).copied().unwrap_or_default();
+        if fleeing {
+            morale.change(-loss);
+            continue;
+        }
+        let pos = transform.translation;
+        let facing = member_of
+            .and_then(|m| q_formations.get(m.0).ok())
+            .and_then(|(formation, _, _)| formation.dir.try_normalize());
+        let mut contact = false;
+        for (at, other) in tree.within_distance(pos, CONTACT_RANGE) {
+            let Some(other) = other else {
+                continue;
+            };
+            if other == entity || !q_sides.get(other).is_ok_and(|f| f != faction) {
+                continue;
+            }
+            contact = true;
+            let Some(facing) = facing else {
+                continue;
+            };
+            let toward = (at - pos).with_y(0.0).normalize_or_zero().dot(facing);
+            if toward < REAR_COS {
+                loss += 2.0 * FLANK_MORALE_PER_SEC * dt;
+            } else if toward < FLANK_COS {
+                loss += FLANK_MORALE_PER_SEC * dt;
+            }
+        }
+        if let Some(member_of) = member_of {
+            loss += shock.get(&member_of.0).copied().unwrap_or_default();
+        }
+        if !contact {
+            loss -= MORALE_RECOVERY_PER_SEC * dt;
+        }
+        morale.change(-loss);
+    }
+}
+
+/// Average the morale of every boid below each formation into its own
+/// [`Morale`], and act on it. A formation with boid members whose morale
+/// drops below [`ROUT_MORALE`] routs: its orders are dropped and its boids
+/// leave it (`MemberOf` together with `FormationSlot`, see
+/// [`FormationSlot`]) to flee as free boids, [`RoutedFrom`] it until it
+/// rallies them. A container does not rout itself; its sub-formations do.
+/// Falling below [`SHAKEN_MORALE`] is reported, as is a rout.
+#[allow(clippy::type_complexity)]
+pub fn formation_morale_system(
+    mut q_formations: Query<(
+        Entity,
+        &mut Formation,
+        &mut Morale,
+        &Transform,
+        &Faction,
+        Option<&Members>,
+    )>,
+    q_hierarchy: Query<(Option<&Members>, Option<&Formations>), With<Formation>>,
+    q_boid_morale: Query<&Morale, Without<Formation>>,
+    mut events: MessageWriter<MoraleEvent>,
+    mut commands: Commands,
+) {
+    // Sum and count of the morale of every boid below `formation`.
+    fn tally(
+        q_hierarchy: &Query<(Option<&Members>, Option<&Formations>), With<Formation>>,
+        q_boid_morale: &Query<&Morale, Without<Formation>>,
+        formation: Entity,
+    ) -> (f32, usize) {
+        let Ok((members, subs)) = q_hierarchy.get(formation) else {
+            return (0.0, 0);
+        };
+        let boids = members
+            .into_iter()
+            .flat_map(|m| m.iter())
+            .filter_map(|m| q_boid_morale.get(m).ok())
+            .fold((0.0, 0), |(sum, n), morale| (sum + morale.value, n + 1));
+        subs.into_iter()
+            .flat_map(|s| s.iter())
+            .map(|sub| tally(q_hierarchy, q_boid_morale, sub))
+            .fold(boids, |(sum, n), (s, m)| (sum + s, n + m))
+    }
+
+    for (entity, mut formation, mut morale, transform, faction, members) in &mut q_formations {
+        let (sum, count) = tally(&q_hierarchy, &q_boid_morale, entity);
+        if count == 0 {
+            continue; // routed or fallen: keeps its last reading
+        }
+        let before = morale.value;
+        morale.value = sum / count as f32;
+        match (morale.state(), members) {
+            (MoraleState::Routing, Some(members)) => {
+                formation.tasks.clear();
+                for member in members.iter() {
+                    commands
+                        .entity(member)
+                        .remove::<(MemberOf, FormationSlot)>()
+                        .insert(RoutedFrom(entity));
+                }
+                events.write(MoraleEvent::Routed {
+                    formation: entity,
+                    faction: *faction,
+                    pos: transform.translation,
+                });
+                info!("[morale] {entity:?} routs, {} boids flee", members.len());
+            }
+            (MoraleState::Shaken, _) if before >= SHAKEN_MORALE => {
+                events.write(MoraleEvent::Shaken { formation: entity });
+                info!("[morale] {entity:?} is shaken");
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Fleeing boids run directly away from every enemy within
+/// [`FLEE_SAFE_DISTANCE`], nearer ones weighing more, and stop where they
+/// end up once none is left that close. Only then do they recover: at
+/// [`MORALE_RECOVERY_PER_SEC`], or [`RALLY_RECOVERY_PER_SEC`] while their
+/// formation is rallying.
+pub fn flee_system(
+    time: Res<Time>,
+    tree: Res<NNTree>,
+    q_formations: Query<&Formation>,
+    q_sides: Query<&Faction, With<Health>>,
+    mut q_fleeing: Query<
+        (
+            Entity,
+            &Transform,
+            &Faction,
+            &RoutedFrom,
+            &mut Target,
+            &mut Morale,
+        ),
+        Without<MemberOf>,
+    >,
+) {
+    let dt = time.delta_secs();
+    for (entity, transform, faction, routed_from, mut target, mut morale) in &mut q_fleeing {
+        let pos = transform.translation;
+        let away: Vec3 = tree
+            .within_distance(pos, FLEE_SAFE_DISTANCE)
+            .into_iter()
+            .filter_map(|(at, other)| Some((at, other?)))
+            .filter(|&(_, other)| other != entity)
+            .filter(|&(_, other)| q_sides.get(other).is_ok_and(|f| f != faction))
+            .map(|(at, _)| {
+                let from = (pos - at).with_y(0.0);
+                from / from.length_squared().max(0.01)
+            })
+            .sum();
+        if let Ok(away) = Dir3::new(away) {
+            target.pos = (pos + away * FLEE_STEP).with_y(0.0);
+            target.speed = None;
+            continue;
+        }
+        let rallying = q_formations
+            .get(routed_from.0)
+            .is_ok_and(|f| matches!(f.tasks.front(), Some(FormationOrder::Rally)));
+        let recovery = if rallying {
+            RALLY_RECOVERY_PER_SEC
+        } else {
+            MORALE_RECOVERY_PER_SEC
+        };
+        morale.change(recovery * dt);
+    }
+}
+
+/// Carry out [`FormationOrder::Rally`]: every boid fleeing from the
+/// formation whose morale is back to [`RALLY_MORALE`] rejoins it (without
+/// a slot, so `assign_slots` gives it one). The order finishes once nobody
+/// is left fleeing from it; those still running meanwhile recover faster
+/// (see [`flee_system`]).
+pub fn rally_system(
+    mut q_formations: Query<(Entity, &mut Formation, Option<&Routers>)>,
+    q_morale: Query<&Morale>,
+    mut events: MessageWriter<MoraleEvent>,
+    mut commands: Commands,
+) {
+    for (entity, mut formation, routers) in &mut q_formations {
+        if !matches!(formation.tasks.front(), Some(FormationOrder::Rally)) {
+            continue;
+        }
+        let Some(routers) = routers else {
+            formation.tasks.pop_front();
+            continue;
+        };
+        let rallied: Vec<Entity> = routers
+            .iter()
+            .filter(|&boid| q_morale.get(boid).is_ok_and(|m| m.value >= RALLY_MORALE))
+            .collect();
+        if rallied.is_empty() {
+            continue;
+        }
+        for &boid in &rallied {
+            commands
+                .entity(boid)
+                .remove::<RoutedFrom>()
+                .insert(MemberOf(entity));
+        }
+        events.write(MoraleEvent::Rallied {
+            formation: entity,
+            boids: rallied.len(),
+        });
+        info!("[morale] {} boids rally to {entity:?}", rallied.len());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formations::{FormationKind, FormationParams};
+    use crate::kinematics::TrackedByTree;
+    use bevy_spatial::{AutomaticUpdate, TransformMode};
+    use std::time::Duration;
+
+    /// Headless app running [`morale_system`] on manual time, with the kd
+    /// tree refreshed every frame.
+    fn test_app() -> App {
+        let mut app = App::new();
+        app.init_resource::<Time>()
+            .add_message::<CombatEvent>()
+            .add_message::<MoraleEvent>()
+            .add_plugins(
+                AutomaticUpdate::<TrackedByTree>::new()
+                    .with_frequency(Duration::from_secs_f32(1.0 / 20.0))
+                    .with_transform(TransformMode::Transform),
+            )
+            .add_systems(Update, morale_system);
+        app
+    }
+
+    fn tick(app: &mut App, dt: f32) {
+        app.world_mut()
+            .resource_mut::<Time>()
+            .advance_by(Duration::from_secs_f32(dt));
+        app.update();
+    }
+
+    fn spawn_boid(app: &mut App, pos: Vec3, faction: Faction) -> Entity {
+        app.world_mut()
+            .spawn((
+                Boid::default(),
+                Transform::from_translation(pos),
+                TrackedByTree,
+                Health::default(),
+                faction,
+            ))
+            .id()
+    }
+
+    /// A formation of four facing +Z, one rank of two files deep, far apart
+    /// so none is in contact with another.
+    fn spawn_formation(app: &mut App) -> (Entity, Vec<Entity>) {
+        let formation = app
+            .world_mut()
+            .spawn((
+                Formation {
+                    kind: FormationKind::Grid,
+                    params: FormationParams {
+                        files: Some(2),
+                        ..default()
+                    },
+                    dir: Vec3::Z,
+                    ..default()
+                },
+                Transform::default(),
+            ))
+            .id();
+        let members = (0..4)
+            .map(|i| {
+                let member = spawn_boid(app, Vec3::new(i as f32 * 50.0, 0.0, 0.0), Faction(0));
+                app.world_mut()
+                    .entity_mut(member)
+                    .insert((MemberOf(formation), FormationSlot(i)));
+                member
+            })
+            .collect();
+        (formation, members)
+    }
+
+    fn morale(app: &App, boid: Entity) -> f32 {
+        app.world().get::<Morale>(boid).unwrap().value
+    }
+
+    fn kill(app: &mut App, formation: Entity, target: Entity) {
+        app.world_mut().write_message(CombatEvent::Killed {
+            attacker: Entity::PLACEHOLDER,
+            target,
+            faction: Faction(0),
+            formation: Some(formation),
+            pos: Vec3::ZERO,
+        });
+    }
+
+    #[test]
+    fn a_fallen_member_shakes_the_rest_of_its_formation() {
+        let mut app = test_app();
+        let (formation, members) = spawn_formation(&mut app);
+        let outsider = spawn_boid(&mut app, Vec3::new(0.0, 0.0, 50.0), Faction(0));
+        let total = members.len();
+        let private = (0..total)
+            .find(|&i| {
+                let formation = app.world().get::<Formation>(formation).unwrap();
+                formation.rank_role(i, total) != Some(RankRole::Officer)
+            })
+            .unwrap();
+
+        // Frozen time: no recovery muddles the shock.
+        kill(&mut app, formation, members[private]);
+        tick(&mut app, 0.0);
+        let share = CASUALTY_MORALE / total as f32;
+        for &member in &members {
+            assert_eq!(morale(&app, member), 100.0 - share);
+        }
+        assert_eq!(morale(&app, outsider), 100.0, "not of that formation");
+    }
+
+    #[test]
+    fn losing_the_officer_costs_more() {
+        let mut app = test_app();
+        let (formation, members) = spawn_formation(&mut app);
+        let total = members.len();
+        let officer = app
+            .world()
+            .get::<Formation>(formation)
+            .unwrap()
+            .rank_roles(total)
+            .iter()
+            .position(|&role| role == RankRole::Officer)
+            .unwrap();
+
+        kill(&mut app, formation, members[officer]);
+        tick(&mut app, 0.0);
+        let share = CASUALTY_MORALE / total as f32;
+        assert_eq!(
+            morale(&app, members[0]),
+            100.0 - share - OFFICER_LOSS_MORALE
+        );
+    }
+
+    #[test]
+    fn enemies_on_the_flank_and_rear_wear_morale_down() {
+        let mut app = test_app();
+        let (_, members) = spawn_formation(&mut app);
+        // One enemy each: in front of the first member, beside the second,
+        // behind the third; the fourth is left alone.
+        for (member, offset) in members.iter().zip([Vec3::Z, Vec3::X, Vec3::NEG_Z]) {
+            let pos = app.world().get::<Transform>(*member).unwrap().translation;
+            spawn_boid(&mut app, pos + 2.0 * offset, Faction(1));
+        }
+        tick(&mut app, 1.0);
+        for &member in &members {
+            app.world_mut().get_mut::<Morale>(member).unwrap().value = 50.0;
+        }
+
+        tick(&mut app, 1.0);
+        assert_eq!(morale(&app, members[0]), 50.0, "held from the front");
+        assert_eq!(morale(&app, members[1]), 50.0 - FLANK_MORALE_PER_SEC);
+        assert_eq!(morale(&app, members[2]), 50.0 - 2.0 * FLANK_MORALE_PER_SEC);
+        assert_eq!(
+            morale(&app, members[3]),
+            50.0 + MORALE_RECOVERY_PER_SEC,
+            "out of contact: recovers"
+        );
+    }
+
+    #[test]
+    fn broken_formation_routs_and_rallies_when_ordered() {
+        let mut app = App::new();
+        app.add_message::<MoraleEvent>()
+            .add_systems(Update, (formation_morale_system, rally_system).chain());
+        let formation = app
+            .world_mut()
+            .spawn((Formation::default(), Transform::default()))
+            .id();
+        app.world_mut()
+            .get_mut::<Formation>(formation)
+            .unwrap()
+            .tasks
+            .push_back(FormationOrder::Hold {
+                pos: Vec3::ZERO,
+                facing_dir: Vec3::Z,
+            });
+        let boids: Vec<Entity> = (0..4)
+            .map(|i| {
+                app.world_mut()
+                    .spawn((
+                        Morale {
+                            value: 10.0,
+                            ..default()
+                        },
+                        MemberOf(formation),
+                        FormationSlot(i),
+                    ))
+                    .id()
+            })
+            .collect();
+        let members = |world: &World| world.get::<Members>(formation).map_or(0, |m| m.len());
+        app.update();
+
+        let world = app.world_mut();
+        assert!(world.get::<Formation>(formation).unwrap().tasks.is_empty());
+        for &boid in &boids {
+            assert!(world.get::<MemberOf>(boid).is_none(), "routed boids flee");
+            assert!(world.get::<FormationSlot>(boid).is_none());
+            assert_eq!(world.get::<RoutedFrom>(boid).unwrap().0, formation);
+        }
+
+        // Two have recovered: they answer the rally, the others keep it up.
+        for &boid in &boids[..2] {
+            world.get_mut::<Morale>(boid).unwrap().value = 80.0;
+        }
+        world
+            .get_mut::<Formation>(formation)
+            .unwrap()
+            .tasks
+            .push_back(FormationOrder::Rally);
+        app.update();
+        let world = app.world();
+        assert_eq!(members(world), 2);
+        assert!(matches!(
+            world.get::<Formation>(formation).unwrap().tasks.front(),
+            Some(FormationOrder::Rally)
+        ));
+
+        for &boid in &boids[2..] {
+            app.world_mut().get_mut::<Morale>(boid).unwrap().value = 80.0;
+        }
+        app.update();
+        app.update();
+        let world = app.world();
+        assert_eq!(members(world), 4);
+        assert!(
+            world.get::<Formation>(formation).unwrap().tasks.is_empty(),
+            "the rally finishes once nobody is left fleeing"
+        );
+    }
+}
@@ -1,26 +1,35 @@
-use crate::boid::Boid;
+use crate::boid::{Boid, UnitStats, UnitType};
+use crate::combat::RangedAttack;
+use crate::faction::{Faction, Factions};
 use crate::formations::{
-    Formation, FormationKind, FormationOf, FormationOrder, FormationSlot, Formations, MemberOf,
-    Members, QuickCommandGroup,
+    CommandHierarchy, Footprint, Formation, FormationKind, FormationOf, FormationOrder,
+    FormationSlot, Formations, Intervals, Lead, MemberOf, Members, NeedsSpeedInit, Pace,
+    QUICK_GROUPS, QuickCommandGroup, RoadMarch, SplitBy, disband_formation, organize_hierarchy,
+    split_members,
 };
+use crate::horse::Horse;
+use crate::input::{Action, Actions};
 use crate::kinematics::{NNTree, Velocity};
+use crate::morale::RoutedFrom;
 use crate::target::Target;
+use crate::ui::{BlocksPointer, pointer_over_ui};
 use crate::util::within_rect;
+use crate::vision::FogOfWar;
 use bevy::color::palettes::basic::YELLOW;
 use bevy::ecs::component::{Mutable, StorageType};
 use bevy::ecs::lifecycle::{ComponentHook, HookContext};
 use bevy::ecs::relationship::RelationshipTarget as _;
+use bevy::ecs::system::SystemParam;
 use bevy::ecs::world::DeferredWorld;
 use bevy::gizmos::GizmoAsset;
-use bevy::gizmos::config::GizmoLineConfig;
+use bevy::gizmos::config::{GizmoConfigGroup, GizmoConfigStore, GizmoLineConfig};
 use bevy::math::{Isometry3d, Quat, Vec3};
 use bevy::prelude::{
-    Assets, ButtonInput, Camera, ChildOf, Children, Color, Commands, Component, Dir3, Entity,
-    FromWorld, Gizmo, Gizmos, GlobalTransform, Handle, InfinitePlane3d, KeyCode, MouseButton,
-    Query, Res, ResMut, Resource, Transform, Vec2, Window, With, Without, World, default, info,
-    warn,
+    Assets, Camera, ChildOf, Children, Color, Commands, Component, Dir3, Entity, FromWorld, Gizmo,
+    Gizmos, GlobalTransform, Handle, Has, InfinitePlane3d, Interaction, Query, Real, Reflect, Res,
+    ResMut, Resource, Time, Transform, Vec2, Window, With, Without, World, default, info, warn,
 };
-use bevy_rts_camera::{Ground, RtsCameraControls};
+use bevy_rts_camera::{Ground, RtsCamera, RtsCameraControls};
 use std::f32::consts::FRAC_PI_2;
 
 #[derive(Resource, Default)]
@@ -30,10 +39,65 @@ pub struct Player {
     corner3: Vec3,
     /// Left front corner of a frontage being designated by RMB drag.
     front_left: Option<Vec3>,
+    /// Real time (seconds) and pick of the last click, awaiting a second
+    /// click to make it a double-click.
+    last_click: Option<(f64, Entity)>,
+    /// Real time (seconds) and group of the last group recall, awaiting a
+    /// second press to centre the camera.
+    last_recall: Option<(f64, u8)>,
+}
+
+impl Player {
+    /// Register a click picking `picked` at real time `now`: whether it
+    /// completes a double-click, the second click on the same pick within
+    /// [`DOUBLE_CLICK_SECS`]. A third click starts over rather than
+    /// double-clicking again.
+    fn click(&mut self, now: f64, picked: Entity) -> bool {
+        let double = self
+            .last_click
+            .is_some_and(|(at, last)| last == picked && now - at < DOUBLE_CLICK_SECS);
+        self.last_click = (!double).then_some((now, picked));
+        double
+    }
 }
 
 pub struct Selected;
 
+/// The selected units, with the formation each belongs to: a member's
+/// [`MemberOf`], or the formation a fleeing boid routed from
+/// ([`RoutedFrom`]).
+pub type SelectedUnits<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        Option<&'static MemberOf>,
+        Option<&'static RoutedFrom>,
+    ),
+    With<Selected>,
+>;
+
+/// The formations the selection commands, each once, in selection order: a
+/// selected formation itself, and the formation of every selected member or
+/// fleeing boid. Whatever `is_formation` rejects (free boids, formations
+/// the caller has no use for) is left out.
+pub fn selected_formations(
+    q_selected: &SelectedUnits,
+    is_formation: impl Fn(Entity) -> bool,
+) -> Vec<Entity> {
+    let mut formations = Vec::new();
+    for (entity, member_of, routed_from) in q_selected {
+        let formation = member_of
+            .map(|m| m.0)
+            .or(routed_from.map(|r| r.0))
+            .unwrap_or(entity);
+        if is_formation(formation) && !formations.contains(&formation) {
+            formations.push(formation);
+        }
+    }
+    formations
+}
+
 /// Shared gizmo asset for selection rings.
 #[derive(Resource)]
 pub struct SelectionGizmo(pub Handle<GizmoAsset>);
@@ -55,7 +119,8 @@ impl FromWorld for SelectionGizmo {
 }
 
 /// Shared gizmo asset for formation selection: a flat unit square (side 1,
-/// centered); the child entity's Transform scale sizes it to the formation extent.
+/// centered); the child entity's Transform scales and offsets it onto the
+/// formation [`Footprint`].
 #[derive(Resource)]
 pub struct FormationSelectionGizmo(pub Handle<GizmoAsset>);
 
@@ -77,13 +142,14 @@ impl FromWorld for FormationSelectionGizmo {
 pub struct SelectionIndicator;
 
 fn on_selected_insert(mut world: DeferredWorld, ctx: HookContext) {
-    // Formations get an extent-sized square; boids get the small ring.
-    let formation_extent = world
-        .get_entity_mut(ctx.entity)
-        .ok()
-        .and_then(|entity| entity.get::<Formation>().map(|formation| formation.extent));
-    let handle = match formation_extent {
-        Some(extent) => {
+    // Formations get a footprint-sized rectangle; boids get the small ring.
+    let formation_footprint = world.get_entity_mut(ctx.entity).ok().and_then(|entity| {
+        entity
+            .get::<Formation>()
+            .map(|formation| formation.footprint)
+    });
+    let handle = match formation_footprint {
+        Some(footprint) => {
             let Some(gizmo) = world.get_resource::<FormationSelectionGizmo>() else {
                 warn!("Selected inserted before FormationSelectionGizmo resource exists");
                 return;
@@ -100,7 +166,7 @@ fn on_selected_insert(mut world: DeferredWorld, ctx: HookContext) {
                         },
                         depth_bias: -1.0,
                     },
-                    Transform::from_xyz(0.0, 0.05, 0.0).with_scale(Vec3::splat(extent)),
+                    footprint_indicator_transform(&footprint),
                 ));
             });
             return;
@@ -129,6 +195,14 @@ fn on_selected_insert(mut world: DeferredWorld, ctx: HookContext) {
     });
 }
 
+/// Indicator child transform laying the unit square over `footprint`, in
+/// the formation's (slot) frame.
+fn footprint_indicator_transform(footprint: &Footprint) -> Transform {
+    let lift = Vec3::new(0.0, 0.05, 0.0);
+    let size = Vec3::new(footprint.width, 1.0, footprint.depth);
+    Transform::from_translation(footprint.center + lift).with_scale(size)
+}
+
 fn on_selected_remove(mut world: DeferredWorld, ctx: HookContext) {
     world
         .commands()
@@ -149,7 +223,7 @@ impl Component for Selected {
     }
 }
 
-fn get_intersection(
+pub(crate) fn get_intersection(
     cursor_position: &Vec2,
     camera: &Camera,
     camera_transform: &GlobalTransform,
@@ -203,18 +277,46 @@ pub fn draw_cursor(
     }
 }
 
+/// A release within this distance (world units) of the press is a click,
+/// not a box.
+const CLICK_TOLERANCE: f32 = 0.5;
+
+/// A click picks the nearest boid within this distance of the cursor.
+const PICK_RADIUS: f32 = 1.0;
+
+/// Two clicks on the same pick within this many seconds are a double-click.
+const DOUBLE_CLICK_SECS: f64 = 0.35;
+
+/// Select with the left mouse button ([`Action::Select`]):
+/// - drag -> box select the boids inside
+/// - click -> pick the nearest boid under the cursor, or else the formation
+///   whose footprint is under it
+/// - double-click a boid -> select its formation (via [`MemberOf`])
+/// - Ctrl+double-click a boid ([`Action::SelectSameType`]) -> select every
+///   visible on-screen boid of its [`UnitType`]
+///
+/// The selection is replaced unless Shift ([`Action::AddToSelection`]) is
+/// held. Only units of the local [`Faction`] are selected.
+#[allow(clippy::too_many_arguments)]
 pub fn mouse_click_system(
     mut player: ResMut<Player>,
     mut q_camera: Query<(&Camera, &GlobalTransform)>,
     q_ground: Query<&GlobalTransform, With<Ground>>,
-    mouse_button_input: Res<ButtonInput<MouseButton>>,
-    keys: Res<ButtonInput<KeyCode>>,
+    actions: Actions,
+    time: Res<Time<Real>>,
     windows: Query<&Window>,
     q_selected: Query<(Entity, &Children), With<Selected>>,
+    q_formations: Query<(Entity, &Transform, &Formation)>,
+    q_member_of: Query<&MemberOf>,
+    q_units: Query<(Entity, &GlobalTransform, &UnitStats, &Visibility), With<Boid>>,
+    q_ui: Query<&Interaction, With<BlocksPointer>>,
+    factions: Res<Factions>,
+    q_faction: Query<&Faction>,
     tree: Res<NNTree>,
     mut gizmos: Gizmos,
     mut commands: Commands,
 ) {
+    let own = |entity: Entity| q_faction.get(entity).is_ok_and(|f| factions.is_local(*f));
     let (camera, camera_transform) = q_camera.single_mut().unwrap();
     let ground = q_ground.single().unwrap();
     let Some(cursor_position) = windows.single().unwrap().cursor_position() else {
@@ -224,25 +326,63 @@ pub fn mouse_click_system(
         return;
     };
 
-    if mouse_button_input.just_pressed(MouseButton::Left) {
+    if actions.just_pressed(Action::Select) && !pointer_over_ui(&q_ui) {
         player.selecting = true;
         player.corner1 = point;
     }
 
-    if mouse_button_input.just_released(MouseButton::Left) && player.selecting {
+    if actions.just_released(Action::Select) && player.selecting {
         player.selecting = false;
 
-        if !keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]) {
+        if !actions.pressed(Action::AddToSelection) {
             for (entity, _) in &q_selected {
                 commands.entity(entity).remove::<Selected>();
             }
         }
 
-        // Click without drag: deselect (above) but no box select. This also
-        // guards against a stale corner1 producing a phantom selection.
-        if player.corner1 == point {
+        // Click without drag: pick instead of box select. This also guards
+        // against a stale corner1 producing a phantom selection.
+        if player.corner1.distance(point) < CLICK_TOLERANCE {
+            let Some(picked) = pick(point, &tree, &q_formations, own) else {
+                player.last_click = None;
+                return;
+            };
+            let double = player.click(time.elapsed_secs_f64(), picked);
+
+            let same_type = actions.pressed(Action::SelectSameType);
+            match (double, q_units.get(picked)) {
+                (true, Ok((_, _, stats, _))) if same_type => {
+                    let viewport = camera.logical_viewport_size().unwrap_or_default();
+                    let on_screen = |pos: Vec3| {
+                        camera
+                            .world_to_viewport(camera_transform, pos)
+                            .is_ok_and(|p| p.cmpge(Vec2::ZERO).all() && p.cmple(viewport).all())
+                    };
+                    for (unit, transform, other, visibility) in &q_units {
+                        if other.unit_type == stats.unit_type
+                            && own(unit)
+                            && *visibility != Visibility::Hidden
+                            && on_screen(transform.translation())
+                        {
+                            commands.entity(unit).insert(Selected);
+                        }
+                    }
+                }
+                (true, _) => {
+                    if let Ok(member_of) = q_member_of.get(picked) {
+                        commands.entity(picked).remove::<Selected>();
+                        commands.entity(member_of.0).insert(Selected);
+                    } else {
+                        commands.entity(picked).insert(Selected);
+                    }
+                }
+                (false, _) => {
+                    commands.entity(picked).insert(Selected);
+                }
+            }
             return;
         }
+        player.last_click = None;
 
         player.corner3 = point;
 
@@ -258,11 +398,14 @@ pub fn mouse_click_system(
         let corner4 = corner1 + dif_hor;
 
         for (_, entity) in within_rect(corner1, corner2, corner3, corner4, tree) {
-            commands.entity(entity.unwrap()).insert(Selected);
+            let entity = entity.unwrap();
+            if own(entity) {
+                commands.entity(entity).insert(Selected);
+            }
         }
     }
 
-    if mouse_button_input.pressed(MouseButton::Left) {
+    if actions.pressed(Action::Select) {
         player.corner3 = point;
 
         let right = camera_transform.right();
@@ -283,126 +426,619 @@ pub fn mouse_click_system(
     }
 }
 
-/// Hotkeys 1-6, RTS quick command groups:
-/// - Ctrl+N -> assign current selection to slot N (boids: new formation;
-///   a selected formation: re-slot it)
-/// - N alone -> select the formation stored in slot N (replacing selection)
+/// The unit a click at `point` picks among those `selectable`: the nearest
+/// boid within [`PICK_RADIUS`], else the formation whose footprint covers
+/// `point` (the smallest, so a company wins over its battalion).
+fn pick(
+    point: Vec3,
+    tree: &NNTree,
+    q_formations: &Query<(Entity, &Transform, &Formation)>,
+    selectable: impl Fn(Entity) -> bool,
+) -> Option<Entity> {
+    // The tree is 3D and boids bob above the ground: search a little wider,
+    // then measure on the ground plane.
+    let boid = tree
+        .within_distance(point, PICK_RADIUS * 2.0)
+        .into_iter()
+        .filter_map(|(pos, entity)| Some((pos.xz().distance(point.xz()), entity?)))
+        .filter(|&(distance, entity)| distance <= PICK_RADIUS && selectable(entity))
+        .min_by(|(a, _), (b, _)| a.total_cmp(b))
+        .map(|(_, entity)| entity);
+    boid.or_else(|| {
+        q_formations
+            .iter()
+            .filter(|(entity, transform, formation)| {
+                selectable(*entity)
+                    && formation.footprint.contains(
+                        transform.translation,
+                        transform.rotation,
+                        point,
+                    )
+            })
+            .min_by(|(_, _, a), (_, _, b)| {
+                let area = |f: &Formation| f.footprint.width * f.footprint.depth;
+                area(a).total_cmp(&area(b))
+            })
+            .map(|(entity, _, _)| entity)
+    })
+}
+
+/// Finds what a click would target among the enemies of the local
+/// [`Faction`] in its sight ([`FogOfWar`]).
+#[derive(SystemParam)]
+pub struct EnemyPicker<'w, 's> {
+    tree: Res<'w, NNTree>,
+    factions: Res<'w, Factions>,
+    fog: Res<'w, FogOfWar>,
+    q_faction: Query<'w, 's, &'static Faction>,
+    q_member_of: Query<'w, 's, &'static MemberOf>,
+}
+
+impl EnemyPicker<'_, '_> {
+    /// The enemy under `point`: the nearest boid of another faction within
+    /// [`PICK_RADIUS`] that is in sight, taken as its formation when it has
+    /// one.
+    pub fn pick(&self, point: Vec3) -> Option<Entity> {
+        let enemy = |entity: Entity| {
+            self.q_faction
+                .get(entity)
+                .is_ok_and(|f| !self.factions.is_local(*f))
+        };
+        self.tree
+            .within_distance(point, PICK_RADIUS * 2.0)
+            .into_iter()
+            .filter(|&(pos, _)| self.fog.sees(self.factions.local, pos))
+            .filter_map(|(pos, entity)| Some((pos.xz().distance(point.xz()), entity?)))
+            .filter(|&(distance, entity)| distance <= PICK_RADIUS && enemy(entity))
+            .min_by(|(a, _), (b, _)| a.total_cmp(b))
+            .map(|(_, boid)| self.q_member_of.get(boid).map_or(boid, |m| m.0))
+    }
+}
+
+/// Some boid of `formation`, at any depth, passes `test`.
+fn any_member(
+    q_formations: &Query<(&mut Formation, Option<&Members>, Option<&Formations>)>,
+    formation: Entity,
+    test: &impl Fn(Entity) -> bool,
+) -> bool {
+    let Ok((_, members, subs)) = q_formations.get(formation) else {
+        return false;
+    };
+    members.is_some_and(|m| m.iter().any(test))
+        || subs.is_some_and(|s| s.iter().any(|s| any_member(q_formations, s, test)))
+}
+
+/// Order the formations among `units` that have shooters ([`RangedAttack`]
+/// members, at any depth) to fire at `target`, replacing their orders.
+/// Returns the units that cannot shoot.
+fn fire_at(
+    target: Entity,
+    units: &[Entity],
+    q_formation_mut: &mut Query<(&mut Formation, Option<&Members>, Option<&Formations>)>,
+    q_arms: &Query<(Has<RangedAttack>, Option<&Horse>)>,
+) -> Vec<Entity> {
+    let shooter = |boid| q_arms.get(boid).is_ok_and(|(ranged, _)| ranged);
+    let mut rest = Vec::new();
+    for &unit in units {
+        if !any_member(q_formation_mut, unit, &shooter) {
+            rest.push(unit);
+            continue;
+        }
+        let Ok((mut formation, _, _)) = q_formation_mut.get_mut(unit) else {
+            continue;
+        };
+        formation.tasks.clear();
+        formation.tasks.push_back(FormationOrder::FireAt { target });
+        info!("[fire] formation {unit:?} at {target:?}");
+    }
+    rest
+}
+
+/// Order the formations among `units` that have riders (mounted [`Horse`]
+/// members, at any depth) to charge `target`, replacing their orders.
+/// Returns the units without riders.
+fn charge(
+    target: Entity,
+    units: &[Entity],
+    q_formation_mut: &mut Query<(&mut Formation, Option<&Members>, Option<&Formations>)>,
+    q_arms: &Query<(Has<RangedAttack>, Option<&Horse>)>,
+    q_transforms: &Query<&Transform>,
+) -> Vec<Entity> {
+    let rider = |boid| {
+        q_arms
+            .get(boid)
+            .is_ok_and(|(_, horse)| horse.is_some_and(|h| h.mounted))
+    };
+    let Ok(pos) = q_transforms.get(target).map(|t| t.translation.with_y(0.0)) else {
+        return units.to_vec();
+    };
+    let mut rest = Vec::new();
+    for &unit in units {
+        if !any_member(q_formation_mut, unit, &rider) {
+            rest.push(unit);
+            continue;
+        }
+        let Ok((mut formation, _, _)) = q_formation_mut.get_mut(unit) else {
+            continue;
+        };
+        let from = q_transforms.get(unit).map_or(pos, |t| t.translation);
+        let facing_dir = (pos - from)
+            .with_y(0.0)
+            .try_normalize()
+            .unwrap_or(formation.dir);
+        formation.tasks.clear();
+        formation.tasks.push_back(FormationOrder::Charge {
+            target,
+            pos,
+            facing_dir,
+        });
+        info!("[charge] formation {unit:?} at {target:?}");
+    }
+    rest
+}
+
+/// Ctrl+A ([`Action::SelectAll`]) selects every top-level formation and
+/// every free boid of the local [`Faction`]. Individually selected members
+/// and sub-formations are dropped in favour of those unless Shift
+/// ([`Action::AddToSelection`]) is held.
+pub fn select_all_system(
+    actions: Actions,
+    factions: Res<Factions>,
+    q_selected: Query<Entity, With<Selected>>,
+    q_formations: Query<(Entity, &Faction), (With<Formation>, Without<FormationOf>)>,
+    q_free_boids: Query<(Entity, &Faction), (With<Boid>, Without<MemberOf>)>,
+    mut commands: Commands,
+) {
+    if !actions.just_pressed(Action::SelectAll) {
+        return;
+    }
+    if !actions.pressed(Action::AddToSelection) {
+        for entity in &q_selected {
+            commands.entity(entity).remove::<Selected>();
+        }
+    }
+    let own = |(entity, faction): (Entity, &Faction)| factions.is_local(*faction).then_some(entity);
+    let formations: Vec<Entity> = q_formations.iter().filter_map(own).collect();
+    let free_boids: Vec<Entity> = q_free_boids.iter().filter_map(own).collect();
+    for &entity in formations.iter().chain(&free_boids) {
+        commands.entity(entity).insert(Selected);
+    }
+    info!(
+        "[select] all: {} formations, {} free boids",
+        formations.len(),
+        free_boids.len()
+    );
+}
+
+/// Two presses of a group's recall key within this many seconds centre the
+/// camera on the group.
+const DOUBLE_RECALL_SECS: f64 = 0.4;
+
+/// RTS quick command groups [`QUICK_GROUPS`] (digit row by default). A
+/// group is every formation carrying its [`QuickCommandGroup`] number:
+/// - Ctrl+N ([`Action::AssignGroup`]) -> make group N the selection.
+///   Selected formations become the group, the rest leave it. Otherwise
+///   the selected boids are built into a new formation (organised per
+///   [`CommandHierarchy`]) and the group's old formations are disbanded
+/// - Shift+N ([`Action::AddToGroup`]) -> add the selection to group N,
+///   disbanding nothing: selected formations join the group, selected boids
+///   join its largest formation (or a new one if the group is empty)
+/// - N alone ([`Action::RecallGroup`]) -> select group N (replacing
+///   selection); pressed twice quickly, also centre the camera on it
+///
+/// Groups only ever hold formations of the local [`Faction`].
+#[allow(clippy::too_many_arguments)]
 pub fn quick_group_system(
-    keys: Res<ButtonInput<KeyCode>>,
+    actions: Actions,
+    time: Res<Time<Real>>,
+    mut player: ResMut<Player>,
     q_selected: Query<(Entity, &Transform), (With<Selected>, Without<Formation>)>,
     q_selected_formations: Query<Entity, (With<Selected>, With<Formation>)>,
-    q_formations: Query<(Entity, &QuickCommandGroup, &Members, Option<&Formations>), With<Formation>>,
+    q_groups: Query<
+        (
+            Entity,
+            &QuickCommandGroup,
+            &Transform,
+            Option<&Members>,
+            &Faction,
+        ),
+        With<Formation>,
+    >,
+    factions: Res<Factions>,
+    q_member_of: Query<&MemberOf>,
+    q_hierarchy: Query<(Option<&Members>, Option<&Formations>), With<Formation>>,
+    hierarchy: Res<CommandHierarchy>,
+    mut q_camera: Query<&mut RtsCamera>,
     mut commands: Commands,
 ) {
-    const SLOT_KEYS: [KeyCode; 6] = [
-        KeyCode::Digit1,
-        KeyCode::Digit2,
-        KeyCode::Digit3,
-        KeyCode::Digit4,
-        KeyCode::Digit5,
-        KeyCode::Digit6,
-    ];
-    let Some(slot) = SLOT_KEYS
-        .iter()
-        .position(|key| keys.just_pressed(*key))
-        .map(|i| i as u8)
-    else {
-        return;
+    // Assigning and adding chords usually contain the recall binding
+    // (Ctrl+1 holds 1), so they win.
+    let pressed = |action: fn(u8) -> Action| {
+        QUICK_GROUPS
+            .into_iter()
+            .find(|&n| actions.just_pressed(action(n)))
+    };
+    // (formation, position, member count) of every formation in a group.
+    let (q_groups, factions) = (&q_groups, &factions);
+    let group = move |slot: u8| {
+        let in_group = q_groups
+            .iter()
+            .filter(move |(_, g, _, _, f)| g.0 == slot && factions.is_local(**f));
+        in_group.map(|(entity, _, transform, members, _)| {
+            let count = members.map_or(0, |m| m.len());
+            (entity, transform.translation, count)
+        })
     };
 
-    let ctrl = keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]);
-
-    if ctrl {
-        if let Ok(selected_formation) = q_selected_formations.single() {
-            // Re-slot the selected formation to this number.
-            commands
-                .entity(selected_formation)
-                .insert(QuickCommandGroup(slot));
+    if let Some(slot) = pressed(Action::AssignGroup) {
+        if !q_selected_formations.is_empty() {
+            for (formation, _, _) in group(slot) {
+                if !q_selected_formations.contains(formation) {
+                    commands.entity(formation).remove::<QuickCommandGroup>();
+                }
+            }
+            for formation in &q_selected_formations {
+                commands.entity(formation).insert(QuickCommandGroup(slot));
+            }
         } else if !q_selected.is_empty() {
-            // Assign: build a new formation at the selection's centroid.
-            let mut centroid = Vec3::ZERO;
-            let count = q_selected.iter().count();
-            for (_, transform) in &q_selected {
-                centroid += transform.translation;
+            // Free the slot: disband the group's formations, with their
+            // whole sub-formation hierarchies.
+            for (old, _, _) in group(slot) {
+                disband_formation(&mut commands, &q_hierarchy, old);
             }
-            centroid /= count as f32;
-
-            // Free the slot: detach members of the formation currently in it.
-            // Detached members must also drop their FormationSlot: a stale
-            // slot survives the validity check in assign_slots (in-range,
-            // unique) and would pin the member to an arbitrary slot in
-            // whatever formation it joins next.
-            if let Some((old, _, members, subs)) =
-                q_formations.iter().find(|(_, s, _, _)| s.0 == slot)
-            {
-                for member in members.iter() {
+
+            // Assign: build a new formation at the selection's centroid,
+            // auto-organised into companies when large. max_speed is derived
+            // from the member list by `init_formation_speed` on the ticks
+            // after the members attach.
+            let boids: Vec<(Entity, Vec3)> = q_selected
+                .iter()
+                .map(|(entity, transform)| (entity, transform.translation))
+                .collect();
+            let formation = organize_hierarchy(&mut commands, &boids, factions.local, &hierarchy);
+            commands.entity(formation).insert(QuickCommandGroup(slot));
+        }
+        info!("[group {slot}] assigned");
+    } else if let Some(slot) = pressed(Action::AddToGroup) {
+        for formation in &q_selected_formations {
+            commands.entity(formation).insert(QuickCommandGroup(slot));
+        }
+        // Boids already in a selected formation come along with it.
+        let boids: Vec<(Entity, Vec3)> = q_selected
+            .iter()
+            .filter(|(boid, _)| {
+                !q_member_of
+                    .get(*boid)
+                    .is_ok_and(|m| q_selected_formations.contains(m.0))
+            })
+            .map(|(entity, transform)| (entity, transform.translation))
+            .collect();
+        let largest = group(slot)
+            .max_by_key(|&(_, _, members)| members)
+            .map(|(formation, _, _)| formation);
+        match largest {
+            _ if boids.is_empty() => {}
+            Some(formation) => {
+                for &(boid, _) in &boids {
+                    if let Ok(old) = q_member_of.get(boid) {
+                        if old.0 == formation {
+                            continue;
+                        }
+                        commands.entity(old.0).insert(NeedsSpeedInit);
+                    }
                     commands
-                        .entity(member)
-                        .remove::<MemberOf>()
+                        .entity(boid)
+                        .insert(MemberOf(formation))
                         .remove::<FormationSlot>();
                 }
-                if let Some(subs) = subs {
-                    for sub in subs.iter() {
-                        commands.entity(sub).remove::<FormationOf>();
-                    }
+                commands.entity(formation).insert(NeedsSpeedInit);
+            }
+            None => {
+                let formation =
+                    organize_hierarchy(&mut commands, &boids, factions.local, &hierarchy);
+                commands.entity(formation).insert(QuickCommandGroup(slot));
+            }
+        }
+        info!("[group {slot}] added to");
+    } else if let Some(slot) = pressed(Action::RecallGroup) {
+        let formations: Vec<(Entity, Vec3, usize)> = group(slot).collect();
+        if formations.is_empty() {
+            return;
+        }
+        // Select this group, replacing the current selection.
+        for (entity, _) in &q_selected {
+            commands.entity(entity).remove::<Selected>();
+        }
+        for entity in &q_selected_formations {
+            commands.entity(entity).remove::<Selected>();
+        }
+        for &(formation, _, _) in &formations {
+            commands.entity(formation).insert(Selected);
+        }
+
+        let now = time.elapsed_secs_f64();
+        let double = player
+            .last_recall
+            .is_some_and(|(at, last)| last == slot && now - at < DOUBLE_RECALL_SECS);
+        player.last_recall = (!double).then_some((now, slot));
+        if double {
+            let center =
+                formations.iter().map(|&(_, pos, _)| pos).sum::<Vec3>() / formations.len() as f32;
+            for mut camera in &mut q_camera {
+                camera.target_focus.translation = center;
+            }
+        }
+    }
+}
+
+/// Split and merge the selected formations (including the formations of
+/// selected member boids):
+/// - V ([`Action::Split`]) -> split each in half by files; the left files
+///   form a new formation
+/// - Shift+V (with [`Action::SplitByCount`]) -> split each in half by count;
+///   the rear half forms a new one
+/// - M ([`Action::Merge`]) -> merge all of them into the one with the most
+///   members
+///
+/// New formations keep the old kind, facing and pace, start holding where
+/// they stand and join the selection; quick command groups stay with the
+/// original. Moved members drop their `FormationSlot` with the old
+/// `MemberOf` (see [`FormationSlot`]), so `assign_slots` re-derives slots on
+/// both sides, and every formation whose member list changed re-derives its
+/// speed via [`NeedsSpeedInit`].
+pub fn split_merge_system(
+    actions: Actions,
+    q_selected: SelectedUnits,
+    mut q_formations: Query<(
+        &mut Formation,
+        &Transform,
+        Option<&Members>,
+        Option<&Formations>,
+        Option<&QuickCommandGroup>,
+    )>,
+    q_member_state: Query<(&Transform, Option<&FormationSlot>)>,
+    q_faction: Query<&Faction>,
+    mut commands: Commands,
+) {
+    let split = actions.just_pressed(Action::Split);
+    let merge = actions.just_pressed(Action::Merge);
+    if !split && !merge {
+        return;
+    }
+    let formations = selected_formations(&q_selected, |f| q_formations.contains(f));
+
+    if split {
+        let by_count = actions.pressed(Action::SplitByCount);
+        for formation_entity in formations {
+            let Ok((mut formation, transform, Some(members), subs, _)) =
+                q_formations.get_mut(formation_entity)
+            else {
+                continue;
+            };
+            let total = members.len() + subs.map_or(0, |s| s.len());
+            let slots: Vec<(Entity, Option<usize>)> = members
+                .iter()
+                .map(|m| {
+                    let slot = q_member_state.get(m).ok().and_then(|(_, s)| s.map(|s| s.0));
+                    (m, slot)
+                })
+                .collect();
+            let by = if by_count {
+                SplitBy::Count(slots.len() / 2)
+            } else {
+                SplitBy::Files
+            };
+            let detached = split_members(&formation, &slots, total, by);
+            if detached.is_empty() {
+                info!("[split] formation {formation_entity:?} cannot split {by:?}");
+                continue;
+            }
+
+            let mut centroid = Vec3::ZERO;
+            for &member in &detached {
+                if let Ok((member_transform, _)) = q_member_state.get(member) {
+                    centroid += member_transform.translation;
                 }
-                commands.entity(old).despawn();
             }
+            centroid /= detached.len() as f32;
 
-            // max_speed is derived from the member list by
-            // `init_formation_speed` on the ticks after the members attach.
-            let formation = commands
+            // Splitting by files halves the frontage: both halves keep their
+            // rank depth.
+            let mut params = formation.params;
+            if by == SplitBy::Files && formation.kind.has_files() {
+                let files = formation.kind.files(total, &formation.params);
+                params.files = Some(files / 2);
+                formation.params.files = Some(files - files / 2);
+            }
+            let new_formation = commands
                 .spawn((
-                    Formation::default(),
-                    Transform::from_translation(centroid),
-                    QuickCommandGroup(slot),
+                    Formation {
+                        kind: formation.kind,
+                        params,
+                        dir: formation.dir,
+                        pace: formation.pace,
+                        ..Formation::default()
+                    },
+                    Transform::from_translation(centroid).with_rotation(transform.rotation),
+                    q_faction.get(formation_entity).copied().unwrap_or_default(),
+                    Selected,
                 ))
                 .id();
-            for (entity, _) in &q_selected {
-                commands.entity(entity).insert(MemberOf(formation));
+            for member in &detached {
+                commands
+                    .entity(*member)
+                    .insert(MemberOf(new_formation))
+                    .remove::<FormationSlot>();
             }
+            commands.entity(formation_entity).insert(NeedsSpeedInit);
+            info!(
+                "[split] {} of {} members of {formation_entity:?} -> {new_formation:?}",
+                detached.len(),
+                members.len()
+            );
         }
-    } else if let Some((formation_entity, _, _, _)) =
-        q_formations.iter().find(|(_, s, _, _)| s.0 == slot)
-    {
-        // Plain number: select this group, replacing the current selection.
-        for (entity, _) in &q_selected {
-            commands.entity(entity).remove::<Selected>();
+    } else if formations.len() >= 2 {
+        // Merge into the formation with the most members.
+        let member_count = |f: Entity| {
+            q_formations
+                .get(f)
+                .map_or(0, |(_, _, members, _, _)| members.map_or(0, |m| m.len()))
+        };
+        let keep = *formations
+            .iter()
+            .max_by_key(|&&f| member_count(f))
+            .expect("at least two formations");
+        let own_group = q_formations
+            .get(keep)
+            .ok()
+            .and_then(|(_, _, _, _, group)| group.map(|g| g.0));
+        let mut inherited_group: Option<u8> = None;
+        for &other in formations.iter().filter(|&&f| f != keep) {
+            let Ok((_, _, members, subs, other_group)) = q_formations.get(other) else {
+                continue;
+            };
+            for member in members.into_iter().flat_map(|m| m.iter()) {
+                commands
+                    .entity(member)
+                    .insert(MemberOf(keep))
+                    .remove::<FormationSlot>();
+            }
+            for sub in subs.into_iter().flat_map(|s| s.iter()) {
+                commands.entity(sub).insert(FormationOf(keep));
+            }
+            if let Some(other_group) = other_group {
+                inherited_group =
+                    Some(inherited_group.map_or(other_group.0, |g| g.min(other_group.0)));
+            }
+            commands.entity(other).despawn();
         }
-        for entity in &q_selected_formations {
-            commands.entity(entity).remove::<Selected>();
+        let mut kept = commands.entity(keep);
+        kept.insert(NeedsSpeedInit);
+        // Without a group of its own, the merged formation inherits the
+        // lowest group among those absorbed.
+        if let (None, Some(group)) = (own_group, inherited_group) {
+            kept.insert(QuickCommandGroup(group));
+        }
+        info!("[merge] {} formations -> {keep:?}", formations.len());
+    }
+}
+
+/// R ([`Action::RoadMarch`]) toggles road-march mode ([`RoadMarch`]) on the selected formations,
+/// including the formations of selected member boids. Mixed selections
+/// switch uniformly: on unless every formation already road-marches.
+pub fn road_march_toggle_system(
+    actions: Actions,
+    q_selected: SelectedUnits,
+    q_formations: Query<Has<RoadMarch>, With<Formation>>,
+    mut commands: Commands,
+) {
+    if !actions.just_pressed(Action::RoadMarch) {
+        return;
+    }
+    let formations = selected_formations(&q_selected, |f| q_formations.contains(f));
+    if formations.is_empty() {
+        return;
+    }
+    let enable = !formations
+        .iter()
+        .all(|&f| q_formations.get(f).unwrap_or(false));
+    for formation in formations {
+        if enable {
+            commands.entity(formation).insert(RoadMarch::default());
+        } else {
+            commands.entity(formation).remove::<RoadMarch>();
         }
-        commands.entity(formation_entity).insert(Selected);
     }
+    info!("[road march] {}", if enable { "on" } else { "off" });
 }
 
-/// Right-click drag designates a frontage for the selected entities:
+/// O ([`Action::Intervals`]) orders the selected formations (including the formations of selected
+/// member boids) into open order, or back into close order. Mixed
+/// selections switch uniformly: open unless every formation already is.
+/// Goes through the task queue in front of whatever is pending, so a march
+/// carries on at the new intervals.
+pub fn intervals_toggle_system(
+    actions: Actions,
+    q_selected: SelectedUnits,
+    mut q_formations: Query<&mut Formation>,
+) {
+    if !actions.just_pressed(Action::Intervals) {
+        return;
+    }
+    let formations = selected_formations(&q_selected, |f| q_formations.contains(f));
+    if formations.is_empty() {
+        return;
+    }
+    let open = !formations.iter().all(|&f| {
+        q_formations
+            .get(f)
+            .is_ok_and(|formation| formation.params.intervals == Intervals::Open)
+    });
+    let intervals = if open {
+        Intervals::Open
+    } else {
+        Intervals::Close
+    };
+    for formation in formations {
+        if let Ok(mut formation) = q_formations.get_mut(formation) {
+            formation
+                .tasks
+                .push_front(FormationOrder::Intervals(intervals));
+        }
+    }
+    info!("[intervals] {intervals:?} order");
+}
+
+/// Right-click drag ([`Action::DesignateFrontage`]) designates a frontage for the selected entities:
 /// press = left front corner, release = right front corner. Selected units
-/// (free boids, formations, and formations of member boids) are arranged in
-/// a grid along the frontage, facing perpendicular to it; depth is automatic
-/// from the minimum spacing: 0.5 per boid, or the maximum extent among the
-/// formations being positioned.
+/// (free boids, formations, and formations of member boids) are packed side
+/// by side along the frontage by their [`Footprint`] width, facing
+/// perpendicular to it; units that do not fit wrap into further lines
+/// behind the first.
+///
+/// Modifiers held on release: Ctrl ([`Action::FitWidth`]) fits formation
+/// width to the frontage; Z ([`Action::Walk`]), X ([`Action::QuickMarch`])
+/// and Shift ([`Action::DoubleQuick`]) march at the slower paces, Space
+/// ([`Action::Charge`]) charges. Without a pace modifier each formation
+/// keeps its current [`Pace`]. C ([`Action::Countermarch`])
+/// countermarches instead of facing about when the new frontage reverses a
+/// formation. While dragging, the layout the release would order is drawn
+/// as ghost slot markers, with the modifiers held at the time.
+///
+/// A right-click without a drag moves the selection to the point as it
+/// stands (see [`move_selection`]); on an enemy, formations with shooters
+/// fire at it instead ([`FormationOrder::FireAt`]), formations with riders
+/// charge it ([`FormationOrder::Charge`]) and the rest move up.
+/// Alt ([`Action::RotateInPlace`]) held on release turns the selection to
+/// face the frontage where it stands instead of moving it there.
+#[allow(clippy::too_many_arguments)]
 pub fn frontage_position_system(
     mut player: ResMut<Player>,
-    mouse: Res<ButtonInput<MouseButton>>,
-    keys: Res<ButtonInput<KeyCode>>,
+    actions: Actions,
     q_camera: Query<(&Camera, &GlobalTransform)>,
     q_ground: Query<&GlobalTransform, With<Ground>>,
     windows: Query<&Window>,
     q_selected_boids: Query<Entity, (With<Selected>, With<Boid>, Without<Formation>)>,
     q_selected_formations: Query<Entity, (With<Selected>, With<Formation>)>,
     q_member_of: Query<&MemberOf>,
-    mut q_formation_mut: Query<&mut Formation>,
-    mut commands: Commands,
+    mut q_formation_mut: Query<(&mut Formation, Option<&Members>, Option<&Formations>)>,
     mut q_targets: Query<&mut Target>,
+    q_transforms: Query<&Transform>,
+    enemies: EnemyPicker,
+    q_arms: Query<(Has<RangedAttack>, Option<&Horse>)>,
     mut q_camera_controls: Query<&mut RtsCameraControls>,
+    q_ui: Query<&Interaction, With<BlocksPointer>>,
     mut gizmos: Gizmos,
 ) {
     // Nothing selected: RMB stays the camera drag-pan control. With a
     // selection, RMB becomes frontage designation, so disable camera drag.
     let has_selection = !q_selected_boids.is_empty() || !q_selected_formations.is_empty();
+    let button_drag = actions
+        .map()
+        .button(Action::CameraDrag)
+        .filter(|_| !has_selection);
     for mut controls in &mut q_camera_controls {
-        controls.button_drag = (!has_selection).then_some(MouseButton::Right);
+        controls.button_drag = button_drag;
     }
     if !has_selection {
         player.front_left = None;
@@ -422,55 +1058,118 @@ pub fn frontage_position_system(
         return;
     };
 
-    if mouse.just_pressed(MouseButton::Right) {
+    if actions.just_pressed(Action::DesignateFrontage) && !pointer_over_ui(&q_ui) {
         player.front_left = Some(point);
     }
 
     if let Some(left) = player.front_left {
-        if mouse.pressed(MouseButton::Right) {
+        let rotate_in_place = actions.pressed(Action::RotateInPlace);
+        let adjust_width = actions.pressed(Action::FitWidth) && !rotate_in_place;
+        let countermarch = actions.pressed(Action::Countermarch);
+        let units = frontage_units(&q_selected_boids, &q_selected_formations, &q_member_of);
+        if actions.pressed(Action::DesignateFrontage) {
             gizmos.line(left, point, Color::srgb(0.3, 1.0, 0.3));
-        }
-        if mouse.just_released(MouseButton::Right) {
-            player.front_left = None;
-            let adjust_width = keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]);
-            designate_frontage(
+            let plan = plan_frontage(
                 left,
                 point,
                 adjust_width,
-                &q_selected_boids,
-                &q_selected_formations,
-                &q_member_of,
-                &mut q_formation_mut,
-                &mut q_targets,
+                countermarch,
+                &units,
+                &q_formation_mut,
             );
+            if let Some((forward, mut placements)) = plan {
+                // Turning in place: the same layouts, where the units are.
+                if rotate_in_place {
+                    for placement in &mut placements {
+                        if let Ok(transform) = q_transforms.get(placement.unit) {
+                            placement.pos = transform.translation;
+                        }
+                    }
+                }
+                draw_frontage_preview(forward, &placements, &mut gizmos);
+            }
+        }
+        if actions.just_released(Action::DesignateFrontage) {
+            player.front_left = None;
+            let pace = ordered_pace(&actions);
+            if left.distance(point) < CLICK_TOLERANCE {
+                let units = match enemies.pick(point) {
+                    Some(target) => {
+                        let rest = fire_at(target, &units, &mut q_formation_mut, &q_arms);
+                        charge(target, &rest, &mut q_formation_mut, &q_arms, &q_transforms)
+                    }
+                    None => units,
+                };
+                move_selection(
+                    point,
+                    pace,
+                    &units,
+                    &mut q_formation_mut,
+                    &q_transforms,
+                    &mut q_targets,
+                );
+            } else if rotate_in_place {
+                face_in_place(
+                    left,
+                    point,
+                    countermarch,
+                    &units,
+                    &mut q_formation_mut,
+                    &q_transforms,
+                    &mut q_targets,
+                );
+            } else {
+                designate_frontage(
+                    left,
+                    point,
+                    adjust_width,
+                    pace,
+                    countermarch,
+                    &units,
+                    &mut q_formation_mut,
+                    &mut q_targets,
+                );
+            }
         }
     }
 }
 
-/// Arrange `units` in a grid spanning the frontage from `left` to `right_pt`.
-fn designate_frontage(
-    left: Vec3,
-    right_pt: Vec3,
-    adjust_width: bool,
+/// Pace ordered by the modifiers held, the fastest if several are: Space
+/// ([`Action::Charge`]) charges, Shift ([`Action::DoubleQuick`]), X
+/// ([`Action::QuickMarch`]) and Z ([`Action::Walk`]) march at those paces.
+/// `None` keeps each formation's current pace.
+pub(crate) fn ordered_pace(actions: &Actions) -> Option<Pace> {
+    [
+        (Action::Charge, Pace::Charge),
+        (Action::DoubleQuick, Pace::DoubleQuick),
+        (Action::QuickMarch, Pace::QuickMarch),
+        (Action::Walk, Pace::Walk),
+    ]
+    .into_iter()
+    .find(|&(action, _)| actions.pressed(action))
+    .map(|(_, pace)| pace)
+}
+
+/// Where a frontage puts one unit.
+struct Placement {
+    /// Formation or free boid.
+    unit: Entity,
+    /// Origin position.
+    pos: Vec3,
+    /// For a formation: its layout once the orders taking it there have run
+    /// (files fitted to the frontage, faced about or dressed anew), and its
+    /// member count.
+    layout: Option<(Formation, usize)>,
+}
+
+/// Selected units a frontage places: selected formations directly, member
+/// boids via their formation (its origin takes the slot; propagation moves
+/// the members), free boids on their own.
+pub(crate) fn frontage_units(
     q_selected_boids: &Query<Entity, (With<Selected>, With<Boid>, Without<Formation>)>,
     q_selected_formations: &Query<Entity, (With<Selected>, With<Formation>)>,
     q_member_of: &Query<&MemberOf>,
-    q_formation_mut: &mut Query<&mut Formation>,
-    q_targets: &mut Query<&mut Target>,
-) {
-    let right_vec = right_pt - left;
-    let width = right_vec.length();
-    if width < 0.1 {
-        return;
-    }
-    let right_dir = right_vec / width;
-    // The frontage line has a normal: dragging left->right faces the formation
-    // "up" (+Z on screen), right->left faces it "down". Rows extend BEHIND the
-    // line (opposite the facing), so the line is the formation's front edge.
-    let forward = right_dir.cross(Vec3::Y).normalize();
-
-    // Collect positionable units: formations directly, member boids via their
-    // formation (its origin takes the slot; propagation moves the members).
+) -> Vec<Entity> {
     let mut units: Vec<Entity> = Vec::new();
     for formation in q_selected_formations.iter() {
         if !units.contains(&formation) {
@@ -478,71 +1177,201 @@ fn designate_frontage(
         }
     }
     for boid in q_selected_boids.iter() {
-        match q_member_of.get(boid) {
-            Ok(MemberOf(formation)) => {
-                if !units.contains(formation) {
-                    units.push(*formation);
-                }
-            }
-            Err(_) => {
-                if !units.contains(&boid) {
-                    units.push(boid);
-                }
-            }
+        let unit = q_member_of.get(boid).map_or(boid, |m| m.0);
+        if !units.contains(&unit) {
+            units.push(unit);
         }
     }
-    if units.is_empty() {
-        return;
+    units
+}
+
+/// Facing of the frontage from `left` to `right_pt`, and its width. `None`
+/// for a frontage too short to face.
+fn frontage_facing(left: Vec3, right_pt: Vec3) -> Option<(Vec3, f32)> {
+    let right_vec = right_pt - left;
+    let width = right_vec.length();
+    if width < 0.1 {
+        return None;
     }
+    // The frontage line has a normal: dragging left->right faces the formation
+    // "up" (+Z on screen), right->left faces it "down". Rows extend BEHIND the
+    // line (opposite the facing), so the line is the formation's front edge.
+    let forward = (right_vec / width).cross(Vec3::Y).normalize();
+    Some((forward, width))
+}
 
-    // Minimum spacing: boid slot spacing, widened to the largest formation
-    // extent when any formation is among the units.
-    // Ctrl held: fit each formation's internal grid width to the frontage
-    // (columns = width / SPACING); the slot system re-maps members.
-    if adjust_width {
-        let new_cols = (width / FormationKind::SPACING).round().max(1.0) as usize;
-        for &unit in &units {
-            if let Ok(mut formation) = q_formation_mut.get_mut(unit) {
-                formation.columns = Some(new_cols);
-                info!("[width] formation {unit:?} columns={new_cols}");
+/// Lay `units` out along the frontage from `left` to `right_pt`, front edges
+/// flush with it: the facing, and each unit's [`Placement`]. `None` for a
+/// frontage too short to face. Shared by the release (which orders it) and
+/// the drag preview (which draws it), so what is shown is what is ordered.
+fn plan_frontage(
+    left: Vec3,
+    right_pt: Vec3,
+    adjust_width: bool,
+    countermarch: bool,
+    units: &[Entity],
+    q_formations: &Query<(&mut Formation, Option<&Members>, Option<&Formations>)>,
+) -> Option<(Vec3, Vec<Placement>)> {
+    let (forward, width) = frontage_facing(left, right_pt)?;
+    let right_dir = (right_pt - left) / width;
+    let rotation = Quat::from_rotation_y(forward.x.atan2(forward.z));
+
+    // Each formation as it will stand. Ctrl held: fit its frontage to the
+    // drag (files = width / file pitch) and dress anew; otherwise a reversal
+    // faces it about, or countermarches it (a container faces about, its
+    // sub-formations countermarch). Kinds without files keep their shape.
+    let layouts: Vec<Option<(Formation, usize)>> = units
+        .iter()
+        .map(|&unit| {
+            let (formation, members, subs) = q_formations.get(unit).ok()?;
+            let total = members.map_or(0, |m| m.len()) + subs.map_or(0, |s| s.len());
+            let mut layout = Formation {
+                kind: formation.kind,
+                params: formation.params,
+                faced_about: formation.faced_about,
+                ..Formation::default()
+            };
+            if adjust_width {
+                if formation.kind.has_files() {
+                    let pitch = formation.kind.file_pitch(&formation.params);
+                    layout.params.files = Some((width / pitch).round().max(1.0) as usize);
+                }
+                layout.faced_about = false;
+            } else if formation.reverses(forward) && (!countermarch || subs.is_some()) {
+                layout.faced_about = !layout.faced_about;
             }
-        }
-    }
+            layout.footprint = layout.slot_footprint(total);
+            Some((layout, total))
+        })
+        .collect();
+
+    // Free boids take one slot's worth of ground.
+    let footprints: Vec<Footprint> = layouts
+        .iter()
+        .map(|layout| {
+            layout
+                .as_ref()
+                .map_or(Footprint::default(), |(l, _)| l.footprint)
+        })
+        .collect();
 
-    let mut spacing: f32 = 1.0;
-    for &unit in &units {
-        if let Ok(formation) = q_formation_mut.get_mut(unit) {
-            spacing = spacing.max(formation.extent);
+    // Greedy line packing: fill each line up to the frontage width, then
+    // start the next one behind the deepest unit of the last. A line that
+    // does not fill the frontage spreads its units evenly across it.
+    let mut lines: Vec<Vec<usize>> = vec![Vec::new()];
+    let mut used = 0.0;
+    for (k, footprint) in footprints.iter().enumerate() {
+        let line = lines.last_mut().expect("at least one line");
+        let gap = if line.is_empty() {
+            0.0
+        } else {
+            FormationKind::SPACING
+        };
+        let needed = footprint.width + gap;
+        if !line.is_empty() && used + needed > width {
+            lines.push(vec![k]);
+            used = footprint.width;
+        } else {
+            line.push(k);
+            used += needed;
         }
     }
 
-    let n = units.len();
-    let cols = ((width / spacing) as usize + 1).clamp(1, n);
-    let midpoint = left + right_vec / 2.0;
-
-    for (k, &unit) in units.iter().enumerate() {
-        let col = k % cols;
-        let row = k / cols;
-        let col_x = if cols > 1 {
-            width * col as f32 / (cols - 1) as f32 - width / 2.0
+    let mut positions = vec![Vec3::ZERO; units.len()];
+    let mut line_front = 0.0;
+    for line in &lines {
+        let occupied: f32 = line.iter().map(|&k| footprints[k].width).sum();
+        let gap = if line.len() > 1 {
+            ((width - occupied) / (line.len() - 1) as f32).max(FormationKind::SPACING)
         } else {
             0.0
         };
-        // The line is the FRONT edge: each unit's center sits half its depth
-        // behind it, so the body (boid radius / formation extent) is flush
-        // against the line rather than straddling it.
-        let pos = midpoint + right_dir * col_x - forward * (row as f32 * spacing + spacing * 0.5);
-        if let Ok(mut formation) = q_formation_mut.get_mut(unit) {
+        // Lines wider than the frontage (or a single unit) stay centered.
+        let span = occupied + gap * line.len().saturating_sub(1) as f32;
+        let mut x = (width - span) / 2.0;
+        let mut line_depth: f32 = 0.0;
+        for &k in line {
+            let footprint = &footprints[k];
+            // Middle of the unit's rectangle: its front edge sits on the
+            // line (or on the rear of the previous line); the origin follows
+            // from the footprint's center offset (a wedge's centroid is not
+            // its middle).
+            let middle = left + right_dir * (x + footprint.width / 2.0)
+                - forward * (line_front + footprint.depth / 2.0);
+            positions[k] = middle - rotation * footprint.center;
+            x += footprint.width + gap;
+            line_depth = line_depth.max(footprint.depth);
+        }
+        line_front += line_depth + FormationKind::SPACING;
+    }
+
+    let placements = units
+        .iter()
+        .zip(positions)
+        .zip(layouts)
+        .map(|((&unit, pos), layout)| Placement { unit, pos, layout })
+        .collect();
+    Some((forward, placements))
+}
+
+/// Order `units` onto the frontage from `left` to `right_pt` (see
+/// [`plan_frontage`]). Formations march at `pace`, or their current pace
+/// when `None`. A formation the new frontage reverses faces about first, or
+/// countermarches when `countermarch` is set.
+#[allow(clippy::too_many_arguments)]
+fn designate_frontage(
+    left: Vec3,
+    right_pt: Vec3,
+    adjust_width: bool,
+    pace: Option<Pace>,
+    countermarch: bool,
+    units: &[Entity],
+    q_formation_mut: &mut Query<(&mut Formation, Option<&Members>, Option<&Formations>)>,
+    q_targets: &mut Query<&mut Target>,
+) {
+    let Some((forward, placements)) = plan_frontage(
+        left,
+        right_pt,
+        adjust_width,
+        countermarch,
+        units,
+        q_formation_mut,
+    ) else {
+        return;
+    };
+
+    for Placement { unit, pos, layout } in placements {
+        if let Ok((mut formation, _, _)) = q_formation_mut.get_mut(unit) {
+            // Ctrl held: take the fitted files; the slot system re-maps
+            // members. The footprint is refitted right away so the next
+            // frontage sees the new shape.
+            if let Some((layout, _)) = layout.filter(|_| adjust_width) {
+                if formation.params.files != layout.params.files {
+                    formation.params.files = layout.params.files;
+                    info!("[width] formation {unit:?} files={:?}", layout.params.files);
+                }
+                formation.footprint = layout.footprint;
+            }
             // Formation control goes through the task queue: a new order
             // replaces pending tasks. Move handles the facing-change slot
-            // re-map; a width change reforms first (new columns).
+            // re-map; a width change reforms first (new files).
             formation.tasks.clear();
             if adjust_width {
                 formation.tasks.push_back(FormationOrder::Reform);
+            } else if formation.reverses(forward) {
+                // Reversing keeps the slots: about-face (roles stay,
+                // rear rank leads) or countermarch (front rank leads).
+                formation.tasks.push_back(if countermarch {
+                    FormationOrder::Countermarch
+                } else {
+                    FormationOrder::AboutFace
+                });
             }
+            let pace = pace.unwrap_or(formation.pace);
             formation.tasks.push_back(FormationOrder::Move {
                 pos,
                 facing_dir: forward,
+                pace,
             });
         } else if let Ok(mut target) = q_targets.get_mut(unit) {
             // Free boids (not in any formation): direct target.
@@ -552,16 +1381,156 @@ fn designate_frontage(
     }
 }
 
+/// Move `units` to `point` as they stand: formations keep their
+/// arrangement about their common center and their [`Formation::dir`],
+/// marching at `pace` (or their current pace when `None`); free boids
+/// spread out in a square block about the point.
+pub(crate) fn move_selection(
+    point: Vec3,
+    pace: Option<Pace>,
+    units: &[Entity],
+    q_formation_mut: &mut Query<(&mut Formation, Option<&Members>, Option<&Formations>)>,
+    q_transforms: &Query<&Transform>,
+    q_targets: &mut Query<&mut Target>,
+) {
+    let origins: Vec<(Entity, Vec3)> = units
+        .iter()
+        .filter(|&&unit| q_formation_mut.contains(unit))
+        .filter_map(|&unit| q_transforms.get(unit).ok().map(|t| (unit, t.translation)))
+        .collect();
+    if !origins.is_empty() {
+        let center = origins.iter().map(|&(_, pos)| pos).sum::<Vec3>() / origins.len() as f32;
+        let shift = point - center;
+        for (unit, origin) in &origins {
+            let Ok((mut formation, _, _)) = q_formation_mut.get_mut(*unit) else {
+                continue;
+            };
+            // A formation never ordered anywhere has no facing yet.
+            let facing_dir = formation.dir.try_normalize().unwrap_or(Vec3::Z);
+            let pace = pace.unwrap_or(formation.pace);
+            formation.tasks.clear();
+            formation.tasks.push_back(FormationOrder::Move {
+                pos: *origin + shift,
+                facing_dir,
+                pace,
+            });
+        }
+    }
+
+    let boids: Vec<Entity> = units
+        .iter()
+        .copied()
+        .filter(|&unit| !q_formation_mut.contains(unit))
+        .collect();
+    let columns = (boids.len() as f32).sqrt().ceil() as usize;
+    let rows = boids.len().div_ceil(columns.max(1));
+    for (i, &boid) in boids.iter().enumerate() {
+        let Ok(mut target) = q_targets.get_mut(boid) else {
+            continue;
+        };
+        let x = (i % columns) as f32 - (columns - 1) as f32 / 2.0;
+        let z = (i / columns) as f32 - (rows - 1) as f32 / 2.0;
+        target.pos = point + Vec3::new(x, 0.0, z) * FormationKind::SPACING;
+    }
+    info!(
+        "[move] {} formations, {} free boids -> {point}",
+        origins.len(),
+        boids.len()
+    );
+}
+
+/// Turn `units` where they stand to face the frontage from `left` to
+/// `right_pt`. A formation the new facing reverses faces about first, or
+/// countermarches when `countermarch` is set; then it marches to its own
+/// origin facing the frontage, so the turn is the same `Move` a frontage
+/// orders (a slight one wheels, a sharper one re-maps the slots). Free
+/// boids just turn.
+fn face_in_place(
+    left: Vec3,
+    right_pt: Vec3,
+    countermarch: bool,
+    units: &[Entity],
+    q_formation_mut: &mut Query<(&mut Formation, Option<&Members>, Option<&Formations>)>,
+    q_transforms: &Query<&Transform>,
+    q_targets: &mut Query<&mut Target>,
+) {
+    let Some((forward, _)) = frontage_facing(left, right_pt) else {
+        return;
+    };
+    for &unit in units {
+        if let Ok((mut formation, _, _)) = q_formation_mut.get_mut(unit) {
+            let Ok(transform) = q_transforms.get(unit) else {
+                continue;
+            };
+            let reverses = formation.reverses(forward);
+            formation.tasks.clear();
+            if reverses {
+                formation.tasks.push_back(if countermarch {
+                    FormationOrder::Countermarch
+                } else {
+                    FormationOrder::AboutFace
+                });
+            }
+            let pace = formation.pace;
+            formation.tasks.push_back(FormationOrder::Move {
+                pos: transform.translation,
+                facing_dir: forward,
+                pace,
+            });
+        } else if let Ok(mut target) = q_targets.get_mut(unit) {
+            target.dir = forward;
+        }
+    }
+    info!("[face] {} units -> {forward}", units.len());
+}
+
+/// Ghost markers of a frontage drag in progress: every slot of every
+/// placed formation, laid out as it would stand on release (see
+/// [`plan_frontage`]), with a facing arrow off its front edge; a free boid
+/// gets a single marker.
+fn draw_frontage_preview(forward: Vec3, placements: &[Placement], gizmos: &mut Gizmos) {
+    const LIFT: Vec3 = Vec3::new(0.0, 0.2, 0.0);
+    let color = Color::srgba(0.3, 1.0, 0.3, 0.5);
+    let rotation = Quat::from_rotation_y(forward.x.atan2(forward.z));
+    let flat = Quat::from_rotation_x(-FRAC_PI_2);
+    let radius = FormationKind::SPACING / 4.0;
+    for placement in placements {
+        let Some((layout, total)) = &placement.layout else {
+            gizmos.circle(Isometry3d::new(placement.pos + LIFT, flat), radius, color);
+            continue;
+        };
+        for index in 0..*total {
+            let slot = placement.pos + rotation * layout.slot_offset(index, *total);
+            gizmos.circle(Isometry3d::new(slot + LIFT, flat), radius, color);
+        }
+        let footprint = &layout.footprint;
+        let front = footprint.world_center(placement.pos, rotation)
+            + forward * (footprint.depth / 2.0)
+            + LIFT;
+        gizmos.arrow(front, front + forward * FormationKind::SPACING, color);
+    }
+}
+
 /// Point each selected boid's triangle indicator along its current movement
 /// direction (velocity if moving, else its target direction). Formation
-/// indicators (squares) are skipped - their facing is the formation's.
+/// indicators (rectangles) take their facing from the formation; they are
+/// refitted to its [`Footprint`], which changes with kind, params and
+/// member count.
 pub fn selection_indicator_face(
     q_boids: Query<(&Velocity, &Target), With<Boid>>,
+    q_formations: Query<&Formation>,
     mut q_indicators: Query<(&mut Transform, &ChildOf), With<SelectionIndicator>>,
 ) {
     for (mut transform, parent) in &mut q_indicators {
+        if let Ok(formation) = q_formations.get(parent.parent()) {
+            let fitted = footprint_indicator_transform(&formation.footprint);
+            if *transform != fitted {
+                *transform = fitted;
+            }
+            continue;
+        }
         let Ok((velocity, target)) = q_boids.get(parent.parent()) else {
-            continue; // formation square: orientation handled by the formation
+            continue;
         };
         let dir = if velocity.v.length_squared() > 0.01 {
             velocity.v
@@ -573,3 +1542,789 @@ pub fn selection_indicator_face(
         }
     }
 }
+
+/// Gizmo group of the order overlay ([`order_overlay_system`]), so it can be
+/// switched off without touching the other gizmos.
+#[derive(Default, Reflect, GizmoConfigGroup)]
+pub struct OrderGizmos;
+
+/// T ([`Action::OrderOverlay`]) shows or hides the order overlay.
+pub fn order_overlay_toggle_system(actions: Actions, mut config_store: ResMut<GizmoConfigStore>) {
+    if !actions.just_pressed(Action::OrderOverlay) {
+        return;
+    }
+    let (config, _) = config_store.config_mut::<OrderGizmos>();
+    config.enabled = !config.enabled;
+    info!(
+        "[orders] overlay {}",
+        if config.enabled { "on" } else { "off" }
+    );
+}
+
+/// Draws the task queue of the selected formations (including the
+/// formations of selected member boids): the lead point with the line from
+/// the center of mass to it, then every queued `Move` frontage as a ghost
+/// [`Footprint`] with a facing arrow, linked in queue order. `Hold`
+/// positions are drawn the same way in their own colour, ringed.
+pub fn order_overlay_system(
+    q_selected: SelectedUnits,
+    q_formations: Query<(&Formation, &Lead)>,
+    mut gizmos: Gizmos<OrderGizmos>,
+) {
+    const LIFT: Vec3 = Vec3::new(0.0, 0.2, 0.0);
+    let lead_color = Color::srgb(0.2, 0.6, 1.0);
+    let link_color = Color::srgb(0.5, 0.5, 0.5);
+    let move_color = Color::srgba(0.3, 1.0, 0.3, 0.6);
+    let hold_color = Color::srgba(1.0, 0.5, 0.2, 0.6);
+
+    let formations = selected_formations(&q_selected, |f| q_formations.contains(f));
+    for formation_entity in formations {
+        let Ok((formation, lead)) = q_formations.get(formation_entity) else {
+            continue;
+        };
+        gizmos.line(lead.center_of_mass + LIFT, lead.goal + LIFT, lead_color);
+        gizmos.sphere(
+            Isometry3d::from_translation(lead.goal + LIFT),
+            0.3,
+            lead_color,
+        );
+
+        let mut from = lead.goal;
+        for task in &formation.tasks {
+            let (pos, facing_dir, color) = match *task {
+                FormationOrder::Move {
+                    pos, facing_dir, ..
+                }
+                | FormationOrder::Charge {
+                    pos, facing_dir, ..
+                } => (pos, facing_dir, move_color),
+                FormationOrder::Hold { pos, facing_dir } => (pos, facing_dir, hold_color),
+                _ => continue,
+            };
+            if from.distance(pos) > 1e-3 {
+                gizmos.line(from + LIFT, pos + LIFT, link_color);
+            }
+            from = pos;
+
+            let footprint = &formation.footprint;
+            let rotation = Quat::from_rotation_y(facing_dir.x.atan2(facing_dir.z));
+            let center = footprint.world_center(pos, rotation) + LIFT;
+            gizmos.rect(
+                Isometry3d::new(center, rotation * Quat::from_rotation_x(-FRAC_PI_2)),
+                Vec2::new(footprint.width, footprint.depth),
+                color,
+            );
+            let front = center + rotation * Vec3::Z * (footprint.depth / 2.0);
+            gizmos.arrow(
+                front,
+                front + rotation * Vec3::Z * FormationKind::SPACING,
+                color,
+            );
+            if matches!(task, FormationOrder::Hold { .. }) {
+                gizmos.circle(
+                    Isometry3d::new(pos + LIFT, Quat::from_rotation_x(-FRAC_PI_2)),
+                    FormationKind::SPACING / 2.0,
+                    color,
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formations::FormationParams;
+    use crate::input::InputMap;
+    use crate::kinematics::TrackedByTree;
+    use crate::vision::VIEW_RANGE;
+    use bevy::ecs::system::SystemState;
+    use bevy::prelude::{App, ButtonInput, KeyCode, MouseButton, Update};
+    use bevy_spatial::{AutomaticUpdate, TransformMode};
+    use std::time::Duration;
+
+    /// Headless app with manual time and the kd tree refreshed by the real
+    /// AutomaticUpdate plugin.
+    fn test_app() -> App {
+        let mut app = App::new();
+        app.init_resource::<Time>().add_plugins(
+            AutomaticUpdate::<TrackedByTree>::new()
+                .with_frequency(Duration::from_secs_f32(1.0 / 20.0))
+                .with_transform(TransformMode::Transform),
+        );
+        app
+    }
+
+    fn tick(app: &mut App, dt: f32) {
+        app.world_mut()
+            .resource_mut::<Time>()
+            .advance_by(Duration::from_secs_f32(dt));
+        app.update();
+    }
+
+    fn spawn_boid(app: &mut App, pos: Vec3, faction: Faction) -> Entity {
+        app.world_mut()
+            .spawn((Transform::from_translation(pos), TrackedByTree, faction))
+            .id()
+    }
+
+    fn spawn_formation(app: &mut App, width: f32, depth: f32) -> Entity {
+        let formation = Formation {
+            footprint: Footprint {
+                width,
+                depth,
+                center: Vec3::ZERO,
+            },
+            ..default()
+        };
+        app.world_mut()
+            .spawn((formation, Transform::default(), Faction(0)))
+            .id()
+    }
+
+    /// What a click at `point` picks among the units of faction 0.
+    fn pick_at(app: &mut App, point: Vec3) -> Option<Entity> {
+        let world = app.world_mut();
+        let mut state: SystemState<(
+            Res<NNTree>,
+            Query<(Entity, &Transform, &Formation)>,
+            Query<&Faction>,
+        )> = SystemState::new(world);
+        let (tree, q_formations, q_faction) = state.get(world);
+        pick(point, &tree, &q_formations, |entity| {
+            q_faction.get(entity).is_ok_and(|f| *f == Faction(0))
+        })
+    }
+
+    #[test]
+    fn click_picks_the_nearest_selectable_boid() {
+        let mut app = test_app();
+        let near = spawn_boid(&mut app, Vec3::new(0.3, 0.0, 0.0), Faction(0));
+        spawn_boid(&mut app, Vec3::new(0.8, 0.0, 0.0), Faction(0));
+        // Nearer still, but not ours to select.
+        spawn_boid(&mut app, Vec3::new(0.1, 0.0, 0.0), Faction(1));
+        // Bobbing above the ground is no further away.
+        let high = spawn_boid(&mut app, Vec3::new(-5.0, 1.2, 0.0), Faction(0));
+        tick(&mut app, 0.1);
+
+        assert_eq!(pick_at(&mut app, Vec3::ZERO), Some(near));
+        assert_eq!(pick_at(&mut app, Vec3::new(-5.0, 0.0, 0.2)), Some(high));
+        assert_eq!(pick_at(&mut app, Vec3::new(20.0, 0.0, 0.0)), None);
+    }
+
+    #[test]
+    fn click_off_the_boids_picks_the_smallest_footprint_under_it() {
+        let mut app = test_app();
+        let battalion = spawn_formation(&mut app, 20.0, 10.0);
+        let company = spawn_formation(&mut app, 6.0, 4.0);
+        // Out of PICK_RADIUS of the click.
+        spawn_boid(&mut app, Vec3::new(4.0, 0.0, 0.0), Faction(0));
+        tick(&mut app, 0.1);
+
+        assert_eq!(pick_at(&mut app, Vec3::new(2.0, 0.0, 1.0)), Some(company));
+        assert_eq!(pick_at(&mut app, Vec3::new(8.0, 0.0, 1.0)), Some(battalion));
+        assert_eq!(pick_at(&mut app, Vec3::new(0.0, 0.0, 8.0)), None);
+    }
+
+    #[test]
+    fn enemies_out_of_sight_are_not_picked() {
+        let mut app = test_app();
+        let mut fog = FogOfWar::new(100.0);
+        fog.update(2, [], [(Faction(0), Vec3::ZERO, VIEW_RANGE)]);
+        app.init_resource::<Factions>().insert_resource(fog);
+        let formation = app
+            .world_mut()
+            .spawn((Formation::default(), Transform::default(), Faction(1)))
+            .id();
+        let seen = spawn_boid(&mut app, Vec3::new(10.0, 0.0, 0.0), Faction(1));
+        app.world_mut().entity_mut(seen).insert(MemberOf(formation));
+        let unseen = spawn_boid(&mut app, Vec3::new(80.0, 0.0, 0.0), Faction(1));
+        spawn_boid(&mut app, Vec3::new(-10.0, 0.0, 0.0), Faction(0));
+        tick(&mut app, 0.1);
+
+        let world = app.world_mut();
+        let mut state: SystemState<EnemyPicker<'static, 'static>> = SystemState::new(world);
+        let picker = state.get(world);
+        assert_eq!(
+            picker.pick(Vec3::new(10.0, 0.0, 0.0)),
+            Some(formation),
+            "in sight, taken as its formation"
+        );
+        assert_eq!(picker.pick(Vec3::new(80.0, 0.0, 0.0)), None, "out of sight");
+        assert_eq!(picker.pick(Vec3::new(-10.0, 0.0, 0.0)), None, "our own");
+
+        world.resource_mut::<FogOfWar>().enabled = false;
+        let picker = state.get(world);
+        assert_eq!(picker.pick(Vec3::new(80.0, 0.0, 0.0)), Some(unseen));
+    }
+
+    /// App running [`quick_group_system`] on the default bindings.
+    fn group_app() -> App {
+        let mut app = App::new();
+        app.init_resource::<Time<Real>>()
+            .init_resource::<Player>()
+            .init_resource::<Factions>()
+            .init_resource::<CommandHierarchy>()
+            .init_resource::<InputMap>()
+            .init_resource::<ButtonInput<KeyCode>>()
+            .init_resource::<ButtonInput<MouseButton>>()
+            .add_systems(Update, quick_group_system);
+        app
+    }
+
+    /// Hold `keys` down together for a frame, then let go.
+    fn press(app: &mut App, keys: &[KeyCode]) {
+        let mut input = app.world_mut().resource_mut::<ButtonInput<KeyCode>>();
+        for &key in keys {
+            input.press(key);
+        }
+        app.update();
+        let mut input = app.world_mut().resource_mut::<ButtonInput<KeyCode>>();
+        input.release_all();
+        input.clear();
+    }
+
+    fn spawn_selected_boid(app: &mut App, pos: Vec3) -> Entity {
+        app.world_mut()
+            .spawn((Transform::from_translation(pos), Faction(0), Selected))
+            .id()
+    }
+
+    /// The formations in quick command group `slot`.
+    fn in_group(world: &mut World, slot: u8) -> Vec<Entity> {
+        let mut query = world.query::<(Entity, &QuickCommandGroup)>();
+        let mut group: Vec<Entity> = query
+            .iter(world)
+            .filter(|(_, g)| g.0 == slot)
+            .map(|(entity, _)| entity)
+            .collect();
+        group.sort();
+        group
+    }
+
+    fn formation_of(world: &World, boid: Entity) -> Option<Entity> {
+        world.get::<MemberOf>(boid).map(|m| m.0)
+    }
+
+    #[test]
+    fn assigning_selected_boids_replaces_the_group_with_a_new_formation() {
+        let mut app = group_app();
+        let first: Vec<Entity> = (0..3)
+            .map(|i| spawn_selected_boid(&mut app, Vec3::X * i as f32))
+            .collect();
+        press(&mut app, &[KeyCode::ControlLeft, KeyCode::Digit1]);
+
+        let world = app.world_mut();
+        let [old] = in_group(world, 1)[..] else {
+            panic!("group 1 should hold one formation");
+        };
+        for &boid in &first {
+            assert_eq!(formation_of(world, boid), Some(old));
+        }
+        assert!(
+            world.get::<Selected>(old).is_none(),
+            "Ctrl+1 holds 1, but must not recall the group too"
+        );
+
+        for &boid in &first {
+            world.entity_mut(boid).remove::<Selected>();
+        }
+        let second: Vec<Entity> = (0..2)
+            .map(|i| spawn_selected_boid(&mut app, Vec3::Z * i as f32))
+            .collect();
+        press(&mut app, &[KeyCode::ControlLeft, KeyCode::Digit1]);
+
+        let world = app.world_mut();
+        let [new] = in_group(world, 1)[..] else {
+            panic!("group 1 should hold one formation");
+        };
+        assert!(world.get_entity(old).is_err(), "old formation disbanded");
+        for &boid in &first {
+            assert_eq!(formation_of(world, boid), None);
+        }
+        for &boid in &second {
+            assert_eq!(formation_of(world, boid), Some(new));
+        }
+    }
+
+    #[test]
+    fn assigning_selected_formations_makes_them_the_whole_group() {
+        let mut app = group_app();
+        let world = app.world_mut();
+        let before = world
+            .spawn((
+                Formation::default(),
+                Transform::default(),
+                QuickCommandGroup(1),
+            ))
+            .id();
+        let kept = world
+            .spawn((
+                Formation::default(),
+                Transform::default(),
+                QuickCommandGroup(1),
+                Selected,
+            ))
+            .id();
+        let joined = world.spawn((Formation::default(), Selected)).id();
+        let other = world
+            .spawn((
+                Formation::default(),
+                Transform::default(),
+                QuickCommandGroup(2),
+            ))
+            .id();
+        press(&mut app, &[KeyCode::ControlRight, KeyCode::Digit1]);
+
+        let world = app.world_mut();
+        let mut expected = vec![kept, joined];
+        expected.sort();
+        assert_eq!(in_group(world, 1), expected);
+        assert!(world.get::<QuickCommandGroup>(before).is_none());
+        assert_eq!(in_group(world, 2), vec![other], "other groups untouched");
+        assert!(world.get_entity(before).is_ok(), "left, not disbanded");
+    }
+
+    #[test]
+    fn adding_joins_the_largest_formation_of_the_group() {
+        let mut app = group_app();
+        let world = app.world_mut();
+        let large = world
+            .spawn((
+                Formation::default(),
+                Transform::default(),
+                QuickCommandGroup(2),
+            ))
+            .id();
+        let small = world
+            .spawn((
+                Formation::default(),
+                Transform::default(),
+                QuickCommandGroup(2),
+            ))
+            .id();
+        for formation in [large, large, small] {
+            world.spawn((Transform::default(), Faction(0), MemberOf(formation)));
+        }
+        let selected = world.spawn((Formation::default(), Selected)).id();
+        // Comes along with its selected formation instead.
+        let member = world
+            .spawn((
+                Transform::default(),
+                Faction(0),
+                MemberOf(selected),
+                Selected,
+            ))
+            .id();
+        let free = spawn_selected_boid(&mut app, Vec3::ZERO);
+        press(&mut app, &[KeyCode::ShiftLeft, KeyCode::Digit2]);
+
+        let world = app.world_mut();
+        let mut expected = vec![large, small, selected];
+        expected.sort();
+        assert_eq!(in_group(world, 2), expected, "nothing disbanded");
+        assert_eq!(formation_of(world, free), Some(large));
+        assert_eq!(formation_of(world, member), Some(selected));
+    }
+
+    #[test]
+    fn adding_to_an_empty_group_builds_a_formation() {
+        let mut app = group_app();
+        let boids: Vec<Entity> = (0..2)
+            .map(|i| spawn_selected_boid(&mut app, Vec3::X * i as f32))
+            .collect();
+        press(&mut app, &[KeyCode::ShiftLeft, KeyCode::Digit4]);
+
+        let world = app.world_mut();
+        let [formation] = in_group(world, 4)[..] else {
+            panic!("group 4 should hold one formation");
+        };
+        for &boid in &boids {
+            assert_eq!(formation_of(world, boid), Some(formation));
+        }
+    }
+
+    #[test]
+    fn recalling_a_group_replaces_the_selection() {
+        let mut app = group_app();
+        let world = app.world_mut();
+        let group: Vec<Entity> = (0..2)
+            .map(|_| {
+                world
+                    .spawn((
+                        Formation::default(),
+                        Transform::default(),
+                        QuickCommandGroup(3),
+                    ))
+                    .id()
+            })
+            .collect();
+        let stray = world.spawn((Formation::default(), Selected)).id();
+        let boid = spawn_selected_boid(&mut app, Vec3::ZERO);
+        press(&mut app, &[KeyCode::Digit3]);
+
+        let world = app.world_mut();
+        for &formation in &group {
+            assert!(world.get::<Selected>(formation).is_some());
+        }
+        assert!(world.get::<Selected>(stray).is_none());
+        assert!(world.get::<Selected>(boid).is_none());
+
+        // An empty group leaves the selection alone.
+        press(&mut app, &[KeyCode::Digit5]);
+        let world = app.world_mut();
+        for &formation in &group {
+            assert!(world.get::<Selected>(formation).is_some());
+        }
+    }
+
+    /// A formation of `members` boids, drawn up as `kind` facing +Z.
+    fn spawn_drawn_up(world: &mut World, kind: FormationKind, members: usize) -> Entity {
+        let formation = world
+            .spawn((
+                Formation {
+                    kind,
+                    dir: Vec3::Z,
+                    ..default()
+                },
+                Transform::default(),
+            ))
+            .id();
+        for _ in 0..members {
+            world.spawn((Transform::default(), MemberOf(formation)));
+        }
+        formation
+    }
+
+    /// Plan the frontage from `left` to `right` for `units` as the drag
+    /// preview does, then order it as the release does.
+    fn plan_and_order(
+        world: &mut World,
+        (left, right): (Vec3, Vec3),
+        adjust_width: bool,
+        countermarch: bool,
+        units: &[Entity],
+    ) -> (Vec3, Vec<Placement>) {
+        let mut state: SystemState<(
+            Query<(&mut Formation, Option<&Members>, Option<&Formations>)>,
+            Query<&mut Target>,
+        )> = SystemState::new(world);
+        let (mut q_formations, mut q_targets) = state.get_mut(world);
+        let plan = plan_frontage(
+            left,
+            right,
+            adjust_width,
+            countermarch,
+            units,
+            &q_formations,
+        )
+        .expect("a frontage long enough to face");
+        designate_frontage(
+            left,
+            right,
+            adjust_width,
+            None,
+            countermarch,
+            units,
+            &mut q_formations,
+            &mut q_targets,
+        );
+        plan
+    }
+
+    fn tasks(world: &World, formation: Entity) -> Vec<FormationOrder> {
+        let formation = world.get::<Formation>(formation).unwrap();
+        formation.tasks.iter().copied().collect()
+    }
+
+    /// The `Move` ending `formation`'s orders: where and facing what.
+    fn ordered_move(world: &World, formation: Entity) -> (Vec3, Vec3) {
+        match tasks(world, formation).last() {
+            Some(&FormationOrder::Move {
+                pos, facing_dir, ..
+            }) => (pos, facing_dir),
+            other => panic!("expected a Move last, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn pace_modifiers_order_every_pace_but_the_default_march() {
+        let mut world = World::new();
+        world.insert_resource(InputMap::default());
+        world.insert_resource(ButtonInput::<KeyCode>::default());
+        world.insert_resource(ButtonInput::<MouseButton>::default());
+        let mut state = SystemState::<Actions>::new(&mut world);
+        assert_eq!(ordered_pace(&state.get(&world)), None, "keeps its pace");
+        for (key, pace) in [
+            (KeyCode::KeyZ, Pace::Walk),
+            (KeyCode::KeyX, Pace::QuickMarch),
+            (KeyCode::ShiftLeft, Pace::DoubleQuick),
+            (KeyCode::Space, Pace::Charge),
+        ] {
+            let mut keys = world.resource_mut::<ButtonInput<KeyCode>>();
+            keys.release_all();
+            keys.press(key);
+            assert_eq!(ordered_pace(&state.get(&world)), Some(pace), "{key:?}");
+        }
+        // Space still held: with Z as well, the charge wins.
+        world
+            .resource_mut::<ButtonInput<KeyCode>>()
+            .press(KeyCode::KeyZ);
+        assert_eq!(ordered_pace(&state.get(&world)), Some(Pace::Charge));
+    }
+
+    #[test]
+    fn frontage_orders_what_it_plans() {
+        let mut world = World::new();
+        let line = spawn_drawn_up(&mut world, FormationKind::Line, 6);
+        let grid = spawn_drawn_up(&mut world, FormationKind::Grid, 9);
+        let free = world.spawn((Transform::default(), Target::default())).id();
+        let frontage = (Vec3::ZERO, Vec3::new(30.0, 0.0, 0.0));
+        let (forward, plan) =
+            plan_and_order(&mut world, frontage, false, false, &[line, grid, free]);
+
+        assert_eq!(forward, Vec3::Z);
+        assert_eq!(plan.len(), 3);
+        for placement in &plan {
+            if placement.unit == free {
+                let target = world.get::<Target>(free).unwrap();
+                assert_eq!((target.pos, target.dir), (placement.pos, forward));
+                assert!(placement.layout.is_none());
+                continue;
+            }
+            assert_eq!(
+                ordered_move(&world, placement.unit),
+                (placement.pos, forward)
+            );
+            assert_eq!(tasks(&world, placement.unit).len(), 1, "no reversal");
+            let (layout, _) = placement.layout.as_ref().unwrap();
+            assert!(!layout.faced_about);
+        }
+    }
+
+    #[test]
+    fn fitted_frontage_orders_the_planned_width() {
+        let mut world = World::new();
+        let line = spawn_drawn_up(&mut world, FormationKind::Line, 12);
+        let pitch = FormationKind::Line.file_pitch(&FormationParams::default());
+        let frontage = (Vec3::ZERO, Vec3::new(4.0 * pitch, 0.0, 0.0));
+        let (forward, plan) = plan_and_order(&mut world, frontage, true, false, &[line]);
+
+        let [
+            Placement {
+                pos,
+                layout: Some((ref layout, total)),
+                ..
+            },
+        ] = plan[..]
+        else {
+            panic!("one formation placed");
+        };
+        assert_eq!(total, 12);
+        assert_eq!(layout.params.files, Some(4));
+        let formation = world.get::<Formation>(line).unwrap();
+        assert_eq!(formation.params.files, layout.params.files);
+        assert_eq!(formation.footprint, layout.footprint);
+        assert!(matches!(
+            tasks(&world, line)[..],
+            [FormationOrder::Reform, FormationOrder::Move { .. }]
+        ));
+        assert_eq!(ordered_move(&world, line), (pos, forward));
+    }
+
+    #[test]
+    fn reversing_frontage_plans_the_about_face_it_orders() {
+        let mut world = World::new();
+        let about = spawn_drawn_up(&mut world, FormationKind::Line, 8);
+        let counter = spawn_drawn_up(&mut world, FormationKind::Line, 8);
+        // Dragged right to left: the frontage faces -Z, behind them.
+        let frontage = (Vec3::new(20.0, 0.0, 0.0), Vec3::ZERO);
+
+        let (forward, plan) = plan_and_order(&mut world, frontage, false, false, &[about]);
+        assert_eq!(forward, Vec3::NEG_Z);
+        let (layout, _) = plan[0].layout.as_ref().unwrap();
+        assert!(layout.faced_about, "faced about once the orders have run");
+        assert!(matches!(
+            tasks(&world, about)[..],
+            [FormationOrder::AboutFace, FormationOrder::Move { .. }]
+        ));
+        assert_eq!(ordered_move(&world, about), (plan[0].pos, forward));
+
+        // Countermarching keeps the layout the right way round.
+        let (_, plan) = plan_and_order(&mut world, frontage, false, true, &[counter]);
+        let (layout, _) = plan[0].layout.as_ref().unwrap();
+        assert!(!layout.faced_about);
+        assert!(matches!(
+            tasks(&world, counter)[..],
+            [FormationOrder::Countermarch, FormationOrder::Move { .. }]
+        ));
+        assert_eq!(ordered_move(&world, counter), (plan[0].pos, forward));
+    }
+
+    type OrderState = SystemState<(
+        Query<
+            'static,
+            'static,
+            (
+                &'static mut Formation,
+                Option<&'static Members>,
+                Option<&'static Formations>,
+            ),
+        >,
+        Query<'static, 'static, &'static Transform>,
+        Query<'static, 'static, &'static mut Target>,
+    )>;
+
+    #[test]
+    fn click_move_shifts_formations_as_they_stand() {
+        let mut world = World::new();
+        let left = spawn_drawn_up(&mut world, FormationKind::Line, 4);
+        let right = spawn_drawn_up(&mut world, FormationKind::Line, 4);
+        let unfaced = spawn_drawn_up(&mut world, FormationKind::Grid, 4);
+        world
+            .entity_mut(left)
+            .insert(Transform::from_xyz(-5.0, 0.0, 0.0));
+        world
+            .entity_mut(right)
+            .insert(Transform::from_xyz(5.0, 0.0, 0.0));
+        world
+            .entity_mut(unfaced)
+            .insert(Transform::from_xyz(0.0, 0.0, 3.0));
+        world.get_mut::<Formation>(right).unwrap().dir = Vec3::X;
+        let mut details = world.get_mut::<Formation>(unfaced).unwrap();
+        details.dir = Vec3::ZERO;
+        details.pace = Pace::Walk;
+
+        let mut state = OrderState::new(&mut world);
+        let (mut q_formations, q_transforms, mut q_targets) = state.get_mut(&mut world);
+        let point = Vec3::new(0.0, 0.0, 21.0);
+        move_selection(
+            point,
+            None,
+            &[left, right, unfaced],
+            &mut q_formations,
+            &q_transforms,
+            &mut q_targets,
+        );
+
+        // The common center (0, 0, 1) goes to the point; each keeps its
+        // place about it and its facing.
+        assert_eq!(
+            ordered_move(&world, left),
+            (Vec3::new(-5.0, 0.0, 20.0), Vec3::Z)
+        );
+        assert_eq!(
+            ordered_move(&world, right),
+            (Vec3::new(5.0, 0.0, 20.0), Vec3::X)
+        );
+        assert_eq!(
+            ordered_move(&world, unfaced),
+            (Vec3::new(0.0, 0.0, 23.0), Vec3::Z),
+            "never faced anywhere: forward"
+        );
+        // Each keeps its current pace.
+        for (formation, pace) in [(left, Pace::default()), (unfaced, Pace::Walk)] {
+            assert!(matches!(
+                tasks(&world, formation)[..],
+                [FormationOrder::Move { pace: p, .. }] if p == pace
+            ));
+        }
+    }
+
+    #[test]
+    fn click_move_spreads_free_boids_in_a_square() {
+        let mut world = World::new();
+        let boids: Vec<Entity> = (0..4)
+            .map(|_| world.spawn((Transform::default(), Target::default())).id())
+            .collect();
+        let mut state = OrderState::new(&mut world);
+        let (mut q_formations, q_transforms, mut q_targets) = state.get_mut(&mut world);
+        let point = Vec3::new(10.0, 0.0, -4.0);
+        move_selection(
+            point,
+            Some(Pace::Charge),
+            &boids,
+            &mut q_formations,
+            &q_transforms,
+            &mut q_targets,
+        );
+
+        let half = FormationKind::SPACING / 2.0;
+        let expected = [
+            Vec3::new(-half, 0.0, -half),
+            Vec3::new(half, 0.0, -half),
+            Vec3::new(-half, 0.0, half),
+            Vec3::new(half, 0.0, half),
+        ];
+        for (boid, offset) in boids.iter().zip(expected) {
+            assert_eq!(world.get::<Target>(*boid).unwrap().pos, point + offset);
+        }
+    }
+
+    #[test]
+    fn turning_in_place_marches_to_where_it_stands() {
+        let mut world = World::new();
+        let turning = spawn_drawn_up(&mut world, FormationKind::Line, 6);
+        let reversing = spawn_drawn_up(&mut world, FormationKind::Line, 6);
+        let origin = Vec3::new(3.0, 0.0, 4.0);
+        world
+            .entity_mut(turning)
+            .insert(Transform::from_translation(origin));
+        world
+            .entity_mut(reversing)
+            .insert(Transform::from_translation(origin));
+        let free = world
+            .spawn((Transform::from_translation(origin), Target::default()))
+            .id();
+        world.get_mut::<Target>(free).unwrap().pos = origin;
+        // Facing -X, it faces about first.
+        world.get_mut::<Formation>(reversing).unwrap().dir = Vec3::NEG_X;
+
+        let mut state = OrderState::new(&mut world);
+        let (mut q_formations, q_transforms, mut q_targets) = state.get_mut(&mut world);
+        // Dragged towards -Z: the frontage faces +X.
+        let frontage = (Vec3::ZERO, Vec3::new(0.0, 0.0, -10.0));
+        face_in_place(
+            frontage.0,
+            frontage.1,
+            false,
+            &[turning, reversing, free],
+            &mut q_formations,
+            &q_transforms,
+            &mut q_targets,
+        );
+        assert!(matches!(
+            tasks(&world, turning)[..],
+            [FormationOrder::Move { .. }]
+        ));
+        assert_eq!(ordered_move(&world, turning), (origin, Vec3::X));
+        assert!(matches!(
+            tasks(&world, reversing)[..],
+            [FormationOrder::AboutFace, FormationOrder::Move { .. }]
+        ));
+        assert_eq!(ordered_move(&world, reversing), (origin, Vec3::X));
+        let target = world.get::<Target>(free).unwrap();
+        assert_eq!((target.pos, target.dir), (origin, Vec3::X), "just turns");
+    }
+
+    #[test]
+    fn second_quick_click_on_the_same_pick_is_a_double_click() {
+        let mut world = World::new();
+        let (a, b) = (world.spawn_empty().id(), world.spawn_empty().id());
+        let mut player = Player::default();
+
+        assert!(!player.click(10.0, a));
+        assert!(player.click(10.2, a));
+        // A third click starts over...
+        assert!(!player.click(10.3, a));
+        // ...and makes a double with the next.
+        assert!(player.click(10.5, a));
+
+        assert!(!player.click(20.0, a));
+        assert!(!player.click(20.1, b), "another pick");
+        assert!(
+            !player.click(20.1 + DOUBLE_CLICK_SECS + 0.01, b),
+            "too late"
+        );
+    }
+}
@@ -1,3 +1,4 @@
+use crate::faction::Faction;
 use bevy::math::Vec3;
 use bevy::prelude::{Handle, Mesh, Resource, StandardMaterial};
 
@@ -12,4 +13,16 @@ pub struct Materials {
     pub debug_material: Handle<StandardMaterial>,
     pub black: Handle<StandardMaterial>,
     pub white: Handle<StandardMaterial>,
-}
\ No newline at end of file
+    /// Boid material of each faction, indexed by [`Faction`].
+    pub factions: Vec<Handle<StandardMaterial>>,
+}
+
+impl Materials {
+    /// Boid material of `faction`; the debug material for one without.
+    pub fn faction(&self, faction: Faction) -> Handle<StandardMaterial> {
+        self.factions
+            .get(faction.0 as usize)
+            .unwrap_or(&self.debug_material)
+            .clone()
+    }
+}
@@ -0,0 +1,557 @@
+use crate::boid::Boid;
+use crate::combat::Health;
+use crate::faction::{Faction, Factions};
+use crate::formations::{
+    Formation, FormationOf, Formations, LodUnloaded, Members, NeedsSpeedInit, QuickCommandGroup,
+};
+use crate::kinematics::TrackedByTree;
+use bevy::prelude::*;
+
+/// Scenario identifier of a formation, for [`FormationRef::Id`]. Unlike a
+/// [`QuickCommandGroup`], the player cannot reassign it.
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ScenarioId(pub u32);
+
+/// The formations an objective is about.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FormationRef {
+    /// Every formation in this [`QuickCommandGroup`].
+    Group(u8),
+    /// The formation carrying this [`ScenarioId`].
+    Id(u32),
+}
+
+/// What a faction has to achieve.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ObjectiveKind {
+    /// Keep the ground within `radius` of `center` for `secs` seconds on
+    /// end: some of the faction there and no enemy.
+    HoldZone {
+        center: Vec3,
+        radius: f32,
+        secs: f32,
+    },
+    /// Destroy or rout `fraction` (0..=1) of the enemy's top-level
+    /// formations, counted when the scenario starts.
+    Rout { fraction: f32 },
+    /// Bring `formation` to within `radius` of `to`. Failed if it is
+    /// destroyed or routed on the way.
+    Escort {
+        formation: FormationRef,
+        to: Vec3,
+        radius: f32,
+    },
+    /// Still be on the field `secs` seconds into the scenario.
+    Survive { secs: f32 },
+}
+
+/// Where an objective stands.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ObjectiveStatus {
+    #[default]
+    Pending,
+    Complete,
+    Failed,
+}
+
+/// An objective of `faction`'s. A faction wins once all of its objectives
+/// are complete, and loses as soon as one fails.
+#[derive(Clone, Debug)]
+pub struct Objective {
+    pub faction: Faction,
+    pub kind: ObjectiveKind,
+    pub status: ObjectiveStatus,
+    /// Seconds the zone has been held, for [`ObjectiveKind::HoldZone`].
+    pub held: f32,
+    /// Enemy formations at the start, for [`ObjectiveKind::Rout`]. Taken
+    /// once every formation knows its faction.
+    initial: Option<Vec<Entity>>,
+}
+
+impl Objective {
+    pub fn new(faction: Faction, kind: ObjectiveKind) -> Self {
+        Self {
+            faction,
+            kind,
+            status: ObjectiveStatus::default(),
+            held: 0.0,
+            initial: None,
+        }
+    }
+}
+
+/// The objectives in play, checked every fixed tick by
+/// [`scenario_system`].
+#[derive(Resource, Default, Debug)]
+pub struct Scenario {
+    pub objectives: Vec<Objective>,
+    /// Seconds since the scenario started.
+    pub elapsed: f32,
+}
+
+/// How the battle stands for the local player (see [`Factions::local`]).
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BattleState {
+    #[default]
+    InProgress,
+    Victory,
+    Defeat,
+}
+
+/// Progress of the scenario, for systems that react to it (UI, AI).
+#[derive(Message, Clone, Copy, Debug)]
+pub enum ScenarioEvent {
+    ObjectiveComplete {
+        objective: usize,
+        faction: Faction,
+    },
+    ObjectiveFailed {
+        objective: usize,
+        faction: Faction,
+    },
+    /// `faction` achieved all its objectives.
+    Victory {
+        faction: Faction,
+    },
+    /// `faction` failed one of its objectives.
+    Defeat {
+        faction: Faction,
+    },
+}
+
+/// Boids below `formation` at any depth: none left means destroyed or
+/// routed.
+fn strength(
+    q_hierarchy: &Query<(Option<&Members>, Option<&Formations>), With<Formation>>,
+    formation: Entity,
+) -> usize {
+    let Ok((members, subs)) = q_hierarchy.get(formation) else {
+        return 0;
+    };
+    members.map_or(0, |m| m.len())
+        + subs
+            .into_iter()
+            .flat_map(|s| s.iter())
+            .map(|sub| strength(q_hierarchy, sub))
+            .sum::<usize>()
+}
+
+/// Check every pending objective against the boids and formations as they
+/// stand, and settle the battle once a faction has completed all of its
+/// objectives or failed one. A zone is held by the boids standing in it
+/// now, and by formations the LOD manager unloaded whose origin is in it
+/// (their boids are nowhere in particular). Formations of an
+/// [`ObjectiveKind::Escort`] are found by [`FormationRef`]; a formation
+/// counts as gone once no boid is left below it, routed or fallen. Does
+/// nothing more once the battle is decided.
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+pub fn scenario_system(
+    time: Res<Time>,
+    factions: Res<Factions>,
+    mut scenario: ResMut<Scenario>,
+    mut state: ResMut<BattleState>,
+    q_top: Query<(Entity, &Faction), (With<Formation>, Without<FormationOf>)>,
+    q_refs: Query<
+        (
+            Entity,
+            &Transform,
+            Option<&QuickCommandGroup>,
+            Option<&ScenarioId>,
+        ),
+        With<Formation>,
+    >,
+    q_hierarchy: Query<(Option<&Members>, Option<&Formations>), With<Formation>>,
+    q_boids: Query<&Faction, (With<Boid>, With<Health>)>,
+    q_standing: Query<(&Transform, &Faction), (With<Boid>, With<Health>, With<TrackedByTree>)>,
+    q_unloaded: Query<(&Transform, &Faction), With<LodUnloaded>>,
+    q_uninit: Query<(), (With<Formation>, With<NeedsSpeedInit>)>,
+    mut events: MessageWriter<ScenarioEvent>,
+) {
+    if *state != BattleState::InProgress {
+        return;
+    }
+    let dt = time.delta_secs();
+    scenario.elapsed += dt;
+    let elapsed = scenario.elapsed;
+
+    for (index, objective) in scenario.objectives.iter_mut().enumerate() {
+        if objective.status != ObjectiveStatus::Pending {
+            continue;
+        }
+        let faction = objective.faction;
+        objective.status = match objective.kind {
+            ObjectiveKind::HoldZone {
+                center,
+                radius,
+                secs,
+            } => {
+                let (mut ours, mut theirs) = (false, false);
+                for (transform, side) in q_standing.iter().chain(&q_unloaded) {
+                    if transform.translation.distance(center) <= radius {
+                        ours |= *side == faction;
+                        theirs |= *side != faction;
+                    }
+                }
+                objective.held = if ours && !theirs {
+                    objective.held + dt
+                } else {
+                    0.0
+                };
+                if objective.held >= secs {
+                    ObjectiveStatus::Complete
+                } else {
+                    ObjectiveStatus::Pending
+                }
+            }
+            ObjectiveKind::Rout { .. } if objective.initial.is_none() && !q_uninit.is_empty() => {
+                ObjectiveStatus::Pending
+            }
+            ObjectiveKind::Rout { fraction } => {
+                let initial = objective.initial.get_or_insert_with(|| {
+                    q_top
+                        .iter()
+                        .filter(|(_, side)| **side != faction)
+                        .map(|(entity, _)| entity)
+                        .collect()
+                });
+                let broken = initial
+                    .iter()
+                    .filter(|&&formation| strength(&q_hierarchy, formation) == 0)
+                    .count();
+                if !initial.is_empty() && broken as f32 >= fraction * initial.len() as f32 {
+                    ObjectiveStatus::Complete
+                } else {
+                    ObjectiveStatus::Pending
+                }
+            }
+            ObjectiveKind::Escort {
+                formation,
+                to,
+                radius,
+            } => {
+                let escorted: Vec<Vec3> = q_refs
+                    .iter()
+                    .filter(|(_, _, group, id)| match formation {
+                        FormationRef::Group(n) => group.is_some_and(|g| g.0 == n),
+                        FormationRef::Id(n) => id.is_some_and(|i| i.0 == n),
+                    })
+                    .filter(|(entity, ..)| strength(&q_hierarchy, *entity) > 0)
+                    .map(|(_, transform, ..)| transform.translation)
+                    .collect();
+                if escorted.is_empty() {
+                    ObjectiveStatus::Failed
+                } else if escorted
+                    .iter()
+                    .all(|pos| pos.with_y(0.0).distance(to.with_y(0.0)) <= radius)
+                {
+                    ObjectiveStatus::Complete
+                } else {
+                    ObjectiveStatus::Pending
+                }
+            }
+            ObjectiveKind::Survive { secs } => {
+                if !q_boids.iter().any(|side| *side == faction) {
+                    ObjectiveStatus::Failed
+                } else if elapsed >= secs {
+                    ObjectiveStatus::Complete
+                } else {
+                    ObjectiveStatus::Pending
+                }
+            }
+        };
+        match objective.status {
+            ObjectiveStatus::Complete => {
+                info!("[scenario] {faction:?} completed {:?}", objective.kind);
+                events.write(ScenarioEvent::ObjectiveComplete {
+                    objective: index,
+                    faction,
+                });
+            }
+            ObjectiveStatus::Failed => {
+                info!("[scenario] {faction:?} failed {:?}", objective.kind);
+                events.write(ScenarioEvent::ObjectiveFailed {
+                    objective: index,
+                    faction,
+                });
+            }
+            ObjectiveStatus::Pending => {}
+        }
+    }
+
+    let mut sides: Vec<Faction> = Vec::new();
+    for objective in &scenario.objectives {
+        if !sides.contains(&objective.faction) {
+            sides.push(objective.faction);
+        }
+    }
+    for faction in sides {
+        let mine = || scenario.objectives.iter().filter(|o| o.faction == faction);
+        let (event, won) = if mine().any(|o| o.status == ObjectiveStatus::Failed) {
+            (ScenarioEvent::Defeat { faction }, false)
+        } else if mine().all(|o| o.status == ObjectiveStatus::Complete) {
+            (ScenarioEvent::Victory { faction }, true)
+        } else {
+            continue;
+        };
+        *state = if won == factions.is_local(faction) {
+            BattleState::Victory
+        } else {
+            BattleState::Defeat
+        };
+        events.write(event);
+        info!("[scenario] {event:?} after {elapsed:.0}s: {:?}", *state);
+        break;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formations::MemberOf;
+    use std::time::Duration;
+
+    /// Every scenario event written, in order.
+    #[derive(Resource, Default)]
+    struct Log(Vec<ScenarioEvent>);
+
+    fn record(mut reader: MessageReader<ScenarioEvent>, mut log: ResMut<Log>) {
+        log.0.extend(reader.read().copied());
+    }
+
+    /// Headless app checking `objectives` every frame, the local player
+    /// being `Faction(0)`.
+    fn test_app(objectives: Vec<Objective>) -> App {
+        let mut app = App::new();
+        app.init_resource::<Time>()
+            .init_resource::<Factions>()
+            .init_resource::<BattleState>()
+            .init_resource::<Log>()
+            .insert_resource(Scenario {
+                objectives,
+                elapsed: 0.0,
+            })
+            .add_message::<ScenarioEvent>()
+            .add_systems(Update, (scenario_system, record).chain());
+        app
+    }
+
+    fn tick(app: &mut App, dt: f32) {
+        app.world_mut()
+            .resource_mut::<Time>()
+            .advance_by(Duration::from_secs_f32(dt));
+        app.update();
+    }
+
+    fn spawn_boid(app: &mut App, pos: Vec3, faction: Faction) -> Entity {
+        app.world_mut()
+            .spawn((
+                Boid::default(),
+                Transform::from_translation(pos),
+                TrackedByTree,
+                Health::default(),
+                faction,
+            ))
+            .id()
+    }
+
+    /// A top-level formation at `pos` with one boid, its speed known.
+    fn spawn_formation(app: &mut App, pos: Vec3, faction: Faction) -> (Entity, Entity) {
+        let formation = app
+            .world_mut()
+            .spawn((
+                Formation::default(),
+                Transform::from_translation(pos),
+                faction,
+            ))
+            .remove::<NeedsSpeedInit>()
+            .id();
+        let boid = spawn_boid(app, pos, faction);
+        app.world_mut().entity_mut(boid).insert(MemberOf(formation));
+        (formation, boid)
+    }
+
+    fn objective(app: &App) -> &Objective {
+        &app.world().resource::<Scenario>().objectives[0]
+    }
+
+    fn state(app: &App) -> BattleState {
+        *app.world().resource::<BattleState>()
+    }
+
+    fn log(app: &App) -> &[ScenarioEvent] {
+        &app.world().resource::<Log>().0
+    }
+
+    #[test]
+    fn holding_a_zone_restarts_when_an_enemy_gets_in() {
+        let hold = ObjectiveKind::HoldZone {
+            center: Vec3::ZERO,
+            radius: 10.0,
+            secs: 3.0,
+        };
+        let mut app = test_app(vec![Objective::new(Faction(0), hold)]);
+        spawn_boid(&mut app, Vec3::ZERO, Faction(0));
+        tick(&mut app, 0.1);
+        tick(&mut app, 2.0);
+        assert!(objective(&app).held >= 2.0);
+
+        let intruder = spawn_boid(&mut app, Vec3::X, Faction(1));
+        tick(&mut app, 0.1);
+        tick(&mut app, 0.1);
+        assert_eq!(objective(&app).held, 0.0, "contested");
+
+        app.world_mut().despawn(intruder);
+        tick(&mut app, 2.0);
+        assert_eq!(objective(&app).status, ObjectiveStatus::Pending);
+        tick(&mut app, 1.0);
+        assert_eq!(objective(&app).status, ObjectiveStatus::Complete);
+        assert_eq!(state(&app), BattleState::Victory, "ours, all done");
+        assert!(matches!(
+            log(&app),
+            [
+                ScenarioEvent::ObjectiveComplete {
+                    objective: 0,
+                    faction: Faction(0)
+                },
+                ScenarioEvent::Victory {
+                    faction: Faction(0)
+                },
+            ]
+        ));
+    }
+
+    #[test]
+    fn a_zone_counts_boids_where_they_stand_and_unloaded_formations() {
+        let hold = ObjectiveKind::HoldZone {
+            center: Vec3::ZERO,
+            radius: 10.0,
+            secs: 3.0,
+        };
+        let mut app = test_app(vec![Objective::new(Faction(0), hold)]);
+        // Unloaded, its boids left where it set out: the origin counts.
+        let (formation, boid) = spawn_formation(&mut app, Vec3::ZERO, Faction(0));
+        app.world_mut().entity_mut(formation).insert(LodUnloaded);
+        app.world_mut()
+            .entity_mut(boid)
+            .remove::<TrackedByTree>()
+            .insert(Transform::from_xyz(100.0, 0.0, 0.0));
+        tick(&mut app, 1.0);
+        assert_eq!(objective(&app).held, 1.0, "held by the unloaded formation");
+
+        // An enemy counts the moment it steps in.
+        let intruder = spawn_boid(&mut app, Vec3::new(20.0, 0.0, 0.0), Faction(1));
+        tick(&mut app, 1.0);
+        assert_eq!(objective(&app).held, 2.0);
+        app.world_mut()
+            .entity_mut(intruder)
+            .insert(Transform::from_xyz(5.0, 0.0, 0.0));
+        tick(&mut app, 0.1);
+        assert_eq!(objective(&app).held, 0.0, "contested");
+    }
+
+    #[test]
+    fn rout_counts_enemy_formations_present_at_the_start() {
+        let rout = ObjectiveKind::Rout { fraction: 0.5 };
+        let mut app = test_app(vec![Objective::new(Faction(0), rout)]);
+        spawn_formation(&mut app, Vec3::ZERO, Faction(0));
+        let enemies: Vec<Entity> = (0..4)
+            .map(|i| spawn_formation(&mut app, Vec3::X * i as f32, Faction(1)).1)
+            .collect();
+        // Not counted while a formation is yet to know its faction.
+        let late = app.world_mut().spawn(Formation::default()).id();
+        tick(&mut app, 0.1);
+        assert!(objective(&app).initial.is_none());
+        app.world_mut().entity_mut(late).remove::<NeedsSpeedInit>();
+        tick(&mut app, 0.1);
+        assert_eq!(objective(&app).initial.as_ref().map(Vec::len), Some(4));
+
+        // A fifth arriving later is not one to rout.
+        spawn_formation(&mut app, Vec3::Z * 20.0, Faction(1));
+        app.world_mut().despawn(enemies[0]);
+        tick(&mut app, 0.1);
+        assert_eq!(objective(&app).status, ObjectiveStatus::Pending);
+        app.world_mut().despawn(enemies[1]);
+        tick(&mut app, 0.1);
+        assert_eq!(objective(&app).status, ObjectiveStatus::Complete);
+    }
+
+    #[test]
+    fn escort_completes_on_arrival() {
+        let escort = ObjectiveKind::Escort {
+            formation: FormationRef::Id(7),
+            to: Vec3::new(50.0, 0.0, 0.0),
+            radius: 5.0,
+        };
+        let mut app = test_app(vec![Objective::new(Faction(0), escort)]);
+        let (formation, _) = spawn_formation(&mut app, Vec3::ZERO, Faction(0));
+        app.world_mut().entity_mut(formation).insert(ScenarioId(7));
+        tick(&mut app, 0.1);
+        assert_eq!(objective(&app).status, ObjectiveStatus::Pending);
+
+        app.world_mut()
+            .get_mut::<Transform>(formation)
+            .unwrap()
+            .translation = Vec3::new(52.0, 3.0, 0.0);
+        tick(&mut app, 0.1);
+        assert_eq!(objective(&app).status, ObjectiveStatus::Complete);
+    }
+
+    #[test]
+    fn escort_fails_once_its_formation_is_gone() {
+        let escort = ObjectiveKind::Escort {
+            formation: FormationRef::Group(2),
+            to: Vec3::new(50.0, 0.0, 0.0),
+            radius: 5.0,
+        };
+        let mut app = test_app(vec![Objective::new(Faction(0), escort)]);
+        let (formation, boid) = spawn_formation(&mut app, Vec3::ZERO, Faction(0));
+        app.world_mut()
+            .entity_mut(formation)
+            .insert(QuickCommandGroup(2));
+        tick(&mut app, 0.1);
+        assert_eq!(objective(&app).status, ObjectiveStatus::Pending);
+
+        // Its last boid fled or fell: the formation is left empty.
+        app.world_mut().entity_mut(boid).remove::<MemberOf>();
+        tick(&mut app, 0.1);
+        assert_eq!(objective(&app).status, ObjectiveStatus::Failed);
+        assert_eq!(state(&app), BattleState::Defeat, "ours, failed");
+        assert!(matches!(
+            log(&app),
+            [
+                ScenarioEvent::ObjectiveFailed { .. },
+                ScenarioEvent::Defeat {
+                    faction: Faction(0)
+                },
+            ]
+        ));
+    }
+
+    #[test]
+    fn an_enemy_surviving_is_a_defeat() {
+        let survive = ObjectiveKind::Survive { secs: 10.0 };
+        let mut app = test_app(vec![Objective::new(Faction(1), survive)]);
+        spawn_boid(&mut app, Vec3::ZERO, Faction(1));
+        tick(&mut app, 5.0);
+        assert_eq!(objective(&app).status, ObjectiveStatus::Pending);
+        tick(&mut app, 5.0);
+        assert_eq!(objective(&app).status, ObjectiveStatus::Complete);
+        assert_eq!(state(&app), BattleState::Defeat);
+
+        // Decided: nothing changes any more.
+        let events = log(&app).len();
+        tick(&mut app, 5.0);
+        assert_eq!(log(&app).len(), events);
+        assert_eq!(app.world().resource::<Scenario>().elapsed, 10.0);
+    }
+
+    #[test]
+    fn an_enemy_wiped_out_before_its_time_is_a_victory() {
+        let survive = ObjectiveKind::Survive { secs: 10.0 };
+        let mut app = test_app(vec![Objective::new(Faction(1), survive)]);
+        spawn_boid(&mut app, Vec3::ZERO, Faction(0));
+        tick(&mut app, 1.0);
+        assert_eq!(objective(&app).status, ObjectiveStatus::Failed);
+        assert_eq!(state(&app), BattleState::Victory);
+    }
+}
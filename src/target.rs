@@ -9,6 +9,9 @@ use bevy::prelude::{Component, Query, Transform};
 pub struct Target {
     pub pos: Vec3,
     pub dir: Vec3,
+    /// Speed cap while approaching `pos` (a formation's marching pace);
+    /// `None` allows the full [`MAX_VELOCITY`].
+    pub speed: Option<f32>,
 }
 
 ///Add force in target direction
@@ -21,7 +24,8 @@ pub fn follow_target(mut query: Query<(&Transform, &Target, &mut Velocity)>) {
         //we always wanna be there in DECELERATION_TIME_SEC
         //a = (l-vt)/t2
         let a = (l - v * DECELERATION_TIME_SEC) / DECELERATION_TIME_SEC_SQUARED;
-        vel.target_v = 0.99 * (l / DECELERATION_TIME_SEC).clamp(0., MAX_VELOCITY);
+        let max_v = target.speed.map_or(MAX_VELOCITY, |s| s.min(MAX_VELOCITY));
+        vel.target_v = 0.99 * (l / DECELERATION_TIME_SEC).clamp(0., max_v);
         vel.a = (dir.normalize_or_zero() * a).clamp_length_max(MAX_ACCELERATION);
     }
 }
@@ -6,7 +6,10 @@ use bevy::prelude::*;
 use bevy_rts_camera::Ground;
 
 #[derive(Component, Default)]
-pub struct Terrain {}
+pub struct Terrain {
+    /// Half the ground plane's extent along X and Z, about its origin.
+    pub half_size: Vec2,
+}
 
 #[derive(Bundle)]
 pub struct TerrainBundle {
@@ -23,14 +26,15 @@ impl BundleDefault for TerrainBundle {
         images: &mut ResMut<Assets<Image>>,
         materials: &mut ResMut<Assets<StandardMaterial>>,
     ) -> Self {
+        let half_size = Vec2::new(2500., 2500.);
         TerrainBundle {
             transform: Transform::from_translation(Vec3::new(0.0, 0.0, 0.0)),
             mesh: Mesh3d(meshes.add(Plane3d {
                 normal: Dir3::Y,
-                half_size: Vec2::new(2500., 2500.),
+                half_size,
             })),
             material: MeshMaterial3d(materials.add(Color::WHITE)),
-            terrain: Default::default(),
+            terrain: Terrain { half_size },
             ground: Ground,
         }
     }
@@ -0,0 +1,906 @@
+use crate::boid::Boid;
+use crate::faction::{Faction, Factions};
+use crate::formations::{
+    Formation, FormationKind, FormationOrder, Formations, MemberOf, Members, Pace,
+};
+use crate::input::{Action, Actions};
+use crate::morale::Morale;
+use crate::player::{
+    Selected, SelectedUnits, frontage_units, get_intersection, move_selection, ordered_pace,
+    selected_formations,
+};
+use crate::target::Target;
+use crate::terrain::{Obstacle, Terrain};
+use crate::vision::FogOfWar;
+use bevy::asset::RenderAssetUsages;
+use bevy::prelude::*;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use bevy::ui::RelativeCursorPosition;
+use bevy_rts_camera::{Ground, RtsCamera};
+
+/// Marker on UI that takes the pointer: a click on it is not meant for the
+/// battlefield (see [`pointer_over_ui`]).
+#[derive(Component, Default)]
+#[require(Interaction)]
+pub struct BlocksPointer;
+
+/// Marker on the command card's root node: the panel of buttons for the
+/// selected formations at the bottom left of the screen. Shown only while
+/// something is selected.
+#[derive(Component)]
+#[require(BlocksPointer)]
+pub struct CommandCard;
+
+/// Marker on the card's status line (member count, speed, current task).
+#[derive(Component)]
+pub struct CardStatus;
+
+/// What a command card button does to every selected formation (including
+/// the formations of selected member boids).
+#[derive(Component, Clone, Copy, Debug)]
+#[require(BlocksPointer)]
+pub enum CardButton {
+    /// Draw up as this kind, re-dressing into the new layout.
+    Kind(FormationKind),
+    /// Widen (or narrow) by this many files, re-dressing. Only kinds drawn
+    /// up in files take it (see [`FormationKind::has_files`]).
+    Files(i32),
+    /// March at this pace, the current and queued moves included.
+    Pace(Pace),
+    /// Drop all orders and hold where it stands.
+    Hold,
+    /// Re-dress in place, before the pending orders.
+    Reform,
+    /// Wheel a quarter turn to the right, before the pending orders.
+    Rotate,
+    /// Drop all orders; stop where the members are.
+    Halt,
+    /// Drop all orders and call back the boids that fled when it routed.
+    Rally,
+}
+
+const CARD_BACKGROUND: Color = Color::srgba(0.05, 0.05, 0.05, 0.75);
+const BUTTON_IDLE: Color = Color::srgb(0.2, 0.2, 0.22);
+const BUTTON_HOVERED: Color = Color::srgb(0.3, 0.3, 0.34);
+const BUTTON_PRESSED: Color = Color::srgb(0.45, 0.4, 0.15);
+const CARD_FONT_SIZE: f32 = 13.0;
+
+/// Spawn the (hidden) command card: a status line, then one row each of
+/// kind, file count, pace and order buttons.
+pub fn spawn_command_card(mut commands: Commands) {
+    let row = || Node {
+        flex_direction: FlexDirection::Row,
+        flex_wrap: FlexWrap::Wrap,
+        column_gap: Val::Px(4.0),
+        row_gap: Val::Px(4.0),
+        ..default()
+    };
+    let kinds: Vec<(CardButton, String)> = FormationKind::ALL
+        .iter()
+        .map(|&kind| (CardButton::Kind(kind), format!("{kind:?}")))
+        .collect();
+    let files = vec![
+        (CardButton::Files(-1), "Files -".to_string()),
+        (CardButton::Files(1), "Files +".to_string()),
+    ];
+    let paces: Vec<(CardButton, String)> = Pace::ALL
+        .iter()
+        .map(|&pace| (CardButton::Pace(pace), format!("{pace:?}")))
+        .collect();
+    let orders = vec![
+        (CardButton::Hold, "Hold".to_string()),
+        (CardButton::Reform, "Reform".to_string()),
+        (CardButton::Rotate, "Rotate".to_string()),
+        (CardButton::Halt, "Halt".to_string()),
+        (CardButton::Rally, "Rally".to_string()),
+    ];
+
+    commands
+        .spawn((
+            CommandCard,
+            Node {
+                position_type: PositionType::Absolute,
+                left: Val::Px(10.0),
+                bottom: Val::Px(10.0),
+                max_width: Val::Px(520.0),
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(6.0),
+                padding: UiRect::all(Val::Px(8.0)),
+                display: Display::None,
+                ..default()
+            },
+            BackgroundColor(CARD_BACKGROUND),
+        ))
+        .with_children(|card| {
+            card.spawn((
+                CardStatus,
+                Text::default(),
+                TextFont {
+                    font_size: CARD_FONT_SIZE,
+                    ..default()
+                },
+            ));
+            for buttons in [kinds, files, paces, orders] {
+                card.spawn(row()).with_children(|row| {
+                    for (button, label) in buttons {
+                        row.spawn((
+                            button,
+                            Button,
+                            Node {
+                                padding: UiRect::axes(Val::Px(6.0), Val::Px(3.0)),
+                                ..default()
+                            },
+                            BackgroundColor(BUTTON_IDLE),
+                        ))
+                        .with_children(|button| {
+                            button.spawn((
+                                Text::new(label),
+                                TextFont {
+                                    font_size: CARD_FONT_SIZE,
+                                    ..default()
+                                },
+                            ));
+                        });
+                    }
+                });
+            }
+        });
+}
+
+/// The pointer is over UI that [takes it](BlocksPointer), so a click there
+/// is not meant for the battlefield.
+pub fn pointer_over_ui(q_ui: &Query<&Interaction, With<BlocksPointer>>) -> bool {
+    q_ui.iter().any(|i| *i != Interaction::None)
+}
+
+/// Apply pressed command card buttons to the selected formations (and the
+/// formations of selected member boids, or of selected boids fleeing from
+/// a rout). Like every other control, orders
+/// go through [`Formation::tasks`]; a layout change (kind, files) sets the
+/// layout and queues a [`FormationOrder::Reform`] in front so the members
+/// re-dress into it.
+pub fn command_card_system(
+    mut q_buttons: Query<
+        (&Interaction, &CardButton, &mut BackgroundColor),
+        (Changed<Interaction>, With<Button>),
+    >,
+    q_selected: SelectedUnits,
+    mut q_formations: Query<(
+        &mut Formation,
+        &Transform,
+        Option<&Members>,
+        Option<&Formations>,
+    )>,
+) {
+    for (interaction, &button, mut background) in &mut q_buttons {
+        background.0 = match interaction {
+            Interaction::Pressed => BUTTON_PRESSED,
+            Interaction::Hovered => BUTTON_HOVERED,
+            Interaction::None => BUTTON_IDLE,
+        };
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        let formations = selected_formations(&q_selected, |f| q_formations.contains(f));
+        for formation_entity in &formations {
+            let Ok((mut formation, transform, members, subs)) =
+                q_formations.get_mut(*formation_entity)
+            else {
+                continue;
+            };
+            match button {
+                CardButton::Kind(kind) => {
+                    formation.kind = kind;
+                    formation.tasks.push_front(FormationOrder::Reform);
+                }
+                CardButton::Files(delta) => {
+                    if !formation.kind.has_files() {
+                        continue;
+                    }
+                    let total = members.map_or(0, |m| m.len()) + subs.map_or(0, |s| s.len());
+                    let files = formation.kind.files(total, &formation.params) as i32;
+                    formation.params.files = Some((files + delta).max(1) as usize);
+                    formation.tasks.push_front(FormationOrder::Reform);
+                }
+                CardButton::Pace(pace) => {
+                    formation.pace = pace;
+                    for task in formation.tasks.iter_mut() {
+                        if let FormationOrder::Move { pace: p, .. } = task {
+                            *p = pace;
+                        }
+                    }
+                }
+                CardButton::Hold => {
+                    let order = FormationOrder::Hold {
+                        pos: transform.translation,
+                        facing_dir: formation.dir,
+                    };
+                    formation.tasks.clear();
+                    formation.tasks.push_back(order);
+                }
+                CardButton::Reform => formation.tasks.push_front(FormationOrder::Reform),
+                CardButton::Rotate => {
+                    let dir = formation.dir.try_normalize().unwrap_or(Vec3::Z);
+                    let to = Quat::from_rotation_y(-std::f32::consts::FRAC_PI_2) * dir;
+                    formation.tasks.push_front(FormationOrder::Rotate { to });
+                }
+                CardButton::Halt => formation.tasks.clear(),
+                CardButton::Rally => {
+                    formation.tasks.clear();
+                    formation.tasks.push_back(FormationOrder::Rally);
+                }
+            }
+        }
+        info!("[card] {button:?} -> {} formations", formations.len());
+    }
+}
+
+/// Show the command card while anything is selected and keep its status
+/// line current: member count, the slowest formation's `max_speed`, and
+/// the current task and [`Morale`] of the selected formation (or how many
+/// there are).
+pub fn command_card_status_system(
+    q_selected: SelectedUnits,
+    q_formations: Query<(&Formation, Option<&Members>, Option<&Formations>)>,
+    q_morale: Query<&Morale, With<Formation>>,
+    q_boids: Query<(), With<Boid>>,
+    mut q_card: Query<&mut Node, With<CommandCard>>,
+    mut q_status: Query<&mut Text, With<CardStatus>>,
+) {
+    let display = if q_selected.is_empty() {
+        Display::None
+    } else {
+        Display::Flex
+    };
+    for mut node in &mut q_card {
+        if node.display != display {
+            node.display = display;
+        }
+    }
+    if display == Display::None {
+        return;
+    }
+
+    let formations = selected_formations(&q_selected, |f| q_formations.contains(f));
+    let free_boids = q_selected
+        .iter()
+        .filter(|&(entity, member_of, routed_from)| {
+            member_of.is_none() && routed_from.is_none() && q_boids.contains(entity)
+        })
+        .count();
+    // Boids in the formation and every sub-formation below it.
+    fn member_count(
+        q_formations: &Query<(&Formation, Option<&Members>, Option<&Formations>)>,
+        formation: Entity,
+    ) -> usize {
+        let Ok((_, members, subs)) = q_formations.get(formation) else {
+            return 0;
+        };
+        let subs = subs.into_iter().flat_map(|s| s.iter());
+        members.map_or(0, |m| m.len()) + subs.map(|s| member_count(q_formations, s)).sum::<usize>()
+    }
+    let members: usize = formations
+        .iter()
+        .map(|&f| member_count(&q_formations, f))
+        .sum();
+    let max_speed = formations
+        .iter()
+        .filter_map(|&f| q_formations.get(f).ok())
+        .map(|(formation, _, _)| formation.max_speed)
+        .reduce(f32::min);
+    let task = match formations.as_slice() {
+        [formation] => {
+            let task = q_formations
+                .get(*formation)
+                .ok()
+                .and_then(|(formation, _, _)| formation.tasks.front().copied())
+                .map_or("idle".to_string(), |task| format!("{task:?}"));
+            match q_morale.get(*formation) {
+                Ok(morale) => format!("{task} | morale {:.0} ({:?})", morale.value, morale.state()),
+                Err(_) => task,
+            }
+        }
+        [] => "-".to_string(),
+        many => format!("{} formations", many.len()),
+    };
+    let speed = max_speed.map_or("-".to_string(), |s| format!("{s:.1}"));
+    let status = format!("{members} members, {free_boids} free boids | max speed {speed} | {task}");
+    for mut text in &mut q_status {
+        if text.0 != status {
+            text.0.clone_from(&status);
+        }
+    }
+}
+
+/// Marker on the minimap: a top-down image of the whole [`Terrain`] at the
+/// bottom right of the screen, redrawn every frame by
+/// [`minimap_draw_system`]. North (up) is -Z.
+#[derive(Component)]
+#[require(BlocksPointer, RelativeCursorPosition)]
+pub struct Minimap;
+
+/// Minimap image size in pixels (and in UI pixels on screen).
+const MINIMAP_SIZE: u32 = 200;
+const MINIMAP_BACKGROUND: [u8; 4] = [25, 40, 25, 255];
+const MINIMAP_OBSTACLE: [u8; 4] = [90, 90, 90, 255];
+const MINIMAP_FORMATION: [u8; 4] = [255, 255, 255, 255];
+const MINIMAP_SELECTED: [u8; 4] = [255, 220, 60, 255];
+const MINIMAP_VIEW: [u8; 4] = [255, 255, 255, 255];
+
+/// Seconds between minimap redraws: each one rewrites the whole image and
+/// uploads it again, and a few a second keep up with marching boids.
+const MINIMAP_REFRESH_INTERVAL: f32 = 0.2;
+
+/// Spawn the minimap with a blank image. The image stays in the main world
+/// too, so it can be redrawn there.
+pub fn spawn_minimap(mut commands: Commands, mut images: ResMut<Assets<Image>>) {
+    let image = Image::new_fill(
+        Extent3d {
+            width: MINIMAP_SIZE,
+            height: MINIMAP_SIZE,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        &MINIMAP_BACKGROUND,
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD,
+    );
+    commands.spawn((
+        Minimap,
+        ImageNode::new(images.add(image)),
+        Node {
+            position_type: PositionType::Absolute,
+            right: Val::Px(10.0),
+            bottom: Val::Px(10.0),
+            width: Val::Px(MINIMAP_SIZE as f32),
+            height: Val::Px(MINIMAP_SIZE as f32),
+            ..default()
+        },
+        Outline::new(Val::Px(1.0), Val::ZERO, Color::WHITE),
+    ));
+}
+
+/// Ground-plane rectangle the minimap shows: the terrain's extent.
+#[derive(Clone, Copy, Debug)]
+struct MinimapFrame {
+    /// North-west (min X, min Z) corner.
+    min: Vec2,
+    size: Vec2,
+}
+
+impl MinimapFrame {
+    fn of(terrain: &Terrain, transform: &GlobalTransform) -> Option<Self> {
+        let center = transform.translation().xz();
+        (terrain.half_size.min_element() > 0.0).then(|| Self {
+            min: center - terrain.half_size,
+            size: terrain.half_size * 2.0,
+        })
+    }
+
+    /// Pixel of a world position; `None` off the terrain.
+    fn pixel(&self, pos: Vec3) -> Option<(u32, u32)> {
+        let uv = (pos.xz() - self.min) / self.size;
+        ((0.0..1.0).contains(&uv.x) && (0.0..1.0).contains(&uv.y)).then(|| {
+            let p = uv * MINIMAP_SIZE as f32;
+            (p.x as u32, p.y as u32)
+        })
+    }
+
+    /// Ground position under a point of the minimap, given relative to its
+    /// center (-0.5..0.5 on both axes, +Y down; see
+    /// [`RelativeCursorPosition::normalized`]).
+    fn ground(&self, normalized: Vec2) -> Vec3 {
+        let xz =
+            self.min + (normalized.clamp(Vec2::splat(-0.5), Vec2::splat(0.5)) + 0.5) * self.size;
+        Vec3::new(xz.x, 0.0, xz.y)
+    }
+}
+
+/// Square dot of `2 * radius + 1` pixels.
+fn plot(data: &mut [u8], (x, y): (u32, u32), radius: u32, color: [u8; 4]) {
+    let x_range = x.saturating_sub(radius)..=(x + radius).min(MINIMAP_SIZE - 1);
+    for py in y.saturating_sub(radius)..=(y + radius).min(MINIMAP_SIZE - 1) {
+        for px in x_range.clone() {
+            let i = ((py * MINIMAP_SIZE + px) * 4) as usize;
+            data[i..i + 4].copy_from_slice(&color);
+        }
+    }
+}
+
+/// Redraw the minimap every [`MINIMAP_REFRESH_INTERVAL`]: terrain,
+/// obstacles, boids in their [`Faction`]'s colour (selected ones
+/// highlighted), formation origins, then the ground footprint of the
+/// camera's view. Enemies out of the local faction's sight ([`FogOfWar`])
+/// are left off.
+#[allow(clippy::too_many_arguments)]
+pub fn minimap_draw_system(
+    time: Res<Time>,
+    mut refresh_in: Local<f32>,
+    q_minimap: Query<&ImageNode, With<Minimap>>,
+    mut images: ResMut<Assets<Image>>,
+    q_terrain: Query<(&Terrain, &GlobalTransform)>,
+    q_obstacles: Query<&GlobalTransform, With<Obstacle>>,
+    factions: Res<Factions>,
+    fog: Res<FogOfWar>,
+    q_boids: Query<(&GlobalTransform, &Faction, Has<Selected>), With<Boid>>,
+    q_formations: Query<(&GlobalTransform, &Faction, Has<Selected>), With<Formation>>,
+    q_camera: Query<(&Camera, &GlobalTransform), With<RtsCamera>>,
+    q_ground: Query<&GlobalTransform, With<Ground>>,
+    windows: Query<&Window>,
+) {
+    *refresh_in -= time.delta_secs();
+    if *refresh_in > 0.0 {
+        return;
+    }
+    *refresh_in = MINIMAP_REFRESH_INTERVAL;
+    let Ok(node) = q_minimap.single() else {
+        return;
+    };
+    let Some(frame) = q_terrain
+        .single()
+        .ok()
+        .and_then(|(terrain, transform)| MinimapFrame::of(terrain, transform))
+    else {
+        return;
+    };
+    let Some(data) = images.get_mut(&node.image).and_then(|i| i.data.as_mut()) else {
+        return;
+    };
+    for pixel in data.chunks_exact_mut(4) {
+        pixel.copy_from_slice(&MINIMAP_BACKGROUND);
+    }
+
+    for transform in &q_obstacles {
+        if let Some(p) = frame.pixel(transform.translation()) {
+            plot(data, p, 1, MINIMAP_OBSTACLE);
+        }
+    }
+    let known = |faction: &Faction, transform: &GlobalTransform| {
+        fog.reveals(factions.local, *faction, transform.translation())
+    };
+    for (transform, faction, selected) in &q_boids {
+        if !known(faction, transform) {
+            continue;
+        }
+        let color = if selected {
+            MINIMAP_SELECTED
+        } else {
+            factions.color(*faction).to_srgba().to_u8_array()
+        };
+        if let Some(p) = frame.pixel(transform.translation()) {
+            plot(data, p, 0, color);
+        }
+    }
+    for (transform, faction, selected) in &q_formations {
+        if !known(faction, transform) {
+            continue;
+        }
+        let color = if selected {
+            MINIMAP_SELECTED
+        } else {
+            MINIMAP_FORMATION
+        };
+        if let Some(p) = frame.pixel(transform.translation()) {
+            plot(data, p, 1, color);
+        }
+    }
+
+    // The view's footprint: the screen corners cast onto the ground. A
+    // corner above the horizon has no footprint, so nothing is drawn.
+    let (Ok((camera, camera_transform)), Ok(ground), Ok(window)) =
+        (q_camera.single(), q_ground.single(), windows.single())
+    else {
+        return;
+    };
+    let size = window.size();
+    let corners = [
+        Vec2::ZERO,
+        Vec2::new(size.x, 0.0),
+        size,
+        Vec2::new(0.0, size.y),
+    ]
+    .map(|corner| get_intersection(&corner, camera, camera_transform, ground));
+    let Some(corners) = corners.into_iter().collect::<Option<Vec<Vec3>>>() else {
+        return;
+    };
+    for (i, &from) in corners.iter().enumerate() {
+        let to = corners[(i + 1) % corners.len()];
+        let step = frame.size.max_element() / MINIMAP_SIZE as f32;
+        let samples = (from.distance(to) / step).ceil().max(1.0) as usize;
+        for s in 0..=samples {
+            if let Some(p) = frame.pixel(from.lerp(to, s as f32 / samples as f32)) {
+                plot(data, p, 0, MINIMAP_VIEW);
+            }
+        }
+    }
+}
+
+/// Minimap clicks. Left ([`Action::Select`]) pans the camera to the point,
+/// following the pointer while held; right ([`Action::DesignateFrontage`])
+/// moves the selection there as a plain right-click on the ground would
+/// (see [`move_selection`]), at the pace the modifiers order.
+#[allow(clippy::too_many_arguments)]
+pub fn minimap_click_system(
+    actions: Actions,
+    q_minimap: Query<(&Interaction, &RelativeCursorPosition), With<Minimap>>,
+    q_terrain: Query<(&Terrain, &GlobalTransform)>,
+    mut q_camera: Query<&mut RtsCamera>,
+    q_selected_boids: Query<Entity, (With<Selected>, With<Boid>, Without<Formation>)>,
+    q_selected_formations: Query<Entity, (With<Selected>, With<Formation>)>,
+    q_member_of: Query<&MemberOf>,
+    mut q_formation_mut: Query<(&mut Formation, Option<&Members>, Option<&Formations>)>,
+    q_transforms: Query<&Transform>,
+    mut q_targets: Query<&mut Target>,
+) {
+    let Ok((interaction, cursor)) = q_minimap.single() else {
+        return;
+    };
+    let Some(normalized) = cursor.normalized else {
+        return;
+    };
+    let Some(frame) = q_terrain
+        .single()
+        .ok()
+        .and_then(|(terrain, transform)| MinimapFrame::of(terrain, transform))
+    else {
+        return;
+    };
+    let point = frame.ground(normalized);
+
+    if *interaction == Interaction::Pressed && actions.pressed(Action::Select) {
+        for mut camera in &mut q_camera {
+            camera.target_focus.translation = point;
+        }
+    }
+    if *interaction != Interaction::None && actions.just_pressed(Action::DesignateFrontage) {
+        let units = frontage_units(&q_selected_boids, &q_selected_formations, &q_member_of);
+        if units.is_empty() {
+            return;
+        }
+        move_selection(
+            point,
+            ordered_pace(&actions),
+            &units,
+            &mut q_formation_mut,
+            &q_transforms,
+            &mut q_targets,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::morale::RoutedFrom;
+    use crate::vision::VIEW_RANGE;
+
+    /// The minimap frame of a 100 x 50 terrain centred on (10, 0, -20).
+    fn frame() -> MinimapFrame {
+        let terrain = Terrain {
+            half_size: Vec2::new(50.0, 25.0),
+        };
+        let transform = GlobalTransform::from_translation(Vec3::new(10.0, 3.0, -20.0));
+        MinimapFrame::of(&terrain, &transform).unwrap()
+    }
+
+    #[test]
+    fn minimap_pixels_span_the_terrain() {
+        let frame = frame();
+        let last = MINIMAP_SIZE - 1;
+        assert_eq!(frame.pixel(Vec3::new(-40.0, 0.0, -45.0)), Some((0, 0)));
+        assert_eq!(
+            frame.pixel(Vec3::new(10.0, 7.0, -20.0)),
+            Some((MINIMAP_SIZE / 2, MINIMAP_SIZE / 2)),
+            "height does not matter"
+        );
+        assert_eq!(frame.pixel(Vec3::new(59.9, 0.0, 4.9)), Some((last, last)));
+        // +X is right, +Z down the image.
+        assert_eq!(
+            frame.pixel(Vec3::new(35.0, 0.0, -32.5)),
+            Some((3 * MINIMAP_SIZE / 4, MINIMAP_SIZE / 4))
+        );
+        for off in [
+            Vec3::new(-40.1, 0.0, 0.0),
+            Vec3::new(60.0, 0.0, -20.0),
+            Vec3::new(10.0, 0.0, -45.1),
+            Vec3::new(10.0, 0.0, 5.0),
+        ] {
+            assert_eq!(frame.pixel(off), None, "{off} is off the terrain");
+        }
+    }
+
+    #[test]
+    fn minimap_point_maps_back_to_the_ground() {
+        let frame = frame();
+        assert_eq!(frame.ground(Vec2::ZERO), Vec3::new(10.0, 0.0, -20.0));
+        assert_eq!(
+            frame.ground(Vec2::new(-0.5, -0.5)),
+            Vec3::new(-40.0, 0.0, -45.0)
+        );
+        assert_eq!(
+            frame.ground(Vec2::new(0.25, 0.5)),
+            Vec3::new(35.0, 0.0, 5.0)
+        );
+        // Past the edge: the nearest point on the terrain.
+        assert_eq!(
+            frame.ground(Vec2::new(2.0, -1.0)),
+            Vec3::new(60.0, 0.0, -45.0)
+        );
+        // A pixel's ground lies within it.
+        let pos = frame.ground(Vec2::new(0.1, -0.3));
+        let (x, y) = frame.pixel(pos).unwrap();
+        let (u, v) = (0.6 * MINIMAP_SIZE as f32, 0.2 * MINIMAP_SIZE as f32);
+        assert!(x.abs_diff(u as u32) <= 1 && y.abs_diff(v as u32) <= 1);
+    }
+
+    #[test]
+    fn a_flat_terrain_has_no_minimap() {
+        let terrain = Terrain {
+            half_size: Vec2::new(50.0, 0.0),
+        };
+        assert!(MinimapFrame::of(&terrain, &GlobalTransform::IDENTITY).is_none());
+    }
+
+    #[test]
+    fn minimap_leaves_off_enemies_out_of_sight() {
+        let mut app = App::new();
+        let mut fog = FogOfWar::new(100.0);
+        fog.update(2, [], [(Faction(0), Vec3::ZERO, VIEW_RANGE)]);
+        app.init_resource::<Time>()
+            .init_resource::<Factions>()
+            .init_resource::<Assets<Image>>()
+            .insert_resource(fog)
+            .add_systems(Startup, spawn_minimap)
+            .add_systems(Update, minimap_draw_system);
+        app.world_mut().spawn((
+            Terrain {
+                half_size: Vec2::splat(100.0),
+            },
+            GlobalTransform::IDENTITY,
+        ));
+        let seen = Vec3::new(10.0, 0.0, 0.0);
+        let unseen = Vec3::new(80.0, 0.0, 0.0);
+        let ours = Vec3::new(-80.0, 0.0, 0.0);
+        let formation = Vec3::new(80.0, 0.0, 40.0);
+        for (pos, faction) in [(seen, 1), (unseen, 1), (ours, 0)] {
+            app.world_mut().spawn((
+                Boid::default(),
+                GlobalTransform::from_translation(pos),
+                Faction(faction),
+            ));
+        }
+        app.world_mut().spawn((
+            Formation::default(),
+            GlobalTransform::from_translation(formation),
+            Faction(1),
+        ));
+        app.update();
+
+        let world = app.world_mut();
+        let handle = world
+            .query_filtered::<&ImageNode, With<Minimap>>()
+            .single(world)
+            .unwrap()
+            .image
+            .clone();
+        let data = world.resource::<Assets<Image>>().get(&handle).unwrap();
+        let data = data.data.as_ref().unwrap();
+        let frame = MinimapFrame::of(
+            &Terrain {
+                half_size: Vec2::splat(100.0),
+            },
+            &GlobalTransform::IDENTITY,
+        )
+        .unwrap();
+        let color_at = |pos: Vec3| {
+            let (x, y) = frame.pixel(pos).unwrap();
+            let i = ((y * MINIMAP_SIZE + x) * 4) as usize;
+            <[u8; 4]>::try_from(&data[i..i + 4]).unwrap()
+        };
+        let factions = Factions::default();
+        let color = |f| factions.color(Faction(f)).to_srgba().to_u8_array();
+        assert_eq!(color_at(seen), color(1), "an enemy in sight");
+        assert_eq!(
+            color_at(unseen),
+            MINIMAP_BACKGROUND,
+            "an enemy out of sight"
+        );
+        assert_eq!(color_at(formation), MINIMAP_BACKGROUND, "nor its formation");
+        assert_eq!(color_at(ours), color(0), "our own, seen or not");
+    }
+
+    /// App running the command card on a bare card and status line.
+    fn card_app() -> App {
+        let mut app = App::new();
+        app.add_systems(Update, (command_card_system, command_card_status_system));
+        let world = app.world_mut();
+        world.spawn((
+            CommandCard,
+            Node {
+                display: Display::None,
+                ..default()
+            },
+        ));
+        world.spawn((CardStatus, Text::default()));
+        app
+    }
+
+    /// Press `button` on the card for a frame.
+    fn press(app: &mut App, button: CardButton) {
+        app.world_mut().spawn((
+            button,
+            Button,
+            Interaction::Pressed,
+            BackgroundColor(BUTTON_IDLE),
+        ));
+        app.update();
+    }
+
+    fn spawn_formation(app: &mut App, members: usize) -> Entity {
+        let formation = app
+            .world_mut()
+            .spawn((Formation::default(), Transform::default()))
+            .id();
+        for _ in 0..members {
+            app.world_mut()
+                .spawn((Boid::default(), MemberOf(formation)));
+        }
+        formation
+    }
+
+    fn first_member(app: &mut App, formation: Entity) -> Entity {
+        let world = app.world_mut();
+        let mut query = world.query::<(Entity, &MemberOf)>();
+        query
+            .iter(world)
+            .find_map(|(boid, member_of)| (member_of.0 == formation).then_some(boid))
+            .unwrap()
+    }
+
+    fn tasks(app: &App, formation: Entity) -> Vec<FormationOrder> {
+        let formation = app.world().get::<Formation>(formation).unwrap();
+        formation.tasks.iter().copied().collect()
+    }
+
+    fn status(app: &mut App) -> (Display, String) {
+        let world = app.world_mut();
+        let display = world
+            .query_filtered::<&Node, With<CommandCard>>()
+            .single(world)
+            .unwrap()
+            .display;
+        let text = world
+            .query_filtered::<&Text, With<CardStatus>>()
+            .single(world)
+            .unwrap();
+        (display, text.0.clone())
+    }
+
+    #[test]
+    fn card_orders_each_selected_formation_once() {
+        let mut app = card_app();
+        let selected = spawn_formation(&mut app, 2);
+        let by_member = spawn_formation(&mut app, 2);
+        let routed = spawn_formation(&mut app, 0);
+        let untouched = spawn_formation(&mut app, 2);
+        app.world_mut().entity_mut(selected).insert(Selected);
+        // A member of a selected formation adds nothing more.
+        let member = first_member(&mut app, selected);
+        app.world_mut().entity_mut(member).insert(Selected);
+        let member = first_member(&mut app, by_member);
+        app.world_mut().entity_mut(member).insert(Selected);
+        app.world_mut()
+            .spawn((Boid::default(), RoutedFrom(routed), Selected));
+
+        press(&mut app, CardButton::Kind(FormationKind::Column));
+
+        for formation in [selected, by_member, routed] {
+            let world = app.world();
+            assert_eq!(
+                world.get::<Formation>(formation).unwrap().kind,
+                FormationKind::Column
+            );
+            assert!(matches!(
+                tasks(&app, formation)[..],
+                [FormationOrder::Reform]
+            ));
+        }
+        assert_eq!(
+            app.world().get::<Formation>(untouched).unwrap().kind,
+            FormationKind::default()
+        );
+        assert!(tasks(&app, untouched).is_empty());
+    }
+
+    #[test]
+    fn card_files_change_only_kinds_drawn_up_in_files() {
+        let mut app = card_app();
+        let line = spawn_formation(&mut app, 6);
+        let wedge = spawn_formation(&mut app, 6);
+        let world = app.world_mut();
+        let mut details = world.get_mut::<Formation>(line).unwrap();
+        details.kind = FormationKind::Line;
+        details.params.files = Some(3);
+        world.get_mut::<Formation>(wedge).unwrap().kind = FormationKind::Wedge;
+        world.entity_mut(line).insert(Selected);
+        world.entity_mut(wedge).insert(Selected);
+
+        press(&mut app, CardButton::Files(1));
+        assert_eq!(
+            app.world().get::<Formation>(line).unwrap().params.files,
+            Some(4)
+        );
+        assert!(matches!(tasks(&app, line)[..], [FormationOrder::Reform]));
+        assert!(tasks(&app, wedge).is_empty());
+
+        // Never below one file.
+        for _ in 0..5 {
+            press(&mut app, CardButton::Files(-1));
+        }
+        assert_eq!(
+            app.world().get::<Formation>(line).unwrap().params.files,
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn card_pace_rewrites_queued_moves_and_hold_replaces_them() {
+        let mut app = card_app();
+        let formation = spawn_formation(&mut app, 2);
+        let world = app.world_mut();
+        world
+            .entity_mut(formation)
+            .insert((Selected, Transform::from_xyz(5.0, 0.0, -3.0)));
+        let mut details = world.get_mut::<Formation>(formation).unwrap();
+        for x in [10.0, 20.0] {
+            details.tasks.push_back(FormationOrder::Move {
+                pos: Vec3::new(x, 0.0, 0.0),
+                facing_dir: Vec3::Z,
+                pace: Pace::Charge,
+            });
+        }
+
+        press(&mut app, CardButton::Pace(Pace::Walk));
+        assert_eq!(
+            app.world().get::<Formation>(formation).unwrap().pace,
+            Pace::Walk
+        );
+        let tasks_now = tasks(&app, formation);
+        assert_eq!(tasks_now.len(), 2);
+        for task in tasks_now {
+            assert!(matches!(
+                task,
+                FormationOrder::Move {
+                    pace: Pace::Walk,
+                    ..
+                }
+            ));
+        }
+
+        press(&mut app, CardButton::Hold);
+        let [FormationOrder::Hold { pos, .. }] = tasks(&app, formation)[..] else {
+            panic!("hold replaces the queue");
+        };
+        assert_eq!(pos, Vec3::new(5.0, 0.0, -3.0));
+    }
+
+    #[test]
+    fn card_shows_only_with_a_selection_and_counts_it() {
+        let mut app = card_app();
+        let formation = spawn_formation(&mut app, 3);
+        app.update();
+        assert_eq!(status(&mut app).0, Display::None);
+
+        let member = first_member(&mut app, formation);
+        app.world_mut().entity_mut(member).insert(Selected);
+        app.world_mut().spawn((Boid::default(), Selected));
+        app.update();
+        let (display, text) = status(&mut app);
+        assert_eq!(display, Display::Flex);
+        assert!(
+            text.starts_with("3 members, 1 free boids"),
+            "status was {text:?}"
+        );
+        assert!(text.contains("idle"), "status was {text:?}");
+    }
+}
@@ -0,0 +1,573 @@
+use crate::boid::{Boid, UnitStats};
+use crate::combat::Health;
+use crate::faction::{Faction, Factions};
+use crate::formations::{Footprint, Formation, LodUnloaded, MemberOf, Members};
+use crate::kinematics::TrackedByTree;
+use crate::terrain::{Obstacle, Terrain};
+use bevy::prelude::*;
+use std::collections::HashMap;
+use std::f32::consts::FRAC_PI_2;
+
+/// How far a boid sees by default ([`UnitStats::view_range`]).
+pub const VIEW_RANGE: f32 = 40.0;
+
+/// Side of a [`FogOfWar`] cell.
+pub const CELL_SIZE: f32 = 4.0;
+
+/// Seconds between refreshes of the [`FogOfWar`].
+pub const REFRESH_INTERVAL: f32 = 0.25;
+
+/// What every faction can see, as a grid of [`CELL_SIZE`] cells over the
+/// square of `half_size` about the origin (nothing beyond it is ever seen).
+/// Refreshed by [`vision_system`] from where the boids stand and how far
+/// they see; an [`Obstacle`] blocks the line of sight through its cells,
+/// though the obstacle itself is seen.
+///
+/// Rendering, selection and targeting of enemies go by the local faction's
+/// grid, an [`AiCommander`](crate::ai::AiCommander) by its own. With
+/// `enabled` off every faction sees everything.
+#[derive(Resource, Debug)]
+pub struct FogOfWar {
+    pub enabled: bool,
+    pub half_size: f32,
+    /// Cells per side.
+    side: usize,
+    /// Cells an obstacle stands in.
+    blocked: Vec<bool>,
+    /// Cells in sight, per faction index.
+    visible: Vec<Vec<bool>>,
+    /// Seconds until the next refresh.
+    refresh_in: f32,
+}
+
+impl Default for FogOfWar {
+    fn default() -> Self {
+        Self::new(500.0)
+    }
+}
+
+impl FogOfWar {
+    pub fn new(half_size: f32) -> Self {
+        let side = (2.0 * half_size / CELL_SIZE).ceil() as usize;
+        Self {
+            enabled: true,
+            half_size,
+            side,
+            blocked: vec![false; side * side],
+            visible: Vec::new(),
+            refresh_in: 0.0,
+        }
+    }
+
+    /// Grid coordinates of the cell at `pos`, if on the grid.
+    fn coords(&self, pos: Vec3) -> Option<(usize, usize)> {
+        let x = ((pos.x + self.half_size) / CELL_SIZE).floor();
+        let z = ((pos.z + self.half_size) / CELL_SIZE).floor();
+        let range = 0.0..self.side as f32;
+        (range.contains(&x) && range.contains(&z)).then_some((x as usize, z as usize))
+    }
+
+    /// `faction` sees the ground at `pos`.
+    pub fn sees(&self, faction: Faction, pos: Vec3) -> bool {
+        if !self.enabled {
+            return true;
+        }
+        let Some((x, z)) = self.coords(pos) else {
+            return false;
+        };
+        self.visible
+            .get(faction.0 as usize)
+            .is_some_and(|grid| grid[z * self.side + x])
+    }
+
+    /// `viewer` knows of a unit of `faction` at `pos`: it is one of its own,
+    /// or in sight.
+    pub fn reveals(&self, viewer: Faction, faction: Faction, pos: Vec3) -> bool {
+        viewer == faction || self.sees(viewer, pos)
+    }
+
+    /// Rebuild the grids of `factions` factions: `obstacles` (centre and
+    /// half extents) block the cells they cover on the grid, even those
+    /// reaching off it, and every observer
+    /// (faction, position, view range) sees the cells in range that no
+    /// blocked cell hides. Observers sharing a cell look once, with the
+    /// longest range among them.
+    pub fn update(
+        &mut self,
+        factions: usize,
+        obstacles: impl IntoIterator<Item = (Vec3, Vec3)>,
+        observers: impl IntoIterator<Item = (Faction, Vec3, f32)>,
+    ) {
+        self.blocked.fill(false);
+        let cell = |v: f32| ((v + self.half_size) / CELL_SIZE).floor();
+        let last = self.side as f32 - 1.0;
+        for (center, half) in obstacles {
+            let (low, high) = (center - half.abs(), center + half.abs());
+            let (x0, z0, x1, z1) = (cell(low.x), cell(low.z), cell(high.x), cell(high.z));
+            if x1 < 0.0 || z1 < 0.0 || x0 > last || z0 > last {
+                continue;
+            }
+            let clamp = |c: f32| c.clamp(0.0, last) as usize;
+            for z in clamp(z0)..=clamp(z1) {
+                for x in clamp(x0)..=clamp(x1) {
+                    self.blocked[z * self.side + x] = true;
+                }
+            }
+        }
+
+        self.visible.resize_with(factions, Vec::new);
+        for grid in &mut self.visible {
+            grid.clear();
+            grid.resize(self.side * self.side, false);
+        }
+        let mut eyes: HashMap<(u8, usize, usize), f32> = HashMap::new();
+        for (faction, pos, range) in observers {
+            if let Some((x, z)) = self.coords(pos) {
+                let eye = eyes.entry((faction.0, x, z)).or_default();
+                *eye = eye.max(range);
+            }
+        }
+        for ((faction, x, z), range) in eyes {
+            let Some(mut grid) = self.visible.get_mut(faction as usize).map(std::mem::take) else {
+                continue;
+            };
+            let reach = (range / CELL_SIZE).ceil() as isize;
+            let (x, z) = (x as isize, z as isize);
+            for dz in -reach..=reach {
+                for dx in -reach..=reach {
+                    let (tx, tz) = (x + dx, z + dz);
+                    if (dx * dx + dz * dz) as f32 * CELL_SIZE * CELL_SIZE > range * range
+                        || !(0..self.side as isize).contains(&tx)
+                        || !(0..self.side as isize).contains(&tz)
+                    {
+                        continue;
+                    }
+                    let cell = tz as usize * self.side + tx as usize;
+                    if !grid[cell] && self.clear_line((x, z), (tx, tz)) {
+                        grid[cell] = true;
+                    }
+                }
+            }
+            self.visible[faction as usize] = grid;
+        }
+    }
+
+    /// No blocked cell lies strictly between cells `from` and `to`
+    /// (Bresenham's line).
+    fn clear_line(&self, from: (isize, isize), to: (isize, isize)) -> bool {
+        let (dx, dz) = ((to.0 - from.0).abs(), -(to.1 - from.1).abs());
+        let (sx, sz) = ((to.0 - from.0).signum(), (to.1 - from.1).signum());
+        let (mut x, mut z) = from;
+        let mut error = dx + dz;
+        loop {
+            let doubled = 2 * error;
+            if doubled >= dz {
+                error += dz;
+                x += sx;
+            }
+            if doubled <= dx {
+                error += dx;
+                z += sz;
+            }
+            if (x, z) == to {
+                return true;
+            }
+            if self.blocked[z as usize * self.side + x as usize] {
+                return false;
+            }
+        }
+    }
+}
+
+/// Sizes the [`FogOfWar`] grid to cover the whole [`Terrain`], once it is
+/// spawned: the grid is a square about the origin, and nothing beyond it is
+/// ever seen. Keeps whether the fog is enabled.
+pub fn fit_fog_to_terrain(q_terrain: Query<(&Terrain, &Transform)>, mut fog: ResMut<FogOfWar>) {
+    let Some(half_size) = q_terrain
+        .iter()
+        .map(|(terrain, transform)| {
+            (transform.translation.xz().abs() + terrain.half_size).max_element()
+        })
+        .reduce(f32::max)
+    else {
+        return;
+    };
+    let enabled = fog.enabled;
+    *fog = FogOfWar::new(half_size);
+    fog.enabled = enabled;
+    info!("[vision] fog of war over {half_size} about the origin");
+}
+
+/// Refreshes the [`FogOfWar`] every [`REFRESH_INTERVAL`] from the living
+/// boids of every faction. Boids unloaded by the LOD manager no longer
+/// stand anywhere in particular: their formation looks out for them from
+/// its origin, as far as the farthest-sighted of them.
+#[allow(clippy::type_complexity)]
+pub fn vision_system(
+    time: Res<Time>,
+    factions: Res<Factions>,
+    mut fog: ResMut<FogOfWar>,
+    q_obstacles: Query<&Transform, With<Obstacle>>,
+    q_observers: Query<
+        (&Transform, &Faction, Option<&UnitStats>),
+        (With<Boid>, With<Health>, With<TrackedByTree>),
+    >,
+    q_unloaded: Query<(&Transform, &Faction, &Members), With<LodUnloaded>>,
+    q_stats: Query<&UnitStats>,
+) {
+    fog.refresh_in -= time.delta_secs();
+    if fog.refresh_in > 0.0 {
+        return;
+    }
+    fog.refresh_in = REFRESH_INTERVAL;
+    fog.update(
+        factions.factions.len(),
+        q_obstacles
+            .iter()
+            .map(|transform| (transform.translation, transform.scale / 2.0)),
+        q_observers
+            .iter()
+            .map(|(transform, faction, stats)| {
+                let range = stats.map_or(VIEW_RANGE, |s| s.view_range);
+                (*faction, transform.translation, range)
+            })
+            .chain(q_unloaded.iter().map(|(transform, faction, members)| {
+                let range = members
+                    .iter()
+                    .map(|m| q_stats.get(m).map_or(VIEW_RANGE, |s| s.view_range))
+                    .reduce(f32::max)
+                    .unwrap_or(VIEW_RANGE);
+                (*faction, transform.translation, range)
+            })),
+    );
+}
+
+/// Hides the enemy boids the local faction cannot see, and shows them
+/// again once in sight. Boids unloaded by the LOD manager are its to show.
+#[allow(clippy::type_complexity)]
+pub fn fog_visibility_system(
+    fog: Res<FogOfWar>,
+    factions: Res<Factions>,
+    mut q_boids: Query<(&Transform, &Faction, &mut Visibility), (With<Boid>, With<TrackedByTree>)>,
+) {
+    for (transform, faction, mut visibility) in &mut q_boids {
+        if factions.is_local(*faction) {
+            continue;
+        }
+        visibility.set_if_neq(if fog.sees(factions.local, transform.translation) {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        });
+    }
+}
+
+/// Where the local faction last saw an enemy formation, or a boid fighting
+/// on its own.
+#[derive(Clone, Copy, Debug)]
+pub struct Ghost {
+    pub pos: Vec3,
+    pub rotation: Quat,
+    pub faction: Faction,
+    /// The formation's ground; `None` for a lone boid.
+    pub footprint: Option<Footprint>,
+    /// Seen this frame, so not a ghost at all.
+    pub in_sight: bool,
+}
+
+/// Last-known positions of the enemy, by formation (or lone boid).
+#[derive(Resource, Default, Debug)]
+pub struct Ghosts(pub HashMap<Entity, Ghost>);
+
+/// Keeps [`Ghosts`]: every enemy formation with a boid in the local
+/// faction's sight is recorded where it stands; once out of sight it stays
+/// where it was last seen, until the local faction looks there again and
+/// finds it gone.
+#[allow(clippy::type_complexity)]
+pub fn ghost_system(
+    fog: Res<FogOfWar>,
+    factions: Res<Factions>,
+    mut ghosts: ResMut<Ghosts>,
+    q_boids: Query<
+        (Entity, &Transform, &Faction, Option<&MemberOf>),
+        (With<Boid>, With<Health>, With<TrackedByTree>),
+    >,
+    q_formations: Query<(&Transform, &Formation)>,
+) {
+    for ghost in ghosts.0.values_mut() {
+        ghost.in_sight = false;
+    }
+    for (entity, transform, faction, member_of) in &q_boids {
+        if factions.is_local(*faction) || !fog.sees(factions.local, transform.translation) {
+            continue;
+        }
+        let (unit, pos, rotation, footprint) =
+            match member_of.and_then(|m| q_formations.get(m.0).ok().map(|f| (m.0, f))) {
+                Some((formation, (at, details))) => (
+                    formation,
+                    at.translation,
+                    at.rotation,
+                    Some(details.footprint),
+                ),
+                None => (entity, transform.translation, transform.rotation, None),
+            };
+        ghosts.0.insert(
+            unit,
+            Ghost {
+                pos,
+                rotation,
+                faction: *faction,
+                footprint,
+                in_sight: true,
+            },
+        );
+    }
+    ghosts
+        .0
+        .retain(|_, ghost| ghost.in_sight || !fog.sees(factions.local, ghost.pos));
+}
+
+/// Draws the out-of-sight [`Ghosts`]: a formation's footprint, a lone
+/// boid's ring, faded in its faction's colour.
+pub fn draw_ghosts(ghosts: Res<Ghosts>, factions: Res<Factions>, mut gizmos: Gizmos) {
+    const LIFT: Vec3 = Vec3::new(0.0, 0.2, 0.0);
+    for ghost in ghosts.0.values().filter(|g| !g.in_sight) {
+        let color = factions.color(ghost.faction).with_alpha(0.4);
+        let flat = ghost.rotation * Quat::from_rotation_x(-FRAC_PI_2);
+        match ghost.footprint {
+            Some(footprint) => {
+                let center = footprint.world_center(ghost.pos, ghost.rotation) + LIFT;
+                gizmos.rect(
+                    Isometry3d::new(center, flat),
+                    Vec2::new(footprint.width, footprint.depth),
+                    color,
+                );
+            }
+            None => {
+                let at = ghost.pos.with_y(0.0) + LIFT;
+                gizmos.circle(Isometry3d::new(at, flat), 0.5, color);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn obstacles_block_the_line_of_sight() {
+        let mut fog = FogOfWar::new(100.0);
+        let wall = (Vec3::new(10.0, 0.0, 0.0), Vec3::ONE);
+        let eye = (Faction(0), Vec3::new(2.0, 0.0, 0.5), VIEW_RANGE);
+        fog.update(2, [wall], [eye]);
+
+        let blue = Faction(0);
+        assert!(fog.sees(blue, Vec3::new(10.0, 0.0, 0.5)), "the wall itself");
+        assert!(
+            !fog.sees(blue, Vec3::new(20.0, 0.0, 0.5)),
+            "behind the wall"
+        );
+        assert!(fog.sees(blue, Vec3::new(20.0, 0.0, 20.0)), "past it");
+        assert!(!fog.sees(blue, Vec3::new(60.0, 0.0, 0.5)), "out of range");
+
+        let red = Faction(1);
+        assert!(!fog.sees(red, Vec3::new(2.0, 0.0, 0.5)));
+        assert!(fog.reveals(red, red, Vec3::new(2.0, 0.0, 0.5)));
+        fog.enabled = false;
+        assert!(fog.sees(red, Vec3::new(20.0, 0.0, 0.5)));
+    }
+
+    #[test]
+    fn an_obstacle_reaching_off_the_grid_still_blocks_sight() {
+        let mut fog = FogOfWar::new(100.0);
+        // A wall along X = 20 running off the southern edge.
+        let wall = (Vec3::new(20.0, 0.0, -100.0), Vec3::new(1.0, 1.0, 10.0));
+        let eye = (Faction(0), Vec3::new(2.0, 0.0, -95.0), VIEW_RANGE);
+        fog.update(1, [wall], [eye]);
+
+        let blue = Faction(0);
+        assert!(
+            fog.sees(blue, Vec3::new(20.0, 0.0, -95.0)),
+            "the wall itself"
+        );
+        assert!(
+            !fog.sees(blue, Vec3::new(30.0, 0.0, -95.0)),
+            "behind the wall"
+        );
+    }
+
+    /// Headless app refreshing the fog of a 100 half-size grid; the local
+    /// faction is `Faction(0)`.
+    fn vision_app() -> App {
+        let mut app = App::new();
+        app.init_resource::<Time>()
+            .init_resource::<Factions>()
+            .init_resource::<Ghosts>()
+            .insert_resource(FogOfWar::new(100.0))
+            .add_systems(Update, vision_system);
+        app
+    }
+
+    fn tick(app: &mut App, dt: f32) {
+        app.world_mut()
+            .resource_mut::<Time>()
+            .advance_by(Duration::from_secs_f32(dt));
+        app.update();
+    }
+
+    fn spawn_boid(app: &mut App, pos: Vec3, faction: Faction) -> Entity {
+        app.world_mut()
+            .spawn((
+                Boid::default(),
+                Transform::from_translation(pos),
+                TrackedByTree,
+                Health::default(),
+                Visibility::default(),
+                faction,
+            ))
+            .id()
+    }
+
+    fn move_to(app: &mut App, entity: Entity, pos: Vec3) {
+        app.world_mut()
+            .get_mut::<Transform>(entity)
+            .unwrap()
+            .translation = pos;
+    }
+
+    #[test]
+    fn an_unloaded_formation_looks_out_from_its_origin() {
+        let mut app = vision_app();
+        let formation = app
+            .world_mut()
+            .spawn((
+                Formation::default(),
+                Transform::default(),
+                Faction(0),
+                LodUnloaded,
+            ))
+            .id();
+        // Left far behind, out of the index: it sees nothing from there.
+        app.world_mut().spawn((
+            Boid::default(),
+            Transform::from_xyz(-90.0, 0.0, 0.0),
+            Health::default(),
+            UnitStats {
+                view_range: 60.0,
+                ..default()
+            },
+            Faction(0),
+            MemberOf(formation),
+        ));
+        tick(&mut app, REFRESH_INTERVAL);
+
+        let fog = app.world().resource::<FogOfWar>();
+        assert!(
+            fog.sees(Faction(0), Vec3::new(55.0, 0.0, 0.0)),
+            "as far as its scout"
+        );
+        assert!(!fog.sees(Faction(0), Vec3::new(-90.0, 0.0, 0.0)));
+    }
+
+    #[test]
+    fn enemies_are_shown_only_in_sight() {
+        let mut app = vision_app();
+        app.add_systems(Update, fog_visibility_system.after(vision_system));
+        let eye = spawn_boid(&mut app, Vec3::ZERO, Faction(0));
+        let near = spawn_boid(&mut app, Vec3::new(10.0, 0.0, 0.0), Faction(1));
+        let far = spawn_boid(&mut app, Vec3::new(80.0, 0.0, 0.0), Faction(1));
+        let visibility = |app: &App, boid: Entity| *app.world().get::<Visibility>(boid).unwrap();
+        tick(&mut app, REFRESH_INTERVAL);
+        assert_eq!(visibility(&app, near), Visibility::Inherited);
+        assert_eq!(visibility(&app, far), Visibility::Hidden);
+
+        // Our own are never hidden, though no one of ours sees them.
+        move_to(&mut app, eye, Vec3::new(-80.0, 0.0, 0.0));
+        tick(&mut app, REFRESH_INTERVAL);
+        assert_eq!(visibility(&app, near), Visibility::Hidden);
+        assert_eq!(visibility(&app, eye), Visibility::Inherited);
+
+        move_to(&mut app, eye, Vec3::new(70.0, 0.0, 0.0));
+        tick(&mut app, REFRESH_INTERVAL);
+        assert_eq!(visibility(&app, far), Visibility::Inherited);
+    }
+
+    #[test]
+    fn ghosts_stay_where_the_enemy_was_seen_until_looked_for() {
+        let mut app = vision_app();
+        app.add_systems(Update, ghost_system.after(vision_system));
+        let eye = spawn_boid(&mut app, Vec3::ZERO, Faction(0));
+        let at = Vec3::new(10.0, 0.0, 0.0);
+        let formation = app
+            .world_mut()
+            .spawn((
+                Formation::default(),
+                Transform::from_translation(at),
+                Faction(1),
+            ))
+            .id();
+        let member = spawn_boid(&mut app, at, Faction(1));
+        app.world_mut()
+            .entity_mut(member)
+            .insert(MemberOf(formation));
+        let lone = spawn_boid(&mut app, Vec3::new(0.0, 0.0, 10.0), Faction(1));
+        let ghost =
+            |app: &App, unit: Entity| app.world().resource::<Ghosts>().0.get(&unit).copied();
+
+        // Recorded while in sight: a formation by its origin and footprint,
+        // a lone boid by itself.
+        tick(&mut app, REFRESH_INTERVAL);
+        let seen = ghost(&app, formation).expect("formation recorded");
+        assert!(seen.in_sight && seen.footprint.is_some());
+        assert_eq!(seen.pos, at);
+        assert!(ghost(&app, lone).is_some_and(|g| g.in_sight && g.footprint.is_none()));
+        assert!(ghost(&app, member).is_none(), "a member is its formation");
+        assert!(ghost(&app, eye).is_none(), "not our own");
+
+        // Out of sight: both stay where they were last seen.
+        move_to(&mut app, eye, Vec3::new(-80.0, 0.0, 0.0));
+        move_to(&mut app, formation, Vec3::new(80.0, 0.0, 0.0));
+        move_to(&mut app, member, Vec3::new(80.0, 0.0, 0.0));
+        tick(&mut app, REFRESH_INTERVAL);
+        let lost = ghost(&app, formation).expect("kept out of sight");
+        assert!(!lost.in_sight);
+        assert_eq!(lost.pos, at);
+        assert!(ghost(&app, lone).is_some_and(|g| !g.in_sight));
+
+        // Looked for again: the formation is gone from there, the lone boid
+        // still stands.
+        move_to(&mut app, eye, Vec3::ZERO);
+        tick(&mut app, REFRESH_INTERVAL);
+        assert!(ghost(&app, formation).is_none(), "cleared");
+        assert!(ghost(&app, lone).is_some_and(|g| g.in_sight));
+    }
+
+    #[test]
+    fn the_fog_covers_the_whole_terrain() {
+        let mut app = App::new();
+        let mut fog = FogOfWar::default();
+        fog.enabled = false;
+        app.insert_resource(fog)
+            .add_systems(Update, fit_fog_to_terrain);
+        app.world_mut().spawn((
+            Terrain {
+                half_size: Vec2::new(2500.0, 1000.0),
+            },
+            Transform::from_xyz(0.0, 0.0, 100.0),
+        ));
+        app.update();
+
+        let mut fog = app.world_mut().resource_mut::<FogOfWar>();
+        assert_eq!(fog.half_size, 2500.0);
+        assert!(!fog.enabled, "still off");
+        fog.enabled = true;
+        let eye = (Faction(0), Vec3::new(2400.0, 0.0, 1000.0), VIEW_RANGE);
+        fog.update(1, [], [eye]);
+        assert!(
+            fog.sees(Faction(0), Vec3::new(2410.0, 0.0, 1010.0)),
+            "out by the edge"
+        );
+    }
+}